@@ -0,0 +1,12 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+
+/// Creates a pool that never connects, acquiring a connection fails after 200ms. Used to test
+/// how a DB that cannot be reached is handled.
+pub fn unreachable_pool() -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(200))
+        .connect_lazy("postgres://localhost:1/unreachable")
+        .expect("parse the URL")
+}
@@ -0,0 +1,236 @@
+//! Defines the macro bundling the descriptor, pool and config of an application into one
+//! environment type.
+//!
+//! # Notes
+//! Without an environment every handler takes a generic parameter per dependency, such as
+//! `handler::<SqlxPostGresDescriptor, LivePostGresPool>`. An environment groups them so handlers
+//! take a single `E: AppEnv` and the router is registered once per environment:
+//!
+//! ```ignore
+//! define_app_env!(
+//!     pub trait AppEnv {
+//!         type Dal: IncreaseCount + GetCount;
+//!     }
+//!
+//!     LiveEnv {
+//!         type Dal = SqlxPostGresDescriptor;
+//!         type Pool = LivePostGresPool;
+//!         type Config = EnvConfig;
+//!     }
+//!
+//!     #[cfg(test)]
+//!     TestEnv {
+//!         type Dal = MockCountDal;
+//!         type Pool = UnusedPool;
+//!         type Config = TestConfig;
+//!     }
+//! );
+//!
+//! async fn increase_and_get_count<E: AppEnv>() -> Result<impl IntoResponse, CruxmontError> {
+//!     let pool = E::Pool::yield_pool();
+//!     E::Dal::increase_count(1, pool).await?;
+//!     Ok(Json(E::Dal::get_count(1, pool).await?))
+//! }
+//!
+//! fn counter_factory<E: AppEnv>(app: Router) -> Router {
+//!     app.route("/increment", get(increase_and_get_count::<E>))
+//! }
+//!
+//! let app = counter_factory::<LiveEnv>(Router::new());
+//! ```
+//!
+//! An environment of a trait defined elsewhere, such as one using the pool of an
+//! `#[embedded_pg_test]`, is defined with `define_app_env!(impl AppEnv for EmbeddedEnv { ... })`.
+//! Its struct is private to the module or function defining it, while the structs of the
+//! environments defined with the trait have the visibility of the trait.
+
+/// Defines an environment trait with the `Dal`, `Pool` and `Config` types of an application and
+/// the unit structs implementing it. The traits the `Dal` type must implement are written as
+/// bounds of `type Dal` and must be in scope. For example:
+///
+/// ```
+/// use cruxmont::config::EnvConfig;
+/// use cruxmont::dal::connections::sqlx_postgres::{LivePostGresPool, SqlxPostGresDescriptor};
+/// use cruxmont::define_app_env;
+///
+/// pub trait Describe {
+///     fn describe() -> &'static str;
+/// }
+///
+/// impl Describe for SqlxPostGresDescriptor {
+///     fn describe() -> &'static str {
+///         "postgres"
+///     }
+/// }
+///
+/// define_app_env!(
+///     pub trait AppEnv {
+///         type Dal: Describe;
+///     }
+///
+///     LiveEnv {
+///         type Dal = SqlxPostGresDescriptor;
+///         type Pool = LivePostGresPool;
+///         type Config = EnvConfig;
+///     }
+/// );
+///
+/// fn describe<E: AppEnv>() -> &'static str {
+///     E::Dal::describe()
+/// }
+///
+/// assert_eq!(describe::<LiveEnv>(), "postgres");
+/// ```
+#[macro_export]
+macro_rules! define_app_env {
+    (
+        @env [$($vis:tt)*] $(#[$meta:meta])*
+        impl $env_trait:ident for $env:ident {
+            type Dal = $dal:ty;
+            type Pool = $pool:ty;
+            type Config = $config:ty;
+        }
+    ) => {
+        $(#[$meta])*
+        $($vis)* struct $env;
+
+        $(#[$meta])*
+        impl $env_trait for $env {
+            type Dal = $dal;
+            type Pool = $pool;
+            type Config = $config;
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        impl $env_trait:ident for $env:ident {
+            type Dal = $dal:ty;
+            type Pool = $pool:ty;
+            type Config = $config:ty;
+        }
+    ) => {
+        // the types of the environment can be private to the module or test defining it
+        $crate::define_app_env!(
+            @env [] $(#[$meta])*
+            impl $env_trait for $env {
+                type Dal = $dal;
+                type Pool = $pool;
+                type Config = $config;
+            }
+        );
+    };
+    (
+        $(#[$trait_meta:meta])*
+        $vis:vis trait $env_trait:ident {
+            type Dal: $bound:ident $(+ $more_bound:ident)*;
+        }
+        $(
+            $(#[$meta:meta])*
+            $env:ident {
+                type Dal = $dal:ty;
+                type Pool = $pool:ty;
+                type Config = $config:ty;
+            }
+        )*
+    ) => {
+        $(#[$trait_meta])*
+        $vis trait $env_trait: Send + Sync + 'static {
+            /// The descriptor implementing the transactions of the application.
+            type Dal: 'static + $bound $(+ $more_bound)*;
+            /// The pool the transactions run on.
+            type Pool: $crate::dal::connections::sqlx_postgres::YieldPostGresPool + 'static;
+            /// The provider of the config variables.
+            type Config: $crate::config::GetConfigVariable + 'static;
+        }
+
+        $(
+            $crate::define_app_env!(
+                @env [$vis] $(#[$meta])*
+                impl $env_trait for $env {
+                    type Dal = $dal;
+                    type Pool = $pool;
+                    type Config = $config;
+                }
+            );
+        )*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::GetConfigVariable;
+    use crate::dal::connections::sqlx_postgres::YieldPostGresPool;
+    use crate::define_static_config;
+    use cruxmont_db_tx::{db_transaction, define_dal_transactions};
+    use cruxmont_test_utils::pools::unreachable_pool;
+    use sqlx::{PgPool, Pool, Postgres};
+    use std::sync::LazyLock;
+
+    define_dal_transactions!(
+        mock MockGreetingDal;
+        Greet => greet(name: String) -> String,
+    );
+
+    struct GreetingDescriptor;
+
+    #[db_transaction(GreetingDescriptor, Greet)]
+    async fn greet(name: String) -> String {
+        Ok(format!("hello {}", name))
+    }
+
+    struct UnusedPool;
+
+    impl YieldPostGresPool for UnusedPool {
+        fn yield_pool() -> &'static Pool<Postgres> {
+            static POOL: LazyLock<PgPool> = LazyLock::new(unreachable_pool);
+            &POOL
+        }
+    }
+
+    define_static_config!(GreetingConfig, "NAME" => "ann");
+
+    define_app_env!(
+        trait GreetingEnv {
+            type Dal: Greet;
+        }
+
+        LiveGreetingEnv {
+            type Dal = GreetingDescriptor;
+            type Pool = UnusedPool;
+            type Config = GreetingConfig;
+        }
+    );
+
+    define_app_env!(
+        impl GreetingEnv for MockGreetingEnv {
+            type Dal = MockGreetingDal;
+            type Pool = UnusedPool;
+            type Config = GreetingConfig;
+        }
+    );
+
+    async fn greet_configured_name<E: GreetingEnv>() -> sqlx::Result<String> {
+        let name = E::Config::get_config_variable("NAME".to_string()).unwrap();
+        E::Dal::greet(name).await
+    }
+
+    #[tokio::test]
+    async fn test_handlers_take_one_environment() {
+        assert_eq!(
+            greet_configured_name::<LiveGreetingEnv>().await.unwrap(),
+            "hello ann"
+        );
+
+        let mock = MockGreetingDal::new();
+        mock.greet.returns("hi".to_string());
+        let greeting = mock
+            .run(greet_configured_name::<MockGreetingEnv>())
+            .await
+            .unwrap();
+        assert_eq!(greeting, "hi");
+        assert_eq!(
+            mock.greet.calls()[0].arg::<String>(0),
+            Some(&"ann".to_string())
+        );
+    }
+}
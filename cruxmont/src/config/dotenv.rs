@@ -0,0 +1,179 @@
+//! Defines reading config variables from `.env` files.
+//!
+//! # Notes
+//! The file is read the first time a variable is requested and again whenever it is modified.
+//! It is never written to the process environment so it can be layered under `EnvConfig` with
+//! `LayeredConfig`.
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Parses the contents of a `.env` file.
+///
+/// # Notes
+/// Supports `KEY=value` lines with an optional `export ` prefix, `#` comments, single quoted
+/// literal values and double quoted values with `\n`, `\t`, `\"` and `\\` escapes that can span
+/// multiple lines.
+///
+/// # Arguments
+/// * `contents` - The contents of the file.
+///
+/// # Returns
+/// * `HashMap<String, String>` - The variables defined in the file.
+pub fn parse_dotenv(contents: &str) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            continue;
+        }
+        let value = value.trim_start();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let mut raw = quoted.to_string();
+            while !has_closing_quote(&raw) {
+                match lines.next() {
+                    Some(next) => {
+                        raw.push('\n');
+                        raw.push_str(next);
+                    }
+                    None => break,
+                }
+            }
+            unescape_double_quoted(&raw)
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            quoted.split('\'').next().unwrap_or_default().to_string()
+        } else {
+            let value = match value.find(" #") {
+                Some(index) => &value[..index],
+                None => value,
+            };
+            value.trim().to_string()
+        };
+        variables.insert(key.to_string(), value);
+    }
+    variables
+}
+
+/// Checks if a double quoted value contains its unescaped closing quote.
+fn has_closing_quote(raw: &str) -> bool {
+    let mut escaped = false;
+    for character in raw.chars() {
+        match character {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return true,
+            _ => escaped = false,
+        }
+    }
+    false
+}
+
+/// Reads a double quoted value up to its closing quote, resolving escapes.
+fn unescape_double_quoted(raw: &str) -> String {
+    let mut value = String::new();
+    let mut characters = raw.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => break,
+            '\\' => match characters.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some(other) => value.push(other),
+                None => break,
+            },
+            other => value.push(other),
+        }
+    }
+    value
+}
+
+/// Reads a `.env` file, a missing file is treated as empty.
+///
+/// # Arguments
+/// * `path` - The path of the file.
+///
+/// # Returns
+/// * `Result<HashMap<String, String>, String>` - The variables or the error reading the file.
+pub fn load_dotenv_file(path: impl AsRef<Path>) -> Result<HashMap<String, String>, String> {
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(parse_dotenv(&contents)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(error) => Err(format!("failed to read {}: {}", path.display(), error)),
+    }
+}
+
+/// Defines a config provider reading a `.env` file, for example:
+///
+/// ```
+/// use cruxmont::define_dotenv_config;
+///
+/// define_dotenv_config!(LocalDotEnv, ".env.local");
+/// ```
+#[macro_export]
+macro_rules! define_dotenv_config {
+    ($handle:ident, $path:expr) => {
+        #[derive(Clone)]
+        pub struct $handle;
+
+        impl $handle {
+            fn variables() -> $crate::config::LayerVariables {
+                static VARIABLES: $crate::config::FileLayer = $crate::config::FileLayer::new();
+                VARIABLES.variables($path, || $crate::config::dotenv::load_dotenv_file($path))
+            }
+        }
+
+        impl $crate::config::GetConfigVariable for $handle {
+            fn get_config_variable(
+                variable: String,
+            ) -> Result<String, $crate::errors::CruxmontError> {
+                $crate::config::lookup_layer($path, &Self::variables(), variable)
+            }
+
+            fn layer_name() -> &'static str {
+                $path
+            }
+        }
+    };
+}
+
+define_dotenv_config!(DotEnvConfig, ".env");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GetConfigVariable;
+
+    #[test]
+    fn test_parse_dotenv() {
+        let variables = parse_dotenv(
+            r#"
+# database
+export DATABASE_URL=postgres://localhost/app # the local database
+DB_MAX_CONNECTIONS = 5
+GREETING="hello\n\"world\""
+RAW='no $expansion # here'
+MULTILINE="first
+second"
+EMPTY=
+"#,
+        );
+        assert_eq!(variables["DATABASE_URL"], "postgres://localhost/app");
+        assert_eq!(variables["DB_MAX_CONNECTIONS"], "5");
+        assert_eq!(variables["GREETING"], "hello\n\"world\"");
+        assert_eq!(variables["RAW"], "no $expansion # here");
+        assert_eq!(variables["MULTILINE"], "first\nsecond");
+        assert_eq!(variables["EMPTY"], "");
+        assert_eq!(DotEnvConfig::layer_name(), ".env");
+    }
+}
@@ -0,0 +1,185 @@
+//! Defines reading config variables from TOML, YAML and JSON files.
+//!
+//! # Notes
+//! The format is picked from the extension of the file. Nested tables are flattened into
+//! upper case variable names joined with `_` so the same names work in every layer:
+//!
+//! ```toml
+//! port = 8080
+//!
+//! [database]
+//! url = "postgres://localhost/app"
+//! ```
+//!
+//! defines `PORT` and `DATABASE_URL`. Arrays of values are joined with `,`. TOML and YAML files
+//! are read with the `toml` and `yaml` features, both enabled by default.
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Flattens a parsed config file into variables.
+///
+/// # Arguments
+/// * `value` - The parsed file.
+///
+/// # Returns
+/// * `HashMap<String, String>` - The flattened variables.
+pub fn flatten_config(value: &Value) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    flatten_into("", value, &mut variables);
+    variables
+}
+
+fn flatten_into(prefix: &str, value: &Value, variables: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(table) => {
+            for (key, value) in table {
+                let key = key.to_uppercase().replace(['-', '.'], "_");
+                let name = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}_{}", prefix, key)
+                };
+                flatten_into(&name, value, variables);
+            }
+        }
+        Value::Null => {}
+        value if prefix.is_empty() => {
+            tracing::warn!(%value, "ignoring config value without a key");
+        }
+        Value::Array(values) => {
+            let joined = values
+                .iter()
+                .map(scalar_to_string)
+                .collect::<Vec<String>>()
+                .join(",");
+            variables.insert(prefix.to_string(), joined);
+        }
+        value => {
+            variables.insert(prefix.to_string(), scalar_to_string(value));
+        }
+    }
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Parses the contents of a config file.
+///
+/// # Arguments
+/// * `contents` - The contents of the file.
+/// * `extension` - The extension of the file: `toml`, `yaml`, `yml` or `json`, TOML and YAML
+///   need their features.
+///
+/// # Returns
+/// * `Result<HashMap<String, String>, String>` - The flattened variables or the parse error.
+pub fn parse_config(contents: &str, extension: &str) -> Result<HashMap<String, String>, String> {
+    let value = match extension.to_lowercase().as_str() {
+        #[cfg(feature = "toml")]
+        "toml" => toml::from_str::<Value>(contents).map_err(|error| error.to_string())?,
+        #[cfg(not(feature = "toml"))]
+        "toml" => return Err("TOML files need the toml feature of cruxmont".to_string()),
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => {
+            serde_yaml::from_str::<Value>(contents).map_err(|error| error.to_string())?
+        }
+        #[cfg(not(feature = "yaml"))]
+        "yaml" | "yml" => return Err("YAML files need the yaml feature of cruxmont".to_string()),
+        "json" => serde_json::from_str::<Value>(contents).map_err(|error| error.to_string())?,
+        other => return Err(format!("unsupported config file format: {}", other)),
+    };
+    Ok(flatten_config(&value))
+}
+
+/// Reads a config file, a missing file is treated as empty.
+///
+/// # Arguments
+/// * `path` - The path of the file.
+///
+/// # Returns
+/// * `Result<HashMap<String, String>, String>` - The variables or the error reading the file.
+pub fn load_config_file(path: impl AsRef<Path>) -> Result<HashMap<String, String>, String> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_config(&contents, extension)
+            .map_err(|error| format!("failed to parse {}: {}", path.display(), error)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(error) => Err(format!("failed to read {}: {}", path.display(), error)),
+    }
+}
+
+/// Defines a config provider reading a TOML, YAML or JSON file, for example:
+///
+/// ```
+/// use cruxmont::define_file_config;
+///
+/// define_file_config!(AppFileConfig, "config/app.toml");
+/// ```
+#[macro_export]
+macro_rules! define_file_config {
+    ($handle:ident, $path:expr) => {
+        #[derive(Clone)]
+        pub struct $handle;
+
+        impl $handle {
+            fn variables() -> $crate::config::LayerVariables {
+                static VARIABLES: $crate::config::FileLayer = $crate::config::FileLayer::new();
+                VARIABLES.variables($path, || $crate::config::file::load_config_file($path))
+            }
+        }
+
+        impl $crate::config::GetConfigVariable for $handle {
+            fn get_config_variable(
+                variable: String,
+            ) -> Result<String, $crate::errors::CruxmontError> {
+                $crate::config::lookup_layer($path, &Self::variables(), variable)
+            }
+
+            fn layer_name() -> &'static str {
+                $path
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_flatten_to_the_same_variables() {
+        let toml = "port = 8080\nhosts = [\"a\", \"b\"]\n\n[database]\nurl = \"postgres://localhost/app\"\nmax-connections = 5\n";
+        let yaml = "port: 8080\nhosts: [a, b]\ndatabase:\n  url: postgres://localhost/app\n  max-connections: 5\n";
+        let json = r#"{"port": 8080, "hosts": ["a", "b"], "database": {"url": "postgres://localhost/app", "max-connections": 5}}"#;
+        let expected = HashMap::from([
+            ("PORT".to_string(), "8080".to_string()),
+            ("HOSTS".to_string(), "a,b".to_string()),
+            (
+                "DATABASE_URL".to_string(),
+                "postgres://localhost/app".to_string(),
+            ),
+            ("DATABASE_MAX_CONNECTIONS".to_string(), "5".to_string()),
+        ]);
+        assert_eq!(parse_config(json, "json").unwrap(), expected);
+        // a disabled format is reported rather than read as empty
+        assert_eq!(
+            parse_config(toml, "toml").ok(),
+            cfg!(feature = "toml").then(|| expected.clone())
+        );
+        assert_eq!(
+            parse_config(yaml, "yml").ok(),
+            cfg!(feature = "yaml").then_some(expected)
+        );
+        assert!(parse_config("port = ", "toml").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Defines combining config providers into layers with a defined precedence.
+//!
+//! # Notes
+//! `LayeredConfig` takes a tuple of providers, the first provider that has a variable wins. A
+//! provider that fails to read a variable, rather than not having it, stops the lookup with its
+//! error so a broken secret or config file never falls through to the values of a lower layer.
+//! It implements `GetConfigVariable` itself so it can be passed anywhere a single provider is
+//! used:
+//!
+//! ```
+//! use cruxmont::config::dotenv::DotEnvConfig;
+//! use cruxmont::config::layered::{LayeredConfig, OverrideConfig};
+//! use cruxmont::config::{EnvConfig, GetConfigVariable};
+//! use cruxmont::define_file_config;
+//!
+//! define_file_config!(AppFileConfig, "config/app.toml");
+//!
+//! // overrides > process environment > .env > config/app.toml
+//! type AppConfig = LayeredConfig<(OverrideConfig, EnvConfig, DotEnvConfig, AppFileConfig)>;
+//!
+//! OverrideConfig::set("FEATURE_SIGNUP", "false");
+//! assert_eq!(AppConfig::get_config_variable("FEATURE_SIGNUP".to_string()).unwrap(), "false");
+//! assert_eq!(AppConfig::source_of("FEATURE_SIGNUP"), Some("overrides"));
+//! ```
+use super::typed::CruxmontConfig;
+use super::{GetConfigVariable, is_not_found, not_found};
+use crate::errors::CruxmontError;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::RwLock;
+
+static OVERRIDES: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Config variables set explicitly by the program, usually the top layer.
+#[derive(Clone)]
+pub struct OverrideConfig;
+
+impl OverrideConfig {
+    /// Sets a variable.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    /// * `value` - The value of the variable.
+    pub fn set(variable: impl Into<String>, value: impl Into<String>) {
+        OVERRIDES
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get_or_insert_with(HashMap::new)
+            .insert(variable.into(), value.into());
+    }
+
+    /// Removes a variable.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    pub fn remove(variable: &str) {
+        if let Some(overrides) = OVERRIDES
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_mut()
+        {
+            overrides.remove(variable);
+        }
+    }
+
+    /// Removes all variables.
+    pub fn clear() {
+        *OVERRIDES
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+impl GetConfigVariable for OverrideConfig {
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+        OVERRIDES
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            .and_then(|overrides| overrides.get(&variable).cloned())
+            .ok_or_else(|| not_found(&variable, "overrides"))
+    }
+
+    fn layer_name() -> &'static str {
+        "overrides"
+    }
+}
+
+/// Defines the trait for the tuples of providers used by `LayeredConfig`.
+pub trait ConfigLayers {
+    /// Gets a variable from the first layer that has it or fails to read it.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    ///
+    /// # Returns
+    /// * `Option<(Result<String, CruxmontError>, &'static str)>` - The value or the error of the
+    ///   layer and the name of the layer, None if no layer has the variable.
+    fn resolve(variable: &str) -> Option<(Result<String, CruxmontError>, &'static str)>;
+
+    /// Gets the names of the layers from highest to lowest precedence.
+    ///
+    /// # Returns
+    /// * `Vec<&'static str>` - The names of the layers.
+    fn layer_names() -> Vec<&'static str>;
+}
+
+macro_rules! impl_config_layers {
+    ($($layer:ident),+) => {
+        impl<$($layer: GetConfigVariable),+> ConfigLayers for ($($layer,)+) {
+            fn resolve(variable: &str) -> Option<(Result<String, CruxmontError>, &'static str)> {
+                $(
+                    match $layer::get_config_variable(variable.to_string()) {
+                        Err(error) if is_not_found(&error) => {}
+                        result => return Some((result, $layer::layer_name())),
+                    }
+                )+
+                None
+            }
+
+            fn layer_names() -> Vec<&'static str> {
+                vec![$($layer::layer_name()),+]
+            }
+        }
+    };
+}
+
+impl_config_layers!(A);
+impl_config_layers!(A, B);
+impl_config_layers!(A, B, C);
+impl_config_layers!(A, B, C, D);
+impl_config_layers!(A, B, C, D, E);
+impl_config_layers!(A, B, C, D, E, F);
+impl_config_layers!(A, B, C, D, E, F, G);
+impl_config_layers!(A, B, C, D, E, F, G, H);
+
+/// Config provider combining a tuple of providers, earlier providers take precedence.
+pub struct LayeredConfig<L>(PhantomData<L>);
+
+impl<L: ConfigLayers> GetConfigVariable for LayeredConfig<L> {
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+        match L::resolve(&variable) {
+            Some((result, _)) => result,
+            None => Err(not_found(
+                &variable,
+                &format!("any config layer ({})", L::layer_names().join(", ")),
+            )),
+        }
+    }
+
+    fn layer_name() -> &'static str {
+        "layered"
+    }
+}
+
+impl<L: ConfigLayers> LayeredConfig<L> {
+    /// Gets the layer a variable is read from.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    ///
+    /// # Returns
+    /// * `Option<&'static str>` - The name of the layer, None if no layer has the variable. A
+    ///   layer that fails to read the variable is reported as its source.
+    pub fn source_of(variable: &str) -> Option<&'static str> {
+        L::resolve(variable).map(|(_, layer)| layer)
+    }
+
+    /// Reports the layer each variable is read from. Values are left out of the report so it
+    /// can be logged safely.
+    ///
+    /// # Arguments
+    /// * `variables` - The names of the variables.
+    ///
+    /// # Returns
+    /// * `ConfigReport` - The layer of each variable.
+    pub fn report(variables: &[&str]) -> ConfigReport {
+        ConfigReport {
+            origins: variables
+                .iter()
+                .map(|variable| ConfigOrigin {
+                    variable: variable.to_string(),
+                    layer: Self::source_of(variable),
+                })
+                .collect(),
+        }
+    }
+
+    /// Reports the layer each variable of a config struct is read from.
+    ///
+    /// # Returns
+    /// * `ConfigReport` - The layer of each variable of the struct.
+    pub fn report_for<C: CruxmontConfig>() -> ConfigReport {
+        let variables = C::variables("");
+        let names = variables
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect::<Vec<&str>>();
+        Self::report(&names)
+    }
+}
+
+/// The layer a variable is read from.
+///
+/// # Fields
+/// * `variable` - The name of the variable.
+/// * `layer` - The name of the layer, None if no layer has the variable.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConfigOrigin {
+    pub variable: String,
+    pub layer: Option<&'static str>,
+}
+
+/// The layers a set of variables are read from.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConfigReport {
+    pub origins: Vec<ConfigOrigin>,
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, origin) in self.origins.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{} <- {}",
+                origin.variable,
+                origin.layer.unwrap_or("(not set)")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_static_config;
+
+    define_static_config!(
+        FileLayer,
+        "PORT" => "8080",
+        "LOG_LEVEL" => "info"
+    );
+    define_static_config!(
+        EnvLayer,
+        "LOG_LEVEL" => "debug"
+    );
+
+    type TestConfig = LayeredConfig<(EnvLayer, FileLayer)>;
+
+    /// Fails to read the log level, as an unreadable `_FILE` secret does.
+    struct BrokenLayer;
+
+    impl GetConfigVariable for BrokenLayer {
+        fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+            match variable.as_str() {
+                "LOG_LEVEL" => Err(CruxmontError::unknown("permission denied")),
+                _ => Err(not_found(&variable, "BrokenLayer")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_precedence_and_report() {
+        assert_eq!(
+            TestConfig::get_config_variable("LOG_LEVEL".to_string()).unwrap(),
+            "debug"
+        );
+        assert_eq!(
+            TestConfig::get_config_variable("PORT".to_string()).unwrap(),
+            "8080"
+        );
+        assert!(TestConfig::get_config_variable("MISSING".to_string()).is_err());
+
+        let report = TestConfig::report(&["LOG_LEVEL", "PORT", "MISSING"]);
+        assert_eq!(report.origins[0].layer, Some(EnvLayer::layer_name()));
+        assert_eq!(report.origins[1].layer, Some(FileLayer::layer_name()));
+        assert_eq!(report.origins[2].layer, None);
+    }
+
+    #[test]
+    fn test_unreadable_layer_does_not_fall_through() {
+        type Config = LayeredConfig<(BrokenLayer, FileLayer)>;
+        let error = Config::get_config_variable("LOG_LEVEL".to_string()).unwrap_err();
+        assert_eq!(error.message, "permission denied");
+        assert!(!is_not_found(&error));
+        assert_eq!(
+            Config::get_config_variable("PORT".to_string()).unwrap(),
+            "8080"
+        );
+    }
+}
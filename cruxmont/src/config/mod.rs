@@ -0,0 +1,250 @@
+//! Defines extracting config variables.
+pub mod dotenv;
+pub mod file;
+pub mod layered;
+pub mod profile;
+pub mod reload;
+pub mod secrets;
+pub mod source;
+pub mod typed;
+
+use crate::errors::i18n::message_keys;
+use crate::errors::{CruxmontError, CurxmontErrorStatus};
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+pub use cruxmont_config_derive::CruxmontConfig;
+pub use typed::CruxmontConfig;
+
+/// Defines the trait for getting config variables
+pub trait GetConfigVariable {
+    /// Gets the config variable
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the config variable to get
+    ///
+    /// # Returns
+    /// * `Result<String, String>` - The result of getting the config variable
+    ///
+    /// # Notes
+    /// A variable the provider does not have is reported with `not_found`, typed configs only
+    /// fall back to their defaults for that error.
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError>;
+
+    /// Gets the name of the provider used when reporting which layer a value came from
+    ///
+    /// # Returns
+    /// * `&'static str` - The name of the provider
+    fn layer_name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Defines the struct for getting config variables from the environment
+///
+/// # Notes
+/// When `VAR_FILE` is set, the value is read from the file it points to instead of `VAR` as done
+/// for Docker and Kubernetes secrets.
+pub struct EnvConfig;
+
+impl GetConfigVariable for EnvConfig {
+    /// Gets the config variable from the environment
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the config variable to get
+    ///
+    /// # Returns
+    /// * `Result<String, NanoServiceError>` - The result of getting the config variable
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+        match env::var(format!("{}_FILE", variable)) {
+            Ok(path) => secrets::read_secret_file(&path).map_err(|error| {
+                CruxmontError::new(
+                    format!(
+                        "{}_FILE could not be read from {}: {}",
+                        variable, path, error
+                    ),
+                    CurxmontErrorStatus::Unknown,
+                )
+            }),
+            Err(_) => env::var(&variable).map_err(|_| not_found(&variable, "environment")),
+        }
+    }
+
+    fn layer_name() -> &'static str {
+        "env"
+    }
+}
+
+/// Constructs the error of a variable a provider does not have.
+///
+/// # Arguments
+/// * `variable` - The name of the variable.
+/// * `location` - Where the variable was looked up, such as `environment`.
+///
+/// # Returns
+/// * `CruxmontError` - The error.
+pub fn not_found(variable: &str, location: &str) -> CruxmontError {
+    CruxmontError::from_key(message_keys::CONFIG_NOT_FOUND, CurxmontErrorStatus::Unknown)
+        .with_message_arg("variable", variable)
+        .with_message_arg("location", location)
+}
+
+/// Checks if an error of a provider means the variable is not set, rather than that it could
+/// not be read.
+///
+/// # Arguments
+/// * `error` - The error of the provider.
+///
+/// # Returns
+/// * `bool` - True if the error was constructed with `not_found`.
+pub fn is_not_found(error: &CruxmontError) -> bool {
+    error
+        .message_key
+        .as_ref()
+        .is_some_and(|message_key| message_key.key == message_keys::CONFIG_NOT_FOUND)
+}
+
+/// Logs a warning for a config layer that failed to load, used by the providers that read files.
+///
+/// # Arguments
+/// * `layer` - The name of the layer.
+/// * `loaded` - The variables of the layer or the error loading it.
+///
+/// # Returns
+/// * `Result<HashMap<String, String>, String>` - The loaded layer, unchanged.
+pub fn loaded_layer(
+    layer: &str,
+    loaded: Result<HashMap<String, String>, String>,
+) -> Result<HashMap<String, String>, String> {
+    if let Err(error) = &loaded {
+        tracing::warn!(layer, %error, "config layer failed to load");
+    }
+    loaded
+}
+
+/// The variables of a config layer read from a file, as loaded by `FileLayer::variables`.
+pub type LayerVariables = Arc<Result<HashMap<String, String>, String>>;
+
+/// Caches the variables of a config layer read from a file, used by the providers that read
+/// files.
+///
+/// # Notes
+/// The file is read again when its path or modification time changes, so a `ConfigReloader`
+/// sees the new values of a file it watches.
+pub struct FileLayer {
+    loaded: RwLock<Option<(String, Option<SystemTime>, LayerVariables)>>,
+}
+
+impl FileLayer {
+    /// Constructs an empty cache, the file is read on first use.
+    ///
+    /// # Returns
+    /// * `FileLayer` - The cache.
+    pub const fn new() -> FileLayer {
+        FileLayer {
+            loaded: RwLock::new(None),
+        }
+    }
+
+    /// Gets the variables of the file, reading it if it changed since it was last read.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    /// * `load` - Reads the variables of the file.
+    ///
+    /// # Returns
+    /// * `LayerVariables` - The variables or the error loading the file.
+    pub fn variables(
+        &self,
+        path: &str,
+        load: impl FnOnce() -> Result<HashMap<String, String>, String>,
+    ) -> LayerVariables {
+        let modified = std::fs::metadata(Path::new(path))
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if let Some((loaded_path, loaded_modified, variables)) = &*self
+            .loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            && loaded_path == path
+            && *loaded_modified == modified
+        {
+            return variables.clone();
+        }
+        let variables = Arc::new(loaded_layer(path, load()));
+        *self
+            .loaded
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            Some((path.to_string(), modified, variables.clone()));
+        variables
+    }
+}
+
+impl Default for FileLayer {
+    fn default() -> Self {
+        FileLayer::new()
+    }
+}
+
+/// Gets a variable from the variables of a config layer.
+///
+/// # Arguments
+/// * `layer` - The name of the layer.
+/// * `variables` - The variables of the layer or the error loading it.
+/// * `variable` - The name of the variable.
+///
+/// # Returns
+/// * `Result<String, CruxmontError>` - The value of the variable.
+pub fn lookup_layer(
+    layer: &str,
+    variables: &Result<HashMap<String, String>, String>,
+    variable: String,
+) -> Result<String, CruxmontError> {
+    match variables {
+        Ok(variables) => variables
+            .get(&variable)
+            .cloned()
+            .ok_or_else(|| not_found(&variable, layer)),
+        Err(error) => Err(CruxmontError::new(
+            format!("{} could not be read from {}: {}", variable, layer, error),
+            CurxmontErrorStatus::Unknown,
+        )),
+    }
+}
+
+/// Defines a static config (useful for testing). We can run the by the following:
+///
+/// ```
+/// use cruxmont::define_static_config;
+///
+/// define_static_config!(
+///     TestConfig,
+///     "one" => "1",
+///     "two" => "2"
+/// );
+/// ```
+#[macro_export]
+macro_rules! define_static_config {
+    ($handle:ident, $( $key:expr => $value:expr ),*) => {
+        #[derive(Clone)]
+        pub struct $handle;
+        impl $crate::config::GetConfigVariable for $handle {
+            fn get_config_variable(variable: String) -> Result<String, $crate::errors::CruxmontError> {
+                match variable.as_str() {
+                    $(
+                        $key => Ok($value.to_string()),
+                    )*
+                    _ => Err($crate::config::not_found(&variable, stringify!($handle))),
+                }
+            }
+
+            fn layer_name() -> &'static str {
+                stringify!($handle)
+            }
+        }
+    };
+}
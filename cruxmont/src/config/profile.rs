@@ -0,0 +1,279 @@
+//! Defines the deployment profile selected with `CRUXMONT_ENV` and the config layers specific
+//! to a profile.
+//!
+//! # Notes
+//! Profile layers are named with a `{profile}` placeholder that is replaced by the current
+//! profile, so `config/app.{profile}.toml` reads `config/app.prod.toml` in production:
+//!
+//! ```
+//! use cruxmont::config::dotenv::DotEnvConfig;
+//! use cruxmont::config::layered::LayeredConfig;
+//! use cruxmont::config::profile::ProfileDotEnvConfig;
+//! use cruxmont::config::EnvConfig;
+//! use cruxmont::{define_file_config, define_profile_config};
+//!
+//! define_profile_config!(AppProfileConfig, "config/app.{profile}.toml");
+//! define_file_config!(AppFileConfig, "config/app.toml");
+//!
+//! // env > .env.{profile} > .env > config/app.{profile}.toml > config/app.toml
+//! type AppConfig = LayeredConfig<(
+//!     EnvConfig,
+//!     ProfileDotEnvConfig,
+//!     DotEnvConfig,
+//!     AppProfileConfig,
+//!     AppFileConfig,
+//! )>;
+//! ```
+use super::dotenv::load_dotenv_file;
+use super::file::load_config_file;
+use super::{EnvConfig, GetConfigVariable};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The config variable used by `Profile::from_config`.
+pub const PROFILE_VARIABLE: &str = "CRUXMONT_ENV";
+
+/// The placeholder replaced by the current profile in the paths of profile layers.
+pub const PROFILE_PLACEHOLDER: &str = "{profile}";
+
+// 0 means the profile has not been set and is read from the environment on first use
+static PROFILE: AtomicU8 = AtomicU8::new(0);
+
+/// The deployment profile of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Local development.
+    #[default]
+    Dev,
+    /// Automated tests.
+    Test,
+    /// Pre-production deployments.
+    Staging,
+    /// Production deployments.
+    Prod,
+}
+
+impl Profile {
+    /// Gets the profile of the process, read from `CRUXMONT_ENV` in the environment if it has
+    /// not been set.
+    ///
+    /// # Returns
+    /// * `Profile` - The profile in use for the process.
+    pub fn current() -> Profile {
+        match PROFILE.load(Ordering::Relaxed) {
+            1 => Profile::Dev,
+            2 => Profile::Test,
+            3 => Profile::Staging,
+            4 => Profile::Prod,
+            _ => {
+                let profile = Profile::from_config::<EnvConfig>();
+                profile.set();
+                profile
+            }
+        }
+    }
+
+    /// Sets the profile used for the process.
+    pub fn set(self) {
+        let value = match self {
+            Profile::Dev => 1,
+            Profile::Test => 2,
+            Profile::Staging => 3,
+            Profile::Prod => 4,
+        };
+        PROFILE.store(value, Ordering::Relaxed);
+    }
+
+    /// Reads the profile from the `CRUXMONT_ENV` config variable. A missing or unknown value
+    /// falls back to `dev`, an unknown one is logged as a warning.
+    ///
+    /// # Returns
+    /// * `Profile` - The profile defined in the config.
+    pub fn from_config<T: GetConfigVariable>() -> Profile {
+        match T::get_config_variable(PROFILE_VARIABLE.to_string()) {
+            Ok(value) => value.parse().unwrap_or_else(|error| {
+                tracing::warn!(%error, "unknown profile, using dev");
+                Profile::Dev
+            }),
+            Err(_) => Profile::Dev,
+        }
+    }
+
+    /// Gets the name of the profile used in the paths of profile layers.
+    ///
+    /// # Returns
+    /// * `&'static str` - One of `dev`, `test`, `staging` or `prod`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Staging => "staging",
+            Profile::Prod => "prod",
+        }
+    }
+
+    /// Replaces the `{profile}` placeholder of a path with the name of the profile.
+    ///
+    /// # Arguments
+    /// * `template` - The path with the placeholder.
+    ///
+    /// # Returns
+    /// * `String` - The path of the profile.
+    pub fn path(&self, template: &str) -> String {
+        template.replace(PROFILE_PLACEHOLDER, self.as_str())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "dev" | "development" | "local" => Ok(Profile::Dev),
+            "test" | "testing" => Ok(Profile::Test),
+            "staging" | "stage" => Ok(Profile::Staging),
+            "prod" | "production" => Ok(Profile::Prod),
+            other => Err(format!(
+                "{} is not a known {} profile",
+                other, PROFILE_VARIABLE
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Loads the layer of the current profile. Files named `.env*` are read as `.env` files, any
+/// other file by its extension. A missing file is treated as empty.
+///
+/// # Arguments
+/// * `template` - The path of the layer with the `{profile}` placeholder.
+///
+/// # Returns
+/// * `Result<HashMap<String, String>, String>` - The variables or the error reading the file.
+pub fn load_profile_layer(template: &str) -> Result<HashMap<String, String>, String> {
+    let path = Profile::current().path(template);
+    let is_dotenv = Path::new(&path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(".env"));
+    if is_dotenv {
+        load_dotenv_file(&path)
+    } else {
+        load_config_file(&path)
+    }
+}
+
+/// Defines a config provider reading the file of the current profile, for example:
+///
+/// ```
+/// use cruxmont::define_profile_config;
+///
+/// define_profile_config!(AppProfileConfig, "config/app.{profile}.yaml");
+/// ```
+#[macro_export]
+macro_rules! define_profile_config {
+    ($handle:ident, $template:expr) => {
+        #[derive(Clone)]
+        pub struct $handle;
+
+        impl $handle {
+            fn variables() -> $crate::config::LayerVariables {
+                static VARIABLES: $crate::config::FileLayer = $crate::config::FileLayer::new();
+                VARIABLES.variables(
+                    &$crate::config::profile::Profile::current().path($template),
+                    || $crate::config::profile::load_profile_layer($template),
+                )
+            }
+        }
+
+        impl $crate::config::GetConfigVariable for $handle {
+            fn get_config_variable(
+                variable: String,
+            ) -> Result<String, $crate::errors::CruxmontError> {
+                $crate::config::lookup_layer($template, &Self::variables(), variable)
+            }
+
+            fn layer_name() -> &'static str {
+                $template
+            }
+        }
+    };
+}
+
+define_profile_config!(ProfileDotEnvConfig, ".env.{profile}");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::layered::LayeredConfig;
+    use crate::config::source::{MapConfig, ScopedConfig};
+    use crate::define_dotenv_config;
+    use std::sync::{Arc, LazyLock};
+
+    static DIR: LazyLock<String> = LazyLock::new(|| {
+        let dir = std::env::temp_dir().join(format!("cruxmont-profile-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".env"), "LOG_LEVEL=debug\nPORT=8080\n").unwrap();
+        std::fs::write(dir.join(".env.prod"), "LOG_LEVEL=warn\n").unwrap();
+        dir.display().to_string()
+    });
+    static PROFILE_TEMPLATE: LazyLock<String> =
+        LazyLock::new(|| format!("{}/.env.{}", *DIR, PROFILE_PLACEHOLDER));
+    static DOTENV_PATH: LazyLock<String> = LazyLock::new(|| format!("{}/.env", *DIR));
+
+    define_profile_config!(TestProfileDotEnv, PROFILE_TEMPLATE.as_str());
+    define_dotenv_config!(TestDotEnv, DOTENV_PATH.as_str());
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!("production".parse::<Profile>().unwrap(), Profile::Prod);
+        assert_eq!(" Staging ".parse::<Profile>().unwrap(), Profile::Staging);
+        assert!("qa".parse::<Profile>().is_err());
+        assert_eq!(
+            Profile::Test.path("config/app.{profile}.toml"),
+            "config/app.test.toml"
+        );
+    }
+
+    /// Restores the profile of the process when dropped, even if the test panics.
+    struct ProfileGuard(u8);
+
+    impl Drop for ProfileGuard {
+        fn drop(&mut self) {
+            PROFILE.store(self.0, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_prod_profile_layer_is_read_over_dotenv() {
+        let config = Arc::new(MapConfig::new().with(PROFILE_VARIABLE, "prod"));
+        let profile = ScopedConfig::sync_scope(config, Profile::from_config::<ScopedConfig>);
+        assert_eq!(profile, Profile::Prod);
+
+        let _guard = ProfileGuard(PROFILE.load(Ordering::Relaxed));
+        profile.set();
+        assert_eq!(Profile::current(), Profile::Prod);
+        assert_eq!(
+            load_profile_layer(&PROFILE_TEMPLATE).unwrap()["LOG_LEVEL"],
+            "warn"
+        );
+
+        type Config = LayeredConfig<(TestProfileDotEnv, TestDotEnv)>;
+        assert_eq!(
+            Config::get_config_variable("LOG_LEVEL".to_string()).unwrap(),
+            "warn"
+        );
+        assert_eq!(
+            Config::get_config_variable("PORT".to_string()).unwrap(),
+            "8080"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Defines reloading a config struct while the program runs.
+//!
+//! # Notes
+//! `ConfigReloader` rebuilds its `ConfigSource` on every reload so files are read again, loads
+//! the struct and validates it. The layers of `define_dotenv_config!`, `define_file_config!` and
+//! `define_profile_config!` read their file again once it is modified, so they can be used in
+//! the source through `StaticSource` as well. Only a valid config that differs from the current one is sent
+//! to the subscribers, a failed reload is reported and the current config is kept:
+//!
+//! ```no_run
+//! use cruxmont::config::profile::Profile;
+//! use cruxmont::config::reload::ConfigReloader;
+//! use cruxmont::config::source::{LayeredSource, MapConfig, SharedConfig, StaticSource};
+//! use cruxmont::config::{CruxmontConfig, EnvConfig};
+//! use cruxmont::validation::Validate;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! #[derive(CruxmontConfig, Validate, PartialEq)]
+//! struct RuntimeConfig {
+//!     #[config(default = "info")]
+//!     log_level: String,
+//!     #[config(default = 5)]
+//!     #[validate(range(min = 1, max = 100))]
+//!     pool_size: u32,
+//! }
+//!
+//! # async fn run() -> Result<(), cruxmont::errors::CruxmontError> {
+//! let profile_file = Profile::current().path("config/app.{profile}.toml");
+//! let watched = vec![profile_file.clone().into(), "config/app.toml".into()];
+//! let reloader = ConfigReloader::<RuntimeConfig>::new(move || {
+//!     Ok(Arc::new(
+//!         LayeredSource::default()
+//!             .with_layer(StaticSource::<EnvConfig>::new())
+//!             .with_layer(MapConfig::from_file(&profile_file)?)
+//!             .with_layer(MapConfig::from_file("config/app.toml")?),
+//!     ) as SharedConfig)
+//! })?;
+//!
+//! let mut changes = reloader.subscribe();
+//! reloader.spawn_watcher(watched, Duration::from_secs(2));
+//! while changes.changed().await.is_ok() {
+//!     println!("log level is now {}", changes.borrow().log_level);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use super::source::SharedConfig;
+use super::typed::CruxmontConfig;
+use crate::errors::CruxmontError;
+use crate::validation::Validate;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// The function building the source a config is loaded from.
+pub type SourceBuilder = Arc<dyn Fn() -> Result<SharedConfig, CruxmontError> + Send + Sync>;
+
+/// Holds the current value of a config struct and reloads it on demand or when files change.
+pub struct ConfigReloader<C> {
+    builder: SourceBuilder,
+    sender: Arc<watch::Sender<Arc<C>>>,
+}
+
+impl<C> Clone for ConfigReloader<C> {
+    fn clone(&self) -> Self {
+        ConfigReloader {
+            builder: self.builder.clone(),
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<C> ConfigReloader<C>
+where
+    C: CruxmontConfig + Validate + PartialEq + Send + Sync + 'static,
+{
+    /// Loads the config for the first time.
+    ///
+    /// # Arguments
+    /// * `builder` - Builds the source the config is loaded from, called on every reload.
+    ///
+    /// # Returns
+    /// * `Result<ConfigReloader<C>, CruxmontError>` - The reloader, an error if the first load fails.
+    pub fn new(
+        builder: impl Fn() -> Result<SharedConfig, CruxmontError> + Send + Sync + 'static,
+    ) -> Result<ConfigReloader<C>, CruxmontError> {
+        let builder: SourceBuilder = Arc::new(builder);
+        let config = load_validated::<C>(&builder)?;
+        let (sender, _) = watch::channel(Arc::new(config));
+        Ok(ConfigReloader {
+            builder,
+            sender: Arc::new(sender),
+        })
+    }
+
+    /// Gets the current config.
+    ///
+    /// # Returns
+    /// * `Arc<C>` - The config that was last loaded successfully.
+    pub fn current(&self) -> Arc<C> {
+        self.sender.borrow().clone()
+    }
+
+    /// Subscribes to changes of the config.
+    ///
+    /// # Returns
+    /// * `watch::Receiver<Arc<C>>` - The receiver notified every time a changed config is swapped in.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<C>> {
+        self.sender.subscribe()
+    }
+
+    /// Loads and validates the config, swapping it in if it changed. A failed reload is logged
+    /// as a warning and the current config is kept.
+    ///
+    /// # Returns
+    /// * `Result<bool, CruxmontError>` - Whether the config changed, the error if the reload failed.
+    pub fn reload(&self) -> Result<bool, CruxmontError> {
+        match load_validated::<C>(&self.builder) {
+            Ok(config) => Ok(self.sender.send_if_modified(|current| {
+                if **current == config {
+                    false
+                } else {
+                    *current = Arc::new(config);
+                    true
+                }
+            })),
+            Err(error) => {
+                tracing::warn!(%error, "config reload failed, keeping the current config");
+                Err(error)
+            }
+        }
+    }
+
+    /// Polls the modification times of files and reloads the config when one changes. The
+    /// future runs until every subscriber and reloader is dropped.
+    ///
+    /// # Arguments
+    /// * `paths` - The files to watch, missing files are watched for their creation.
+    /// * `interval` - The time between two polls.
+    pub async fn watch_files(self, paths: Vec<PathBuf>, interval: Duration) {
+        let mut modified = modification_times(&paths);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if Arc::strong_count(&self.sender) == 1 && self.sender.receiver_count() == 0 {
+                return;
+            }
+            let latest = modification_times(&paths);
+            if latest != modified {
+                modified = latest;
+                // failures are reported by reload and retried on the next change
+                let _ = self.reload();
+            }
+        }
+    }
+
+    /// Spawns `watch_files` on the tokio runtime.
+    ///
+    /// # Arguments
+    /// * `paths` - The files to watch.
+    /// * `interval` - The time between two polls.
+    ///
+    /// # Returns
+    /// * `JoinHandle<()>` - The handle of the watcher task.
+    pub fn spawn_watcher(&self, paths: Vec<PathBuf>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(self.clone().watch_files(paths, interval))
+    }
+}
+
+/// Builds the source, loads the config and validates it.
+fn load_validated<C: CruxmontConfig + Validate>(
+    builder: &SourceBuilder,
+) -> Result<C, CruxmontError> {
+    let source = builder()?;
+    let config = C::load_from(&source)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Gets the modification time of every file, None for files that cannot be read.
+fn modification_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CruxmontConfig;
+    use crate::config::source::{MapConfig, StaticSource};
+    use crate::define_file_config;
+    use crate::validation::Validate;
+    use std::sync::{LazyLock, Mutex};
+
+    static FILE_PATH: LazyLock<String> = LazyLock::new(|| {
+        std::env::temp_dir()
+            .join(format!("cruxmont-reload-{}.json", uuid::Uuid::new_v4()))
+            .display()
+            .to_string()
+    });
+
+    define_file_config!(TestFileConfig, FILE_PATH.as_str());
+
+    #[derive(CruxmontConfig, Validate, PartialEq, Debug)]
+    struct RuntimeConfig {
+        log_level: String,
+        #[validate(range(min = 1, max = 100))]
+        pool_size: u32,
+    }
+
+    #[tokio::test]
+    async fn test_reload_validates_before_swapping() {
+        let values = Arc::new(Mutex::new(("info", "5")));
+        let source_values = values.clone();
+        let reloader = ConfigReloader::<RuntimeConfig>::new(move || {
+            let (log_level, pool_size) = *source_values.lock().unwrap();
+            Ok(Arc::new(
+                MapConfig::new()
+                    .with("LOG_LEVEL", log_level)
+                    .with("POOL_SIZE", pool_size),
+            ) as SharedConfig)
+        })
+        .unwrap();
+        let mut changes = reloader.subscribe();
+
+        assert!(!reloader.reload().unwrap());
+
+        *values.lock().unwrap() = ("debug", "10");
+        assert!(reloader.reload().unwrap());
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(changes.borrow_and_update().log_level, "debug");
+
+        // out of range, the current config is kept and nothing is sent
+        *values.lock().unwrap() = ("trace", "0");
+        assert!(reloader.reload().is_err());
+        assert!(!changes.has_changed().unwrap());
+        assert_eq!(reloader.current().pool_size, 10);
+    }
+
+    #[tokio::test]
+    async fn test_watched_file_layer_is_read_again() {
+        std::fs::write(&*FILE_PATH, r#"{"log_level": "info", "pool_size": 5}"#).unwrap();
+        let reloader = ConfigReloader::<RuntimeConfig>::new(|| {
+            Ok(Arc::new(StaticSource::<TestFileConfig>::new()) as SharedConfig)
+        })
+        .unwrap();
+        let mut changes = reloader.subscribe();
+        let watcher =
+            reloader.spawn_watcher(vec![FILE_PATH.as_str().into()], Duration::from_millis(10));
+
+        // leaves the watcher time to record the modification time of the first write
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(&*FILE_PATH, r#"{"log_level": "debug", "pool_size": 5}"#).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changes.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changes.borrow().log_level, "debug");
+
+        watcher.abort();
+        std::fs::remove_file(&*FILE_PATH).unwrap();
+    }
+}
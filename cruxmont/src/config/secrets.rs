@@ -0,0 +1,263 @@
+//! Defines reading secrets mounted as files and keeping them out of logs.
+//!
+//! # Notes
+//! Docker and Kubernetes mount secrets as files. `EnvConfig` follows the `VAR_FILE` convention
+//! for single variables and `SecretsDirConfig` reads every file of a secrets directory as a
+//! variable. Values loaded into a `Secret` are redacted when printed or serialized.
+use super::{GetConfigVariable, not_found};
+use crate::errors::{CruxmontError, CurxmontErrorStatus};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The text printed in place of a secret value.
+pub const REDACTED_SECRET: &str = "[REDACTED]";
+
+/// The config variable holding the directory read by `SecretsDirConfig`.
+pub const SECRETS_DIR_VARIABLE: &str = "CRUXMONT_SECRETS_DIR";
+
+/// The directory read by `SecretsDirConfig` when `CRUXMONT_SECRETS_DIR` is not set.
+pub const DEFAULT_SECRETS_DIR: &str = "/run/secrets";
+
+/// A value that is redacted by `Debug`, `Display` and `Serialize`.
+///
+/// # Notes
+/// The value is only reachable through `expose_secret` so every use of it is explicit. Secrets
+/// parse with the `FromStr` of the inner type so they can be fields of a `CruxmontConfig` struct.
+#[derive(Clone, Default)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    /// Wraps a value.
+    ///
+    /// # Arguments
+    /// * `value` - The secret value.
+    ///
+    /// # Returns
+    /// * `Secret<T>` - The wrapped value.
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    /// Gets the secret value.
+    ///
+    /// # Returns
+    /// * `&T` - The secret value.
+    pub fn expose_secret(&self) -> &T {
+        &self.0
+    }
+
+    /// Unwraps the secret value.
+    ///
+    /// # Returns
+    /// * `T` - The secret value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED_SECRET)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED_SECRET)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED_SECRET)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+impl<T: FromStr> FromStr for Secret<T> {
+    type Err = T::Err;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        T::from_str(value).map(Secret)
+    }
+}
+
+/// Reads a secret file, trailing newlines are removed as editors and `echo` add them.
+///
+/// # Arguments
+/// * `path` - The path of the file.
+///
+/// # Returns
+/// * `Result<String, std::io::Error>` - The contents of the file.
+pub fn read_secret_file(path: impl AsRef<Path>) -> Result<String, std::io::Error> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Gets a variable from a secrets directory. The file named after the variable is read, falling
+/// back to the lower case name as used by Docker secrets when there is no such file. A file that
+/// exists but cannot be read is an error rather than a missing variable.
+///
+/// # Arguments
+/// * `dir` - The secrets directory.
+/// * `variable` - The name of the variable.
+///
+/// # Returns
+/// * `Result<String, CruxmontError>` - The value of the variable.
+pub fn read_secrets_dir_variable(
+    dir: impl AsRef<Path>,
+    variable: &str,
+) -> Result<String, CruxmontError> {
+    let dir = dir.as_ref();
+    // the variable name must not be able to escape the directory
+    if variable.is_empty() || variable.contains(['/', '\\']) || variable.starts_with('.') {
+        return Err(CruxmontError::new(
+            format!("{} is not a valid secret name", variable),
+            CurxmontErrorStatus::Unknown,
+        ));
+    }
+    for name in [variable.to_string(), variable.to_lowercase()] {
+        match read_secret_file(dir.join(&name)) {
+            Ok(value) => return Ok(value),
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => {
+                return Err(CruxmontError::new(
+                    format!(
+                        "{} could not be read from {}: {}",
+                        name,
+                        dir.display(),
+                        error
+                    ),
+                    CurxmontErrorStatus::Unknown,
+                ));
+            }
+        }
+    }
+    Err(not_found(variable, &dir.display().to_string()))
+}
+
+/// Config provider reading secrets from the directory in `CRUXMONT_SECRETS_DIR`, defaulting to
+/// `/run/secrets`. Files are read on every lookup so rotated secrets are picked up.
+#[derive(Clone)]
+pub struct SecretsDirConfig;
+
+impl GetConfigVariable for SecretsDirConfig {
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+        let dir =
+            std::env::var(SECRETS_DIR_VARIABLE).unwrap_or_else(|_| DEFAULT_SECRETS_DIR.to_string());
+        read_secrets_dir_variable(dir, &variable)
+    }
+
+    fn layer_name() -> &'static str {
+        "secrets"
+    }
+}
+
+/// Defines a config provider reading secrets from a fixed directory, for example:
+///
+/// ```
+/// use cruxmont::define_secrets_dir_config;
+///
+/// define_secrets_dir_config!(VaultSecrets, "/vault/secrets");
+/// ```
+#[macro_export]
+macro_rules! define_secrets_dir_config {
+    ($handle:ident, $dir:expr) => {
+        #[derive(Clone)]
+        pub struct $handle;
+
+        impl $crate::config::GetConfigVariable for $handle {
+            fn get_config_variable(
+                variable: String,
+            ) -> Result<String, $crate::errors::CruxmontError> {
+                $crate::config::secrets::read_secrets_dir_variable($dir, &variable)
+            }
+
+            fn layer_name() -> &'static str {
+                $dir
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EnvConfig;
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret: Secret<String> = "hunter2".parse().unwrap();
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(secret.to_string(), REDACTED_SECRET);
+        assert_eq!(
+            serde_json::to_string(&secret).unwrap(),
+            format!("\"{}\"", REDACTED_SECRET)
+        );
+        assert_eq!(secret.expose_secret(), "hunter2");
+    }
+
+    #[test]
+    fn test_secrets_dir() {
+        let dir = std::env::temp_dir().join(format!("cruxmont-secrets-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("db_password"), "hunter2\n").unwrap();
+
+        assert_eq!(
+            read_secrets_dir_variable(&dir, "DB_PASSWORD").unwrap(),
+            "hunter2"
+        );
+        assert!(read_secrets_dir_variable(&dir, "API_KEY").is_err());
+        assert!(read_secrets_dir_variable(&dir, "../db_password").is_err());
+
+        // a secret that cannot be read is not treated as missing
+        fs::create_dir(dir.join("api_key")).unwrap();
+        let error = read_secrets_dir_variable(&dir, "API_KEY").unwrap_err();
+        assert!(!crate::config::is_not_found(&error));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Removes the listed environment variables when dropped, even if the test panics.
+    struct EnvGuard(&'static [&'static str]);
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for variable in self.0 {
+                unsafe { std::env::remove_var(variable) };
+            }
+        }
+    }
+
+    #[test]
+    fn test_env_file_variable_is_preferred() {
+        let path = std::env::temp_dir().join(format!("cruxmont-secret-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, "hunter2\n").unwrap();
+        let _guard = EnvGuard(&["CRUXMONT_TEST_PASSWORD", "CRUXMONT_TEST_PASSWORD_FILE"]);
+        unsafe {
+            std::env::set_var("CRUXMONT_TEST_PASSWORD", "from-env");
+            std::env::set_var("CRUXMONT_TEST_PASSWORD_FILE", &path);
+        }
+
+        assert_eq!(
+            EnvConfig::get_config_variable("CRUXMONT_TEST_PASSWORD".to_string()).unwrap(),
+            "hunter2"
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(EnvConfig::get_config_variable("CRUXMONT_TEST_PASSWORD".to_string()).is_err());
+    }
+}
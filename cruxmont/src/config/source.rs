@@ -0,0 +1,437 @@
+//! Defines config providers that are values instead of types.
+//!
+//! # Notes
+//! `GetConfigVariable` is implemented on types so every provider is global to the process.
+//! `ConfigSource` takes `&self` so a provider can be built at runtime, shared as
+//! `Arc<dyn ConfigSource>` through axum state and created per test without touching `std::env`.
+//! `StaticSource` turns a `GetConfigVariable` type into a source and `ScopedConfig` runs code
+//! that expects a `GetConfigVariable` type against a source:
+//!
+//! ```
+//! use cruxmont::config::source::{ConfigSource, MapConfig, ScopedConfig, SharedConfig};
+//! use cruxmont::config::GetConfigVariable;
+//! use std::sync::Arc;
+//!
+//! let config: SharedConfig = Arc::new(MapConfig::new().with("PORT", "8080"));
+//! assert_eq!(config.get("PORT").unwrap(), "8080");
+//!
+//! let port = ScopedConfig::sync_scope(config, || {
+//!     ScopedConfig::get_config_variable("PORT".to_string())
+//! });
+//! assert_eq!(port.unwrap(), "8080");
+//! ```
+use super::dotenv::load_dotenv_file;
+use super::file::load_config_file;
+use super::{GetConfigVariable, is_not_found, not_found};
+use crate::errors::{CruxmontError, CurxmontErrorStatus};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A config source shared between threads, for example in axum state.
+pub type SharedConfig = Arc<dyn ConfigSource>;
+
+/// Defines the trait for config providers that are values.
+pub trait ConfigSource: Send + Sync {
+    /// Gets a config variable.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    ///
+    /// # Returns
+    /// * `Result<String, CruxmontError>` - The value of the variable.
+    fn get(&self, variable: &str) -> Result<String, CruxmontError>;
+
+    /// Gets the name of the source used when reporting which layer a value came from.
+    ///
+    /// # Returns
+    /// * `&str` - The name of the source.
+    fn name(&self) -> &str {
+        "source"
+    }
+}
+
+impl<S: ConfigSource + ?Sized> ConfigSource for Arc<S> {
+    fn get(&self, variable: &str) -> Result<String, CruxmontError> {
+        (**self).get(variable)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+impl<S: ConfigSource + ?Sized> ConfigSource for Box<S> {
+    fn get(&self, variable: &str) -> Result<String, CruxmontError> {
+        (**self).get(variable)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+/// Config source holding its variables in a map.
+#[derive(Clone, Debug, Default)]
+pub struct MapConfig {
+    name: String,
+    variables: HashMap<String, String>,
+}
+
+impl MapConfig {
+    /// Constructs an empty source named `map`.
+    ///
+    /// # Returns
+    /// * `MapConfig` - The empty source.
+    pub fn new() -> MapConfig {
+        MapConfig::from_map(HashMap::new())
+    }
+
+    /// Constructs a source from a map of variables.
+    ///
+    /// # Arguments
+    /// * `variables` - The variables of the source.
+    ///
+    /// # Returns
+    /// * `MapConfig` - The source.
+    pub fn from_map(variables: HashMap<String, String>) -> MapConfig {
+        MapConfig {
+            name: "map".to_string(),
+            variables,
+        }
+    }
+
+    /// Reads a source from a TOML, YAML or JSON file, a missing file gives an empty source.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    /// * `Result<MapConfig, CruxmontError>` - The source named after the file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<MapConfig, CruxmontError> {
+        MapConfig::from_loaded(path.as_ref(), load_config_file(path.as_ref()))
+    }
+
+    /// Reads a source from a `.env` file, a missing file gives an empty source.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    /// * `Result<MapConfig, CruxmontError>` - The source named after the file.
+    pub fn from_dotenv_file(path: impl AsRef<Path>) -> Result<MapConfig, CruxmontError> {
+        MapConfig::from_loaded(path.as_ref(), load_dotenv_file(path.as_ref()))
+    }
+
+    fn from_loaded(
+        path: &Path,
+        loaded: Result<HashMap<String, String>, String>,
+    ) -> Result<MapConfig, CruxmontError> {
+        match loaded {
+            Ok(variables) => Ok(MapConfig::from_map(variables).named(path.display().to_string())),
+            Err(error) => Err(CruxmontError::new(error, CurxmontErrorStatus::Unknown)),
+        }
+    }
+
+    /// Sets the name of the source.
+    ///
+    /// # Arguments
+    /// * `name` - The name reported for the source.
+    ///
+    /// # Returns
+    /// * `MapConfig` - The renamed source.
+    pub fn named(mut self, name: impl Into<String>) -> MapConfig {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets a variable.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    /// * `value` - The value of the variable.
+    ///
+    /// # Returns
+    /// * `MapConfig` - The source with the variable.
+    pub fn with(mut self, variable: impl Into<String>, value: impl Into<String>) -> MapConfig {
+        self.variables.insert(variable.into(), value.into());
+        self
+    }
+
+    /// Sets a variable in place.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    /// * `value` - The value of the variable.
+    pub fn insert(&mut self, variable: impl Into<String>, value: impl Into<String>) {
+        self.variables.insert(variable.into(), value.into());
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for MapConfig {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        MapConfig::from_map(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl ConfigSource for MapConfig {
+    fn get(&self, variable: &str) -> Result<String, CruxmontError> {
+        self.variables
+            .get(variable)
+            .cloned()
+            .ok_or_else(|| not_found(variable, &self.name))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Config source combining sources, earlier sources take precedence.
+#[derive(Clone, Default)]
+pub struct LayeredSource {
+    layers: Vec<SharedConfig>,
+}
+
+impl LayeredSource {
+    /// Constructs a source from layers ordered from highest to lowest precedence.
+    ///
+    /// # Arguments
+    /// * `layers` - The sources to combine.
+    ///
+    /// # Returns
+    /// * `LayeredSource` - The combined source.
+    pub fn new(layers: Vec<SharedConfig>) -> LayeredSource {
+        LayeredSource { layers }
+    }
+
+    /// Adds a layer below the existing layers.
+    ///
+    /// # Arguments
+    /// * `layer` - The source to add.
+    ///
+    /// # Returns
+    /// * `LayeredSource` - The combined source.
+    pub fn with_layer(mut self, layer: impl ConfigSource + 'static) -> LayeredSource {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Gets the layer a variable is read from.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the variable.
+    ///
+    /// # Returns
+    /// * `Option<&str>` - The name of the layer, None if no layer has the variable. A layer that
+    ///   fails to read the variable is reported as its source.
+    pub fn source_of(&self, variable: &str) -> Option<&str> {
+        self.resolve(variable).map(|(_, layer)| layer.name())
+    }
+
+    /// Gets a variable from the first layer that has it or fails to read it, so a broken layer
+    /// never falls through to the values of a lower layer.
+    fn resolve(&self, variable: &str) -> Option<(Result<String, CruxmontError>, &SharedConfig)> {
+        self.layers
+            .iter()
+            .find_map(|layer| match layer.get(variable) {
+                Err(error) if is_not_found(&error) => None,
+                result => Some((result, layer)),
+            })
+    }
+}
+
+impl ConfigSource for LayeredSource {
+    fn get(&self, variable: &str) -> Result<String, CruxmontError> {
+        match self.resolve(variable) {
+            Some((result, _)) => result,
+            None => {
+                let names = self
+                    .layers
+                    .iter()
+                    .map(|layer| layer.name())
+                    .collect::<Vec<&str>>()
+                    .join(", ");
+                Err(not_found(
+                    variable,
+                    &format!("any config layer ({})", names),
+                ))
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "layered"
+    }
+}
+
+/// Config source reading from a `GetConfigVariable` type such as `EnvConfig`.
+pub struct StaticSource<T>(PhantomData<fn() -> T>);
+
+impl<T: GetConfigVariable> StaticSource<T> {
+    /// Constructs the source.
+    ///
+    /// # Returns
+    /// * `StaticSource<T>` - The source.
+    pub fn new() -> StaticSource<T> {
+        StaticSource(PhantomData)
+    }
+}
+
+impl<T: GetConfigVariable> Default for StaticSource<T> {
+    fn default() -> Self {
+        StaticSource::new()
+    }
+}
+
+impl<T: GetConfigVariable> Clone for StaticSource<T> {
+    fn clone(&self) -> Self {
+        StaticSource::new()
+    }
+}
+
+impl<T: GetConfigVariable> ConfigSource for StaticSource<T> {
+    fn get(&self, variable: &str) -> Result<String, CruxmontError> {
+        T::get_config_variable(variable.to_string())
+    }
+
+    fn name(&self) -> &str {
+        T::layer_name()
+    }
+}
+
+tokio::task_local! {
+    static SCOPED_CONFIG: SharedConfig;
+}
+
+/// Config provider reading from the source of the current scope, so code generic over
+/// `GetConfigVariable` such as `CruxmontConfig::load` runs against a `ConfigSource`.
+///
+/// # Notes
+/// Scopes are task local, concurrent tests each see their own source. Outside of a scope every
+/// variable is missing.
+#[derive(Clone)]
+pub struct ScopedConfig;
+
+impl ScopedConfig {
+    /// Runs a future with a config source.
+    ///
+    /// # Arguments
+    /// * `source` - The source variables are read from.
+    /// * `future` - The future to run.
+    ///
+    /// # Returns
+    /// * `F::Output` - The output of the future.
+    pub async fn scope<F: Future>(source: SharedConfig, future: F) -> F::Output {
+        SCOPED_CONFIG.scope(source, future).await
+    }
+
+    /// Runs a closure with a config source.
+    ///
+    /// # Arguments
+    /// * `source` - The source variables are read from.
+    /// * `function` - The closure to run.
+    ///
+    /// # Returns
+    /// * `R` - The output of the closure.
+    pub fn sync_scope<R>(source: SharedConfig, function: impl FnOnce() -> R) -> R {
+        SCOPED_CONFIG.sync_scope(source, function)
+    }
+
+    /// Gets the source of the current scope.
+    ///
+    /// # Returns
+    /// * `Option<SharedConfig>` - The source, None outside of a scope.
+    pub fn current() -> Option<SharedConfig> {
+        SCOPED_CONFIG.try_with(|source| source.clone()).ok()
+    }
+}
+
+impl GetConfigVariable for ScopedConfig {
+    fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+        match ScopedConfig::current() {
+            Some(source) => source.get(&variable),
+            None => Err(not_found(&variable, "the config scope, none is set")),
+        }
+    }
+
+    fn layer_name() -> &'static str {
+        "scoped"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CruxmontConfig;
+    use crate::define_static_config;
+
+    define_static_config!(DefaultsConfig, "PORT" => "80", "HOST" => "localhost");
+
+    #[derive(CruxmontConfig)]
+    struct ServerConfig {
+        host: String,
+        port: u16,
+    }
+
+    /// Fails to read every variable, as a secrets directory it cannot list does.
+    struct UnreadableSource;
+
+    impl ConfigSource for UnreadableSource {
+        fn get(&self, _variable: &str) -> Result<String, CruxmontError> {
+            Err(CruxmontError::unknown("permission denied"))
+        }
+
+        fn name(&self) -> &str {
+            "unreadable"
+        }
+    }
+
+    #[test]
+    fn test_layered_sources() {
+        let source = LayeredSource::new(vec![Arc::new(
+            MapConfig::from_iter([("PORT", "8080")]).named("test"),
+        )])
+        .with_layer(StaticSource::<DefaultsConfig>::new());
+
+        assert_eq!(source.get("PORT").unwrap(), "8080");
+        assert_eq!(source.get("HOST").unwrap(), "localhost");
+        assert_eq!(source.source_of("PORT"), Some("test"));
+        assert_eq!(source.source_of("HOST"), Some("DefaultsConfig"));
+        assert!(source.get("MISSING").is_err());
+
+        let source = LayeredSource::new(vec![Arc::new(UnreadableSource)])
+            .with_layer(StaticSource::<DefaultsConfig>::new());
+        assert_eq!(source.get("PORT").unwrap_err().message, "permission denied");
+        assert_eq!(source.source_of("PORT"), Some("unreadable"));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_sources_are_isolated() {
+        let first: SharedConfig = Arc::new(MapConfig::new().with("HOST", "a").with("PORT", "1"));
+        let second: SharedConfig = Arc::new(MapConfig::new().with("HOST", "b").with("PORT", "2"));
+
+        let (first, second) = tokio::join!(
+            ScopedConfig::scope(first, async { ServerConfig::load::<ScopedConfig>() }),
+            ScopedConfig::scope(second, async { ServerConfig::load::<ScopedConfig>() }),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        let third = ServerConfig::load_from(
+            &(Arc::new(MapConfig::new().with("HOST", "c").with("PORT", "3")) as SharedConfig),
+        )
+        .unwrap();
+        assert_eq!((third.host.as_str(), third.port), ("c", 3));
+        assert_eq!((first.host.as_str(), first.port), ("a", 1));
+        assert_eq!((second.host.as_str(), second.port), ("b", 2));
+        // outside of a scope every variable is missing
+        assert!(is_not_found(
+            &ScopedConfig::get_config_variable("HOST".to_string()).unwrap_err()
+        ));
+    }
+}
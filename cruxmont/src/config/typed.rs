@@ -0,0 +1,425 @@
+//! Defines loading typed config structs through `GetConfigVariable`.
+//!
+//! # Notes
+//! Structs derive `CruxmontConfig` and are loaded in one go. Every missing or unparsable
+//! variable is collected into a single `ConfigError` instead of failing on the first one.
+//!
+//! ```
+//! use cruxmont::config::{CruxmontConfig, GetConfigVariable};
+//! use cruxmont::define_static_config;
+//!
+//! #[derive(CruxmontConfig)]
+//! struct DatabaseConfig {
+//!     /// The connection string of the database
+//!     url: String,
+//!     #[config(default = 5)]
+//!     max_connections: u32,
+//! }
+//!
+//! #[derive(CruxmontConfig)]
+//! #[config(prefix = "APP_")]
+//! struct AppConfig {
+//!     #[config(env = "PORT", default = "8080")]
+//!     port: u16,
+//!     log_level: Option<String>,
+//!     #[config(nested, prefix = "DB_")]
+//!     database: DatabaseConfig,
+//! }
+//!
+//! define_static_config!(TestConfig, "APP_DB_URL" => "postgres://localhost/app");
+//!
+//! let config = AppConfig::load::<TestConfig>().unwrap();
+//! assert_eq!(config.port, 8080);
+//! assert_eq!(config.database.max_connections, 5);
+//! assert!(AppConfig::env_example().contains("APP_DB_URL="));
+//! ```
+use super::source::{ScopedConfig, SharedConfig};
+use super::{GetConfigVariable, is_not_found};
+use crate::errors::CruxmontError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+
+/// Defines the trait for structs loaded from config variables, usually derived with
+/// `#[derive(CruxmontConfig)]`.
+pub trait CruxmontConfig: Sized {
+    /// Loads the struct with every variable name prefixed.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix of the variable names.
+    ///
+    /// # Returns
+    /// * `Result<Self, ConfigError>` - The loaded struct or every problem with the variables.
+    fn load_with_prefix<T: GetConfigVariable>(prefix: &str) -> Result<Self, ConfigError>;
+
+    /// Describes the variables the struct is loaded from.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix of the variable names.
+    ///
+    /// # Returns
+    /// * `Vec<ConfigVariable>` - The variables in the order of the fields.
+    fn variables(prefix: &str) -> Vec<ConfigVariable>;
+
+    /// Loads the struct.
+    ///
+    /// # Returns
+    /// * `Result<Self, ConfigError>` - The loaded struct or every problem with the variables.
+    fn load<T: GetConfigVariable>() -> Result<Self, ConfigError> {
+        Self::load_with_prefix::<T>("")
+    }
+
+    /// Loads the struct from a config source instead of a `GetConfigVariable` type.
+    ///
+    /// # Arguments
+    /// * `source` - The source the variables are read from.
+    ///
+    /// # Returns
+    /// * `Result<Self, ConfigError>` - The loaded struct or every problem with the variables.
+    fn load_from(source: &SharedConfig) -> Result<Self, ConfigError> {
+        ScopedConfig::sync_scope(source.clone(), Self::load::<ScopedConfig>)
+    }
+
+    /// Renders a `.env.example` file listing every variable of the struct.
+    ///
+    /// # Returns
+    /// * `String` - The contents of the example file.
+    fn env_example() -> String {
+        render_env_example(&Self::variables(""))
+    }
+}
+
+/// The description of a config variable used to render `.env.example` files.
+///
+/// # Fields
+/// * `name` - The full name of the variable.
+/// * `default` - The default value of the variable, if any.
+/// * `required` - Whether loading fails when the variable is missing.
+/// * `description` - The doc comment of the field, if any.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigVariable {
+    pub name: String,
+    pub default: Option<String>,
+    pub required: bool,
+    pub description: Option<String>,
+}
+
+/// Renders variables as a `.env.example` file. Optional variables are commented out.
+///
+/// # Arguments
+/// * `variables` - The variables to render.
+///
+/// # Returns
+/// * `String` - The contents of the example file.
+pub fn render_env_example(variables: &[ConfigVariable]) -> String {
+    let mut example = String::new();
+    for variable in variables {
+        if let Some(description) = &variable.description {
+            example.push_str(&format!("# {}\n", description));
+        }
+        match (&variable.default, variable.required) {
+            (Some(default), _) => example.push_str(&format!("{}={}\n", variable.name, default)),
+            (None, true) => example.push_str(&format!("{}=\n", variable.name)),
+            (None, false) => example.push_str(&format!("# {}=\n", variable.name)),
+        }
+    }
+    example
+}
+
+/// A problem with a single config variable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum ConfigProblem {
+    /// A required variable is not set.
+    Missing { variable: String },
+    /// A variable (or its default) could not be parsed into the type of the field.
+    Invalid { variable: String, message: String },
+    /// The provider failed to read a variable, such as a `_FILE` secret it could not open.
+    Unavailable { variable: String, error: String },
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::Missing { variable } => write!(f, "{} is not set", variable),
+            ConfigProblem::Invalid { variable, message } => {
+                write!(f, "{} is invalid: {}", variable, message)
+            }
+            ConfigProblem::Unavailable { variable, error } => {
+                write!(f, "{} could not be read: {}", variable, error)
+            }
+        }
+    }
+}
+
+/// Every problem found when loading a config struct.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ConfigError {
+    problems: Vec<ConfigProblem>,
+}
+
+impl ConfigError {
+    /// Constructs an empty error.
+    ///
+    /// # Returns
+    /// * `ConfigError` - The empty error.
+    pub fn new() -> ConfigError {
+        ConfigError::default()
+    }
+
+    /// Adds a problem.
+    ///
+    /// # Arguments
+    /// * `problem` - The problem to add.
+    pub fn push(&mut self, problem: ConfigProblem) {
+        self.problems.push(problem);
+    }
+
+    /// Adds the problems of another error, used for nested structs.
+    ///
+    /// # Arguments
+    /// * `other` - The error to take the problems of.
+    pub fn extend(&mut self, other: ConfigError) {
+        self.problems.extend(other.problems);
+    }
+
+    /// Checks if there are no problems.
+    ///
+    /// # Returns
+    /// * `bool` - True if no problems have been added.
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Gets the problems.
+    ///
+    /// # Returns
+    /// * `&[ConfigProblem]` - The problems in the order of the fields.
+    pub fn problems(&self) -> &[ConfigProblem] {
+        &self.problems
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid config:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for CruxmontError {
+    fn from(error: ConfigError) -> Self {
+        CruxmontError::unknown(error.to_string())
+            .with_details(json!({ "problems": error.problems }))
+    }
+}
+
+/// Parses a variable into the type of its field.
+///
+/// # Arguments
+/// * `name` - The name of the variable.
+/// * `raw` - The value of the variable.
+/// * `errors` - The error the problem is added to if parsing fails.
+///
+/// # Returns
+/// * `Option<V>` - The parsed value, None if parsing failed.
+fn parse_variable<V>(name: &str, raw: &str, errors: &mut ConfigError) -> Option<V>
+where
+    V: FromStr,
+    V::Err: fmt::Display,
+{
+    match V::from_str(raw.trim()) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            errors.push(ConfigProblem::Invalid {
+                variable: name.to_string(),
+                message: error.to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Adds the problem of a variable the provider failed to read.
+///
+/// # Arguments
+/// * `name` - The name of the variable.
+/// * `error` - The error of the provider.
+/// * `errors` - The error the problem is added to.
+fn push_unavailable(name: &str, error: CruxmontError, errors: &mut ConfigError) {
+    errors.push(ConfigProblem::Unavailable {
+        variable: name.to_string(),
+        error: error.message,
+    });
+}
+
+/// Loads a required variable, used by the generated `CruxmontConfig` impls.
+///
+/// # Arguments
+/// * `name` - The name of the variable.
+/// * `default` - The value used when the variable is not set, it is not used when the provider
+///   fails to read the variable.
+/// * `errors` - The error problems are added to.
+///
+/// # Returns
+/// * `Option<V>` - The value, None if there was a problem.
+pub fn load_required<T, V>(name: &str, default: Option<&str>, errors: &mut ConfigError) -> Option<V>
+where
+    T: GetConfigVariable,
+    V: FromStr,
+    V::Err: fmt::Display,
+{
+    match (T::get_config_variable(name.to_string()), default) {
+        (Ok(raw), _) => parse_variable(name, &raw, errors),
+        (Err(error), _) if !is_not_found(&error) => {
+            push_unavailable(name, error, errors);
+            None
+        }
+        (Err(_), Some(default)) => parse_variable(name, default, errors),
+        (Err(_), None) => {
+            errors.push(ConfigProblem::Missing {
+                variable: name.to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Loads an optional variable, used by the generated `CruxmontConfig` impls.
+///
+/// # Arguments
+/// * `name` - The name of the variable.
+/// * `default` - The value used when the variable is not set, it is not used when the provider
+///   fails to read the variable.
+/// * `errors` - The error problems are added to.
+///
+/// # Returns
+/// * `Option<Option<V>>` - The value (None if missing), None if there was a problem.
+pub fn load_optional<T, V>(
+    name: &str,
+    default: Option<&str>,
+    errors: &mut ConfigError,
+) -> Option<Option<V>>
+where
+    T: GetConfigVariable,
+    V: FromStr,
+    V::Err: fmt::Display,
+{
+    match (T::get_config_variable(name.to_string()), default) {
+        (Ok(raw), _) => parse_variable(name, &raw, errors).map(Some),
+        (Err(error), _) if !is_not_found(&error) => {
+            push_unavailable(name, error, errors);
+            None
+        }
+        (Err(_), Some(default)) => parse_variable(name, default, errors).map(Some),
+        (Err(_), None) => Some(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CruxmontConfig, not_found};
+    use crate::define_static_config;
+    use crate::errors::CurxmontErrorStatus;
+
+    #[derive(CruxmontConfig, Debug)]
+    struct PoolConfig {
+        url: String,
+        #[config(default = 10)]
+        max_connections: u32,
+    }
+
+    #[derive(CruxmontConfig, Debug)]
+    struct ServiceConfig {
+        /// The port the server listens on
+        port: u16,
+        timeout_secs: Option<u64>,
+        #[config(nested)]
+        primary: PoolConfig,
+    }
+
+    define_static_config!(
+        BrokenConfig,
+        "PORT" => "http",
+        "PRIMARY_MAX_CONNECTIONS" => "-1"
+    );
+
+    define_static_config!(
+        ValidConfig,
+        "PORT" => "8080",
+        "PRIMARY_URL" => "postgres://localhost/app"
+    );
+
+    #[test]
+    fn test_load_with_defaults() {
+        let config = ServiceConfig::load::<ValidConfig>().unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.timeout_secs, None);
+        assert_eq!(config.primary.url, "postgres://localhost/app");
+        assert_eq!(config.primary.max_connections, 10);
+    }
+
+    #[test]
+    fn test_all_problems_are_reported() {
+        let error = ServiceConfig::load::<BrokenConfig>().unwrap_err();
+        assert_eq!(
+            error.problems(),
+            &[
+                ConfigProblem::Invalid {
+                    variable: "PORT".to_string(),
+                    message: "invalid digit found in string".to_string(),
+                },
+                ConfigProblem::Missing {
+                    variable: "PRIMARY_URL".to_string(),
+                },
+                ConfigProblem::Invalid {
+                    variable: "PRIMARY_MAX_CONNECTIONS".to_string(),
+                    message: "invalid digit found in string".to_string(),
+                },
+            ]
+        );
+    }
+
+    /// Reads the url but fails to read the secret holding the maximum connections.
+    struct UnreadableConfig;
+
+    impl GetConfigVariable for UnreadableConfig {
+        fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+            match variable.as_str() {
+                "URL" => Ok("postgres://localhost/app".to_string()),
+                "MAX_CONNECTIONS" => Err(CruxmontError::new(
+                    "permission denied",
+                    CurxmontErrorStatus::Unknown,
+                )),
+                _ => Err(not_found(&variable, "UnreadableConfig")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_is_not_used_for_an_unreadable_variable() {
+        let error = PoolConfig::load::<UnreadableConfig>().unwrap_err();
+        assert_eq!(
+            error.problems(),
+            &[ConfigProblem::Unavailable {
+                variable: "MAX_CONNECTIONS".to_string(),
+                error: "permission denied".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_env_example() {
+        assert_eq!(
+            ServiceConfig::env_example(),
+            "# The port the server listens on\nPORT=\n# TIMEOUT_SECS=\nPRIMARY_URL=\nPRIMARY_MAX_CONNECTIONS=10\n"
+        );
+    }
+}
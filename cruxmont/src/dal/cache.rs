@@ -0,0 +1,506 @@
+//! Defines the cache of the transactions declared with `#[db_transaction(..., cache(...))]`.
+//!
+//! # Notes
+//! A cached transaction returns the value stored under its key while it is fresh and only runs
+//! its body on a miss, caching the value it returns on `Ok`:
+//!
+//! ```ignore
+//! #[db_transaction(SqlxPostGresDescriptor, GetCount, cache(ttl = "30s", key = format!("count:{}", id), tags = ["counts"]))]
+//! async fn get_count(id: i32, pool: &Pool<Postgres>) -> i32 { ... }
+//!
+//! #[db_transaction(SqlxPostGresDescriptor, IncreaseCount, invalidates(tags = ["counts"]))]
+//! async fn increase_count(id: i32, pool: &Pool<Postgres>) -> () { ... }
+//!
+//! #[db_transaction(SqlxPostGresDescriptor, ResetCount, invalidates(keys = [GetCount::get_count => format!("count:{}", id)]))]
+//! async fn reset_count(id: i32, pool: &Pool<Postgres>) -> () { ... }
+//! ```
+//!
+//! Keys are namespaced with the name of their transaction, so two transactions using the same
+//! key do not read each other's values and a write names the transaction of each key it
+//! invalidates. A write invalidates the keys and tags it declares once it returns `Ok`. Values are stored as
+//! JSON so they must implement `Serialize` and `DeserializeOwned`. The cache is shared by the
+//! process and defaults to an `LruCache` of 10,000 entries, `set_cache_backend` replaces it.
+//! Values read inside a `UnitOfWork` are cached before it commits, so do not cache transactions
+//! that run in a unit of work that can be rolled back. A value is not cached when its key or one
+//! of its tags is invalidated while the body runs, as it may have been read before the write.
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::Span;
+
+/// The number of entries of the default cache.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// Defines the trait for the stores of cached transactions.
+pub trait CacheBackend: Send + Sync {
+    /// Gets a value that has not expired.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the value.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The value as JSON, None if it is missing or expired.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Stores a value.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the value.
+    /// * `value` - The value as JSON.
+    /// * `ttl` - How long the value is fresh.
+    /// * `tags` - The tags invalidating the value.
+    fn insert(&self, key: &str, value: String, ttl: Duration, tags: &[&str]);
+
+    /// Removes a value.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the value.
+    fn invalidate(&self, key: &str);
+
+    /// Removes the values stored with a tag.
+    ///
+    /// # Arguments
+    /// * `tag` - The tag of the values.
+    fn invalidate_tag(&self, tag: &str);
+}
+
+struct LruEntry {
+    value: String,
+    expires: Instant,
+    tags: Vec<String>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<String, LruEntry>,
+    // the keys by the tick they were last used at, the first is the least recently used
+    order: BTreeMap<u64, String>,
+    tags: HashMap<String, HashSet<String>>,
+    tick: u64,
+}
+
+impl LruState {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+            for tag in entry.tags {
+                if let Some(keys) = self.tags.get_mut(&tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.tags.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// An in-process cache evicting the least recently used entry when it is full.
+pub struct LruCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+impl LruCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of entries, at least 1.
+    ///
+    /// # Returns
+    /// * `LruCache` - The cache.
+    pub fn new(capacity: usize) -> LruCache {
+        LruCache {
+            capacity: capacity.max(1),
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// Gets the number of entries, expired or not.
+    ///
+    /// # Returns
+    /// * `usize` - The number of entries.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Checks if the cache has no entries.
+    ///
+    /// # Returns
+    /// * `bool` - True if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl CacheBackend for LruCache {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut state = self.lock();
+        let expires = state.entries.get(key)?.expires;
+        if expires <= Instant::now() {
+            state.remove(key);
+            return None;
+        }
+        let tick = state.next_tick();
+        let entry = state.entries.get_mut(key)?;
+        let previous = std::mem::replace(&mut entry.used, tick);
+        let value = entry.value.clone();
+        state.order.remove(&previous);
+        state.order.insert(tick, key.to_string());
+        Some(value)
+    }
+
+    fn insert(&self, key: &str, value: String, ttl: Duration, tags: &[&str]) {
+        let mut state = self.lock();
+        state.remove(key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.remove(&oldest);
+        }
+        let tick = state.next_tick();
+        for tag in tags {
+            state
+                .tags
+                .entry(tag.to_string())
+                .or_default()
+                .insert(key.to_string());
+        }
+        state.order.insert(tick, key.to_string());
+        state.entries.insert(
+            key.to_string(),
+            LruEntry {
+                value,
+                expires: Instant::now() + ttl,
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                used: tick,
+            },
+        );
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.lock().remove(key);
+    }
+
+    fn invalidate_tag(&self, tag: &str) {
+        let mut state = self.lock();
+        let keys = state.tags.remove(tag).unwrap_or_default();
+        for key in keys {
+            state.remove(&key);
+        }
+    }
+}
+
+static CACHE_BACKEND: LazyLock<RwLock<Arc<dyn CacheBackend>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(LruCache::new(DEFAULT_CACHE_CAPACITY))));
+
+/// Sets the cache used by every cached transaction of the process.
+///
+/// # Arguments
+/// * `backend` - The cache.
+pub fn set_cache_backend(backend: impl CacheBackend + 'static) {
+    *CACHE_BACKEND
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(backend);
+}
+
+/// Gets the cache used by the cached transactions.
+///
+/// # Returns
+/// * `Arc<dyn CacheBackend>` - The cache.
+pub fn cache_backend() -> Arc<dyn CacheBackend> {
+    CACHE_BACKEND
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// The counters behind `CacheStats`.
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Counters {
+        Counters {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static TOTALS: Counters = Counters::new();
+
+// there is an entry per cached or invalidated transaction so the map stays small
+static TRANSACTION_COUNTERS: LazyLock<RwLock<HashMap<String, Arc<Counters>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+fn transaction_counters(name: &str) -> Arc<Counters> {
+    if let Some(counters) = TRANSACTION_COUNTERS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(name)
+    {
+        return counters.clone();
+    }
+    TRANSACTION_COUNTERS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+/// The counts of the cached transactions since the process started.
+///
+/// # Fields
+/// * `hits` - The calls answered from the cache.
+/// * `misses` - The calls that ran their body.
+/// * `invalidations` - The keys and tags invalidated by writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+}
+
+impl CacheStats {
+    /// Gets the counts of the process.
+    ///
+    /// # Returns
+    /// * `CacheStats` - The counts.
+    pub fn current() -> CacheStats {
+        TOTALS.stats()
+    }
+
+    /// Gets the counts of a transaction.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the transaction, such as `GetCount::get_count`.
+    ///
+    /// # Returns
+    /// * `CacheStats` - The counts, the invalidations being the keys of the transaction removed
+    ///   by writes as tags are not tied to a transaction.
+    pub fn of_transaction(name: &str) -> CacheStats {
+        transaction_counters(name).stats()
+    }
+}
+
+// the generations of the keys and tags are bumped by invalidations so a value read before one
+// is not cached after it, a collision between two of them only skips an insert
+const GENERATION_SLOTS: usize = 4096;
+static GENERATIONS: [AtomicU64; GENERATION_SLOTS] = [const { AtomicU64::new(0) }; GENERATION_SLOTS];
+
+fn generation_slot(kind: &str, name: &str) -> &'static AtomicU64 {
+    let mut hasher = DefaultHasher::new();
+    (kind, name).hash(&mut hasher);
+    &GENERATIONS[hasher.finish() as usize % GENERATION_SLOTS]
+}
+
+// generations only grow so the sum changes whenever one of them does
+fn generation(key: &str, tags: &[&str]) -> u64 {
+    tags.iter()
+        .map(|tag| generation_slot("tag", tag))
+        .chain(std::iter::once(generation_slot("key", key)))
+        .fold(0, |sum, slot| sum.wrapping_add(slot.load(Ordering::SeqCst)))
+}
+
+/// Namespaces the key of a cached transaction with the name of the transaction.
+///
+/// # Arguments
+/// * `name` - The name of the transaction, such as `GetCount::get_count`.
+/// * `key` - The key of the call.
+///
+/// # Returns
+/// * `String` - The key the value is stored under in the `CacheBackend`.
+pub fn transaction_key(name: &str, key: &str) -> String {
+    format!("{}:{}", name, key)
+}
+
+/// Answers a call of a cached transaction from the cache or runs it on a miss, used by the
+/// generated transactions. The outcome is recorded as `cache` on the span of the transaction.
+///
+/// # Arguments
+/// * `name` - The name of the transaction, such as `GetCount::get_count`.
+/// * `key` - The key of the call.
+/// * `ttl` - How long the value is fresh.
+/// * `tags` - The tags invalidating the value.
+/// * `run` - The body of the transaction.
+///
+/// # Returns
+/// * `Result<T, E>` - The cached value or the result of the body.
+pub async fn cached<T, E>(
+    name: &'static str,
+    key: String,
+    ttl: Duration,
+    tags: &'static [&'static str],
+    run: impl Future<Output = Result<T, E>>,
+) -> Result<T, E>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let key = transaction_key(name, &key);
+    let counters = transaction_counters(name);
+    // a value that no longer deserializes, after its type changed, is treated as a miss
+    let hit = cache_backend()
+        .get(&key)
+        .and_then(|value| serde_json::from_str::<T>(&value).ok());
+    if let Some(value) = hit {
+        TOTALS.hits.fetch_add(1, Ordering::Relaxed);
+        counters.hits.fetch_add(1, Ordering::Relaxed);
+        Span::current().record("cache", "hit");
+        tracing::debug!(transaction = name, "cache hit");
+        return Ok(value);
+    }
+    TOTALS.misses.fetch_add(1, Ordering::Relaxed);
+    counters.misses.fetch_add(1, Ordering::Relaxed);
+    Span::current().record("cache", "miss");
+    tracing::debug!(transaction = name, "cache miss");
+    let before = generation(&key, tags);
+    let result = run.await;
+    if let Ok(value) = &result {
+        if generation(&key, tags) != before {
+            tracing::debug!(transaction = name, "invalidated while running, not caching");
+            return result;
+        }
+        match serde_json::to_string(value) {
+            Ok(value) => {
+                let backend = cache_backend();
+                backend.insert(&key, value, ttl, tags);
+                // an invalidation between the check and the insert may have missed the value
+                if generation(&key, tags) != before {
+                    backend.invalidate(&key);
+                }
+            }
+            Err(error) => tracing::warn!(transaction = name, %error, "value could not be cached"),
+        }
+    }
+    result
+}
+
+/// Invalidates the keys and tags declared by a write, used by the generated transactions.
+///
+/// # Arguments
+/// * `keys` - The keys to remove with the names of the transactions that cached them.
+/// * `tags` - The tags of the values to remove.
+pub fn invalidate(keys: &[(&str, String)], tags: &[&str]) {
+    // the generations are bumped first so a value being read is either skipped or removed
+    let backend = cache_backend();
+    for (name, key) in keys {
+        let key = transaction_key(name, key);
+        generation_slot("key", &key).fetch_add(1, Ordering::SeqCst);
+        backend.invalidate(&key);
+        transaction_counters(name)
+            .invalidations
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    for tag in tags {
+        generation_slot("tag", tag).fetch_add(1, Ordering::SeqCst);
+        backend.invalidate_tag(tag);
+    }
+    TOTALS
+        .invalidations
+        .fetch_add((keys.len() + tags.len()) as u64, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache_evicts_expires_and_invalidates() {
+        let cache = LruCache::new(2);
+        let ttl = Duration::from_secs(60);
+        cache.insert("a", "1".to_string(), ttl, &["letters"]);
+        cache.insert("b", "2".to_string(), ttl, &[]);
+        // reading a makes b the least recently used
+        assert_eq!(cache.get("a"), Some("1".to_string()));
+        cache.insert("c", "3".to_string(), ttl, &["letters"]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 2);
+
+        cache.invalidate_tag("letters");
+        assert!(cache.is_empty());
+
+        cache.insert("d", "4".to_string(), Duration::ZERO, &[]);
+        assert_eq!(cache.get("d"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_values_invalidated_while_running_are_not_cached() {
+        const NAME: &str = "StaleScore::stale_score";
+        let ttl = Duration::from_secs(60);
+        let stored = || cache_backend().get(&transaction_key(NAME, "score"));
+
+        let result: Result<i32, ()> = cached(NAME, "score".to_string(), ttl, &[], async {
+            invalidate(&[(NAME, "score".to_string())], &[]);
+            Ok(1)
+        })
+        .await;
+        assert_eq!(result, Ok(1));
+        assert_eq!(stored(), None);
+
+        let result: Result<i32, ()> =
+            cached(NAME, "score".to_string(), ttl, &["stale-scores"], async {
+                invalidate(&[], &["stale-scores"]);
+                Ok(2)
+            })
+            .await;
+        assert_eq!(result, Ok(2));
+        assert_eq!(stored(), None);
+
+        let result: Result<i32, ()> =
+            cached(NAME, "score".to_string(), ttl, &["stale-scores"], async {
+                Ok(3)
+            })
+            .await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(stored(), Some("3".to_string()));
+        let result: Result<i32, ()> =
+            cached(NAME, "score".to_string(), ttl, &["stale-scores"], async {
+                Ok(4)
+            })
+            .await;
+        assert_eq!(result, Ok(3));
+
+        assert_eq!(
+            CacheStats::of_transaction(NAME),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                invalidations: 1,
+            }
+        );
+    }
+}
@@ -1 +1,2 @@
+pub mod replicas;
 pub mod sqlx_postgres;
@@ -0,0 +1,494 @@
+//! Defines the read replicas the `read_only` transactions are routed to.
+//!
+//! # Notes
+//! Read replicas are registered against the primary pool they replicate. A transaction marked
+//! `read_only` runs on a healthy replica of its pool parameter, or on the pool itself when it has
+//! no replicas or all of them are ejected:
+//!
+//! ```ignore
+//! #[db_transaction(SqlxPostGresDescriptor, GetCount, read_only)]
+//! async fn get_count(id: i32, pool: &Pool<Postgres>) -> i32 {
+//!     let row: (i32,) = sqlx::query_as("SELECT value FROM counts WHERE id = $1")
+//!         .bind(id)
+//!         .fetch_one(pool)
+//!         .await?;
+//!     Ok(row.0)
+//! }
+//! ```
+//!
+//! The replicas of the `LivePostGresPool` are read from the comma separated `DATABASE_READ_URLS`,
+//! `DB_READ_SELECTION` picks `round_robin` (the default) or `least_connections`. A replica that
+//! fails to hand out a connection, begin a transaction or pass a health check is ejected for 30
+//! seconds and tried again after, the read runs on the primary in the meantime.
+use super::super::transaction::{PgTransaction, TransactionOptions, begin};
+use crate::config::{GetConfigVariable, is_not_found};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// The config variable holding the comma separated URLs of the replicas.
+pub const READ_URLS_VARIABLE: &str = "DATABASE_READ_URLS";
+
+/// The config variable holding the `ReplicaSelection`.
+pub const READ_SELECTION_VARIABLE: &str = "DB_READ_SELECTION";
+
+/// How long a failing replica is left out of the selection by default.
+pub const DEFAULT_EJECTION: Duration = Duration::from_secs(30);
+
+// the ejections are stored as milliseconds since the first use of the replicas
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn now_millis() -> u64 {
+    EPOCH.elapsed().as_millis() as u64
+}
+
+/// How a replica is picked for a read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplicaSelection {
+    /// The healthy replicas take turns.
+    #[default]
+    RoundRobin,
+    /// The healthy replica with the fewest connections in use.
+    LeastConnections,
+}
+
+impl FromStr for ReplicaSelection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value
+            .trim()
+            .to_lowercase()
+            .replace(['-', ' '], "_")
+            .as_str()
+        {
+            "round_robin" => Ok(ReplicaSelection::RoundRobin),
+            "least_connections" => Ok(ReplicaSelection::LeastConnections),
+            other => Err(format!("{} is not a known replica selection", other)),
+        }
+    }
+}
+
+/// A replica and the time it is ejected until, 0 when it is healthy.
+struct Replica {
+    pool: Pool<Postgres>,
+    ejected_until: AtomicU64,
+}
+
+impl Replica {
+    fn is_available(&self, now: u64) -> bool {
+        self.ejected_until.load(Ordering::Relaxed) <= now
+    }
+
+    fn in_use(&self) -> usize {
+        (self.pool.size() as usize).saturating_sub(self.pool.num_idle())
+    }
+}
+
+/// The read replicas of a primary pool.
+pub struct ReadReplicas {
+    replicas: Vec<Replica>,
+    selection: ReplicaSelection,
+    ejection: Duration,
+    next: AtomicUsize,
+}
+
+impl ReadReplicas {
+    /// Creates the replicas from their pools.
+    ///
+    /// # Arguments
+    /// * `pools` - The pools of the replicas.
+    /// * `selection` - How a replica is picked for a read.
+    ///
+    /// # Returns
+    /// * `ReadReplicas` - The replicas, all healthy.
+    pub fn new(pools: Vec<Pool<Postgres>>, selection: ReplicaSelection) -> ReadReplicas {
+        ReadReplicas {
+            replicas: pools
+                .into_iter()
+                .map(|pool| Replica {
+                    pool,
+                    ejected_until: AtomicU64::new(0),
+                })
+                .collect(),
+            selection,
+            ejection: DEFAULT_EJECTION,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets how long a failing replica is left out of the selection.
+    ///
+    /// # Arguments
+    /// * `ejection` - The duration of an ejection.
+    ///
+    /// # Returns
+    /// * `ReadReplicas` - The replicas with the ejection.
+    pub fn with_ejection(mut self, ejection: Duration) -> ReadReplicas {
+        self.ejection = ejection;
+        self
+    }
+
+    /// Creates lazily connected replicas from the `DATABASE_READ_URLS` and `DB_READ_SELECTION`
+    /// config variables. The replicas share the `DB_MAX_CONNECTIONS` and `DB_PASSWORD` of the
+    /// primary, URLs that cannot be parsed are left out. A variable that cannot be read is logged
+    /// and treated as not set.
+    ///
+    /// # Returns
+    /// * `ReadReplicas` - The replicas, empty if `DATABASE_READ_URLS` is not set.
+    pub fn from_config<T: GetConfigVariable>() -> ReadReplicas {
+        let get = |variable: &str| match T::get_config_variable(variable.to_string()) {
+            Ok(value) => Some(value),
+            Err(error) if is_not_found(&error) => None,
+            Err(error) => {
+                tracing::warn!(variable, error = %error.message, "could not read replica config");
+                None
+            }
+        };
+        let selection = match get(READ_SELECTION_VARIABLE) {
+            Some(value) => value.parse().unwrap_or_else(|error| {
+                tracing::warn!(%error, "invalid replica selection, using round_robin");
+                ReplicaSelection::RoundRobin
+            }),
+            None => ReplicaSelection::RoundRobin,
+        };
+        let max_connections = get("DB_MAX_CONNECTIONS")
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .unwrap_or(5);
+        let password = get("DB_PASSWORD");
+        let urls = get(READ_URLS_VARIABLE).unwrap_or_default();
+        let pools = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .enumerate()
+            .filter_map(|(index, url)| match PgConnectOptions::from_str(url) {
+                Ok(mut options) => {
+                    if let Some(password) = &password {
+                        options = options.password(password);
+                    }
+                    Some(
+                        PgPoolOptions::new()
+                            .max_connections(max_connections)
+                            .connect_lazy_with(options),
+                    )
+                }
+                // the URL is left out of the message as it can hold credentials
+                Err(_) => {
+                    tracing::warn!(
+                        replica = index,
+                        variable = READ_URLS_VARIABLE,
+                        "replica is not a postgres URL, skipping it"
+                    );
+                    None
+                }
+            })
+            .collect();
+        ReadReplicas::new(pools, selection)
+    }
+
+    /// Gets the number of replicas, ejected or not.
+    ///
+    /// # Returns
+    /// * `usize` - The number of replicas.
+    pub fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Checks if there are no replicas.
+    ///
+    /// # Returns
+    /// * `bool` - True if there are no replicas.
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    /// Gets the number of replicas that are not ejected.
+    ///
+    /// # Returns
+    /// * `usize` - The number of replicas reads can be routed to.
+    pub fn available(&self) -> usize {
+        let now = now_millis();
+        self.replicas
+            .iter()
+            .filter(|replica| replica.is_available(now))
+            .count()
+    }
+
+    /// Picks a replica for a read.
+    ///
+    /// # Returns
+    /// * `Option<&Pool<Postgres>>` - The pool of the replica, None if all replicas are ejected.
+    pub fn select(&self) -> Option<&Pool<Postgres>> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+        let now = now_millis();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        // starting from the next replica spreads the reads between replicas equally in use
+        let mut available = (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .filter(|replica| replica.is_available(now));
+        let replica = match self.selection {
+            ReplicaSelection::RoundRobin => available.next(),
+            ReplicaSelection::LeastConnections => available.min_by_key(|replica| replica.in_use()),
+        };
+        replica.map(|replica| &replica.pool)
+    }
+
+    fn find(&self, pool: &Pool<Postgres>) -> Option<(usize, &Replica)> {
+        self.replicas
+            .iter()
+            .enumerate()
+            .find(|(_, replica)| std::ptr::eq(&replica.pool, pool))
+    }
+
+    /// Ejects a replica that failed so reads are routed elsewhere until the ejection ends.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool of the replica, as returned by `select`.
+    pub fn report_failure(&self, pool: &Pool<Postgres>) {
+        if let Some((index, replica)) = self.find(pool) {
+            let until = now_millis() + (self.ejection.as_millis() as u64).max(1);
+            if replica.ejected_until.swap(until, Ordering::Relaxed) <= now_millis() {
+                tracing::warn!(
+                    replica = index,
+                    ejection_ms = self.ejection.as_millis() as u64,
+                    "read replica ejected"
+                );
+            }
+        }
+    }
+
+    /// Readmits a replica that answered.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool of the replica, as returned by `select`.
+    pub fn report_success(&self, pool: &Pool<Postgres>) {
+        if let Some((_, replica)) = self.find(pool) {
+            replica.ejected_until.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Runs `SELECT 1` on every replica, ejecting the ones that fail and readmitting the others.
+    pub async fn check_health(&self) {
+        for replica in &self.replicas {
+            match sqlx::query("SELECT 1").execute(&replica.pool).await {
+                Ok(_) => self.report_success(&replica.pool),
+                Err(_) => self.report_failure(&replica.pool),
+            }
+        }
+    }
+
+    /// Spawns a task checking the health of the replicas at an interval.
+    ///
+    /// # Arguments
+    /// * `interval` - The time between two checks.
+    ///
+    /// # Returns
+    /// * `tokio::task::JoinHandle<()>` - The handle of the task, abort it to stop the checks.
+    pub fn spawn_health_checks(&'static self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                self.check_health().await;
+            }
+        })
+    }
+}
+
+// keyed by the address of the primary pool
+static READ_REPLICAS: LazyLock<RwLock<HashMap<usize, &'static ReadReplicas>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+fn key(primary: &Pool<Postgres>) -> usize {
+    primary as *const Pool<Postgres> as usize
+}
+
+/// Registers the read replicas of a primary pool, replacing the ones registered before.
+///
+/// # Arguments
+/// * `primary` - The pool yielded by the handle of the primary.
+/// * `replicas` - The replicas of the primary.
+///
+/// # Returns
+/// * `&'static ReadReplicas` - The registered replicas, to report failures or spawn health checks.
+///
+/// # Notes
+/// The replicas are kept for the rest of the process, register them once at startup.
+pub fn register_read_replicas(
+    primary: &'static Pool<Postgres>,
+    replicas: ReadReplicas,
+) -> &'static ReadReplicas {
+    let replicas: &'static ReadReplicas = Box::leak(Box::new(replicas));
+    READ_REPLICAS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(key(primary), replicas);
+    replicas
+}
+
+/// Gets the read replicas registered for a primary pool.
+///
+/// # Arguments
+/// * `primary` - The pool of the primary.
+///
+/// # Returns
+/// * `Option<&'static ReadReplicas>` - The replicas, None if none were registered.
+pub fn read_replicas(primary: &Pool<Postgres>) -> Option<&'static ReadReplicas> {
+    READ_REPLICAS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&key(primary))
+        .copied()
+}
+
+/// Routes a read to a replica of a pool, used by the `read_only` transactions.
+///
+/// # Arguments
+/// * `primary` - The pool of the primary.
+///
+/// # Returns
+/// * `&Pool<Postgres>` - A healthy replica, the primary if it has none.
+pub fn route_read(primary: &Pool<Postgres>) -> &Pool<Postgres> {
+    read_replicas(primary)
+        .and_then(ReadReplicas::select)
+        .unwrap_or(primary)
+}
+
+/// Routes a read to a replica of a pool that can hand out a connection, used by the `read_only`
+/// transactions that are not atomic. A replica with idle connections is used as is, one without
+/// opens a connection first that the read then reuses. A replica that fails to connect is ejected
+/// and the read runs on the primary.
+///
+/// # Arguments
+/// * `primary` - The pool of the primary.
+///
+/// # Returns
+/// * `&Pool<Postgres>` - A replica that connected, the primary if none did.
+pub async fn route_read_checked(primary: &Pool<Postgres>) -> &Pool<Postgres> {
+    let Some(replicas) = read_replicas(primary) else {
+        return primary;
+    };
+    let Some(replica) = replicas.select() else {
+        return primary;
+    };
+    if replica.num_idle() > 0 {
+        return replica;
+    }
+    // the connection goes back to the idle connections of the replica for the read to use
+    match replica.acquire().await {
+        Ok(_) => {
+            replicas.report_success(replica);
+            replica
+        }
+        Err(_) => {
+            replicas.report_failure(replica);
+            primary
+        }
+    }
+}
+
+/// Begins a transaction on a replica of a pool, used by the atomic `read_only` transactions. A
+/// replica that fails to begin it is ejected and the transaction begins on the primary.
+///
+/// # Arguments
+/// * `primary` - The pool of the primary.
+/// * `options` - The modes of the transaction.
+///
+/// # Returns
+/// * `sqlx::Result<PgTransaction>` - The transaction, an error if the primary could not begin it.
+pub async fn begin_read(
+    primary: &Pool<Postgres>,
+    options: &TransactionOptions,
+) -> sqlx::Result<PgTransaction> {
+    let Some(replicas) = read_replicas(primary) else {
+        return begin(primary, options).await;
+    };
+    let Some(replica) = replicas.select() else {
+        return begin(primary, options).await;
+    };
+    match begin(replica, options).await {
+        Ok(transaction) => Ok(transaction),
+        Err(_) => {
+            replicas.report_failure(replica);
+            begin(primary, options).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::source::{MapConfig, ScopedConfig};
+    use cruxmont_test_utils::pools::unreachable_pool;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_reads_are_routed_to_healthy_replicas() {
+        let primary: &'static Pool<Postgres> = Box::leak(Box::new(unreachable_pool()));
+        assert!(std::ptr::eq(route_read(primary), primary));
+
+        let replicas = register_read_replicas(
+            primary,
+            ReadReplicas::new(
+                vec![unreachable_pool(), unreachable_pool()],
+                ReplicaSelection::RoundRobin,
+            ),
+        );
+        let first = route_read(primary);
+        let second = route_read(primary);
+        assert!(!std::ptr::eq(first, primary));
+        assert!(!std::ptr::eq(first, second));
+
+        replicas.report_failure(first);
+        assert_eq!(replicas.available(), 1);
+        assert!(std::ptr::eq(route_read(primary), second));
+        assert!(std::ptr::eq(route_read(primary), second));
+
+        // both replicas fail to begin and the primary is tried last
+        assert!(
+            begin_read(primary, &TransactionOptions::default())
+                .await
+                .is_err()
+        );
+        assert_eq!(replicas.available(), 0);
+        assert!(std::ptr::eq(route_read(primary), primary));
+
+        replicas.report_success(first);
+        assert!(std::ptr::eq(route_read(primary), first));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_replica_falls_back_to_the_primary() {
+        let primary: &'static Pool<Postgres> = Box::leak(Box::new(unreachable_pool()));
+        let replicas = register_read_replicas(
+            primary,
+            ReadReplicas::new(vec![unreachable_pool()], ReplicaSelection::RoundRobin),
+        );
+
+        assert!(std::ptr::eq(route_read_checked(primary).await, primary));
+        assert_eq!(replicas.available(), 0);
+        // the ejected replica is not tried again
+        assert!(std::ptr::eq(route_read_checked(primary).await, primary));
+    }
+
+    #[tokio::test]
+    async fn test_replicas_from_config() {
+        let config = MapConfig::new()
+            .with(
+                READ_URLS_VARIABLE,
+                "postgres://replica-1/app, not a url, postgres://replica-2/app",
+            )
+            .with(READ_SELECTION_VARIABLE, "least-connections");
+        let replicas =
+            ScopedConfig::sync_scope(Arc::new(config), ReadReplicas::from_config::<ScopedConfig>);
+        assert_eq!(replicas.len(), 2);
+        assert_eq!(replicas.selection, ReplicaSelection::LeastConnections);
+    }
+}
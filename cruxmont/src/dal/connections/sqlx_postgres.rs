@@ -4,7 +4,10 @@
 //! - Establishes a connection pool for a PostgreSQL database using the `sqlx` library.
 //! - Provides the `SqlxPostGresDescriptor` struct to serve as a handle for database-related operations.
 //! - Configures the connection pool using environment variables for flexibility and scalability.
+use super::replicas::{ReadReplicas, register_read_replicas, route_read};
+use crate::config::EnvConfig;
 use sqlx::{Pool, Postgres};
+use std::sync::LazyLock;
 
 /// A descriptor struct used for applying database traits and dependency injection.
 ///
@@ -16,14 +19,101 @@ pub struct SqlxPostGresDescriptor;
 /// A descriptor struct for yielding a live PostGres DB pool
 pub struct LivePostGresPool;
 
-cruxmont_pg_pool_macro::define_pg_pool!(SQLX_POSTGRES_POOL, "DATABASE_URL", "DB_MAX_CONNECTIONS");
+cruxmont_pg_pool_macro::define_pg_pool!(
+    SQLX_POSTGRES_POOL,
+    "DATABASE_URL",
+    "DB_MAX_CONNECTIONS",
+    password = "DB_PASSWORD"
+);
+
+// registered with the primary the first time it is yielded
+static LIVE_READ_REPLICAS: LazyLock<Option<&'static ReadReplicas>> = LazyLock::new(|| {
+    let replicas = ReadReplicas::from_config::<EnvConfig>();
+    (!replicas.is_empty()).then(|| register_read_replicas(&SQLX_POSTGRES_POOL, replicas))
+});
 
 pub trait YieldPostGresPool {
     fn yield_pool() -> &'static Pool<Postgres>;
+
+    /// Yields the pool of a read replica of the primary, see `cruxmont::dal::connections::replicas`.
+    ///
+    /// # Returns
+    /// * `&'static Pool<Postgres>` - A healthy replica, the primary if it has none.
+    fn yield_read_pool() -> &'static Pool<Postgres> {
+        route_read(Self::yield_pool())
+    }
 }
 
 impl YieldPostGresPool for LivePostGresPool {
     fn yield_pool() -> &'static Pool<Postgres> {
+        LazyLock::force(&LIVE_READ_REPLICAS);
         &SQLX_POSTGRES_POOL
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GetConfigVariable, not_found};
+    use crate::errors::CruxmontError;
+    use cruxmont_embedded_pg_test_macro::embedded_pg_test;
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+
+    // the variables of `PASSWORD_POOL`, written once the embedded database is up
+    static VARIABLES: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+    struct PasswordPoolConfig;
+
+    impl GetConfigVariable for PasswordPoolConfig {
+        fn get_config_variable(variable: String) -> Result<String, CruxmontError> {
+            VARIABLES
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(&variable)
+                .cloned()
+                .ok_or_else(|| not_found(&variable, "PasswordPoolConfig"))
+        }
+    }
+
+    cruxmont_pg_pool_macro::define_pg_pool!(
+        PASSWORD_POOL,
+        "URL",
+        "MAX_CONNECTIONS",
+        password = "PASSWORD",
+        config = PasswordPoolConfig
+    );
+
+    #[embedded_pg_test]
+    async fn test_pool_password_is_read_from_its_config() {
+        let pool = TestDbHandle::yield_pool();
+        sqlx::query("ALTER USER postgres PASSWORD 'hunter2'")
+            .execute(pool)
+            .await
+            .unwrap();
+        let options = pool.connect_options();
+        let url = format!(
+            "postgres://{}@{}:{}/{}",
+            options.get_username(),
+            options.get_host(),
+            options.get_port(),
+            options.get_database().unwrap()
+        );
+        VARIABLES
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend([
+                ("URL".to_string(), url),
+                ("MAX_CONNECTIONS".to_string(), "1".to_string()),
+                ("PASSWORD".to_string(), "hunter2".to_string()),
+            ]);
+
+        // the URL has no password so connecting fails unless the configured one is used
+        let one: i32 = sqlx::query_scalar("SELECT 1")
+            .fetch_one(&*PASSWORD_POOL)
+            .await
+            .unwrap();
+        assert_eq!(one, 1);
+        PASSWORD_POOL.close().await;
+    }
+}
@@ -0,0 +1,381 @@
+//! Defines the runtime of the mocks generated by `define_dal_transactions!`.
+//!
+//! # Notes
+//! The DAL traits only have associated functions so a mock cannot be passed as a value. The
+//! generated mock is activated for a future with `run` instead, its state is task local so tests
+//! running in parallel never see each other's mocks:
+//!
+//! ```
+//! use cruxmont::define_dal_transactions;
+//!
+//! define_dal_transactions!(
+//!     mock MockCountDal;
+//!     GetCount => get_count(id: i32) -> i32,
+//! );
+//!
+//! async fn double_count<X: GetCount>() -> sqlx::Result<i32> {
+//!     Ok(X::get_count(1).await? * 2)
+//! }
+//!
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let mock = MockCountDal::new();
+//! mock.get_count.returns(21);
+//!
+//! assert_eq!(mock.run(double_count::<MockCountDal>()).await.unwrap(), 42);
+//! mock.get_count.assert_called_times(1);
+//! assert_eq!(mock.get_count.calls()[0].arg::<i32>(0), Some(&1));
+//! # });
+//! ```
+use std::any::{Any, type_name};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+tokio::task_local! {
+    static ACTIVE_MOCKS: Vec<Arc<dyn Any + Send + Sync>>;
+}
+
+/// Runs a future with a mock active, used by the generated `run` functions.
+///
+/// # Arguments
+/// * `mock` - The mock to activate.
+/// * `future` - The future to run.
+///
+/// # Returns
+/// * `F::Output` - The output of the future.
+pub async fn scope<M, F>(mock: M, future: F) -> F::Output
+where
+    M: Any + Send + Sync,
+    F: Future,
+{
+    let mut mocks = ACTIVE_MOCKS
+        .try_with(|mocks| mocks.clone())
+        .unwrap_or_default();
+    mocks.push(Arc::new(mock));
+    ACTIVE_MOCKS.scope(mocks, future).await
+}
+
+/// Gets the active mock of a type, the innermost one if it is active several times.
+///
+/// # Returns
+/// * `Option<Arc<M>>` - The mock, None outside of its `run`.
+pub fn active<M: Any + Send + Sync>() -> Option<Arc<M>> {
+    ACTIVE_MOCKS
+        .try_with(|mocks| {
+            mocks
+                .iter()
+                .rev()
+                .find_map(|mock| mock.clone().downcast::<M>().ok())
+        })
+        .ok()
+        .flatten()
+}
+
+/// An argument recorded by a mock.
+#[derive(Clone)]
+pub struct MockArg {
+    type_name: &'static str,
+    value: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+impl MockArg {
+    /// Records an owned copy of an argument.
+    ///
+    /// # Arguments
+    /// * `value` - The copy of the argument.
+    ///
+    /// # Returns
+    /// * `MockArg` - The recorded argument.
+    pub fn new<T: Any + Send + Sync>(value: T) -> MockArg {
+        MockArg {
+            type_name: type_name::<T>(),
+            value: Some(Arc::new(value)),
+        }
+    }
+
+    /// Records an argument that cannot be copied, such as a `&mut` transaction.
+    ///
+    /// # Arguments
+    /// * `type_name` - The type of the argument.
+    ///
+    /// # Returns
+    /// * `MockArg` - The recorded argument without a value.
+    pub fn opaque(type_name: &'static str) -> MockArg {
+        MockArg {
+            type_name,
+            value: None,
+        }
+    }
+
+    /// Gets the value of the argument.
+    ///
+    /// # Returns
+    /// * `Option<&T>` - The value, None if it has another type or was not recorded.
+    pub fn value<T: Any>(&self) -> Option<&T> {
+        self.value.as_ref()?.downcast_ref::<T>()
+    }
+}
+
+impl fmt::Debug for MockArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MockArg({})", self.type_name)
+    }
+}
+
+/// The arguments of a call to a mocked function. References are recorded as owned values so
+/// a `&str` is read back as a `String` and a `&Pool<Postgres>` as a `Pool<Postgres>`.
+#[derive(Clone, Debug)]
+pub struct MockCall {
+    args: Vec<MockArg>,
+}
+
+impl MockCall {
+    /// Constructs a call, used by the generated mocks.
+    ///
+    /// # Arguments
+    /// * `args` - The recorded arguments in the order of the parameters.
+    ///
+    /// # Returns
+    /// * `MockCall` - The call.
+    pub fn new(args: Vec<MockArg>) -> MockCall {
+        MockCall { args }
+    }
+
+    /// Gets an argument.
+    ///
+    /// # Arguments
+    /// * `index` - The position of the parameter.
+    ///
+    /// # Returns
+    /// * `Option<&T>` - The argument, None if it has another type or was not recorded.
+    pub fn arg<T: Any>(&self, index: usize) -> Option<&T> {
+        self.args.get(index)?.value::<T>()
+    }
+
+    /// Gets every argument.
+    ///
+    /// # Returns
+    /// * `&[MockArg]` - The arguments in the order of the parameters.
+    pub fn args(&self) -> &[MockArg] {
+        &self.args
+    }
+}
+
+type Responder<R, E> = Box<dyn FnMut(&MockCall) -> Result<R, E> + Send>;
+
+struct MockFunctionState<R, E> {
+    calls: Vec<MockCall>,
+    queued: VecDeque<Result<R, E>>,
+    responder: Option<Responder<R, E>>,
+}
+
+/// The programmed results and recorded calls of a mocked function returning `Result<R, E>`.
+/// Clones share their state.
+pub struct MockFunction<R, E = sqlx::Error> {
+    name: &'static str,
+    state: Arc<Mutex<MockFunctionState<R, E>>>,
+}
+
+impl<R, E> Clone for MockFunction<R, E> {
+    fn clone(&self) -> Self {
+        MockFunction {
+            name: self.name,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<R: Send + 'static, E: Send + 'static> MockFunction<R, E> {
+    /// Constructs a function without programmed results.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the function used in panic messages.
+    ///
+    /// # Returns
+    /// * `MockFunction<R, E>` - The function.
+    pub fn new(name: &'static str) -> MockFunction<R, E> {
+        MockFunction {
+            name,
+            state: Arc::new(Mutex::new(MockFunctionState {
+                calls: Vec::new(),
+                queued: VecDeque::new(),
+                responder: None,
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockFunctionState<R, E>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns a value from every call.
+    ///
+    /// # Arguments
+    /// * `value` - The value returned.
+    pub fn returns(&self, value: R)
+    where
+        R: Clone,
+    {
+        self.returns_with(move |_| Ok(value.clone()));
+    }
+
+    /// Returns a result from the next call only, results queued this way are used up before
+    /// the closure of `returns` or `returns_with` is called.
+    ///
+    /// # Arguments
+    /// * `result` - The result returned.
+    pub fn returns_once(&self, result: Result<R, E>) {
+        self.lock().queued.push_back(result);
+    }
+
+    /// Computes the result of every call from its arguments.
+    ///
+    /// # Arguments
+    /// * `responder` - The closure computing the result.
+    pub fn returns_with(
+        &self,
+        responder: impl FnMut(&MockCall) -> Result<R, E> + Send + 'static,
+    ) {
+        self.lock().responder = Some(Box::new(responder));
+    }
+
+    /// Records a call and computes its result, used by the generated mocks.
+    ///
+    /// # Arguments
+    /// * `call` - The arguments of the call.
+    ///
+    /// # Returns
+    /// * `Result<R, E>` - The programmed result.
+    pub fn call(&self, call: MockCall) -> Result<R, E> {
+        let mut state = self.lock();
+        state.calls.push(call.clone());
+        if let Some(result) = state.queued.pop_front() {
+            return result;
+        }
+        match state.responder.as_mut() {
+            Some(responder) => responder(&call),
+            None => panic!("no result programmed for {}", self.name),
+        }
+    }
+
+    /// Gets the recorded calls.
+    ///
+    /// # Returns
+    /// * `Vec<MockCall>` - The calls in the order they were made.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.lock().calls.clone()
+    }
+
+    /// Gets the number of recorded calls.
+    ///
+    /// # Returns
+    /// * `usize` - The number of calls.
+    pub fn call_count(&self) -> usize {
+        self.lock().calls.len()
+    }
+
+    /// Panics if the function was not called exactly `times` times.
+    ///
+    /// # Arguments
+    /// * `times` - The expected number of calls.
+    pub fn assert_called_times(&self, times: usize) {
+        let count = self.call_count();
+        assert_eq!(
+            count, times,
+            "{} was called {} times, expected {}",
+            self.name, count, times
+        );
+    }
+}
+
+/// Wraps an argument so `record` picks the best way to copy it. Borrowed arguments are recorded
+/// with `ToOwned`, owned ones with `Clone` and anything else by type name only.
+#[doc(hidden)]
+pub struct ArgRecorder<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait RecordBorrowed {
+    fn record(&self) -> MockArg;
+}
+
+impl<T> RecordBorrowed for &&ArgRecorder<'_, &T>
+where
+    T: ToOwned + ?Sized,
+    T::Owned: Send + Sync + 'static,
+{
+    fn record(&self) -> MockArg {
+        MockArg::new((*self.0).to_owned())
+    }
+}
+
+#[doc(hidden)]
+pub trait RecordOwned {
+    fn record(&self) -> MockArg;
+}
+
+impl<T: Clone + Send + Sync + 'static> RecordOwned for &ArgRecorder<'_, T> {
+    fn record(&self) -> MockArg {
+        MockArg::new(self.0.clone())
+    }
+}
+
+#[doc(hidden)]
+pub trait RecordOpaque {
+    fn record(&self) -> MockArg;
+}
+
+impl<T: ?Sized> RecordOpaque for ArgRecorder<'_, T> {
+    fn record(&self) -> MockArg {
+        MockArg::opaque(type_name::<T>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::define_dal_transactions;
+
+    pub struct Transaction;
+
+    define_dal_transactions!(
+        mock MockUserDal;
+        CreateUser => create_user(name: &str, transaction: &mut Transaction) -> i32,
+        DeleteUser => delete_user(id: i32) -> bool,
+    );
+
+    async fn replace_user<X: CreateUser + DeleteUser>(id: i32) -> sqlx::Result<i32> {
+        if !X::delete_user(id).await? {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        X::create_user("replacement", &mut Transaction).await
+    }
+
+    #[tokio::test]
+    async fn test_mock_records_and_returns() {
+        let mock = MockUserDal::new();
+        mock.delete_user.returns_once(Ok(true));
+        mock.delete_user.returns(false);
+        mock.create_user
+            .returns_with(|call| Ok(call.arg::<String>(0).unwrap().len() as i32));
+
+        assert_eq!(mock.run(replace_user::<MockUserDal>(7)).await.unwrap(), 11);
+        assert!(matches!(
+            mock.run(replace_user::<MockUserDal>(8)).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+
+        mock.delete_user.assert_called_times(2);
+        mock.create_user.assert_called_times(1);
+        let calls = mock.delete_user.calls();
+        assert_eq!(calls[1].arg::<i32>(0), Some(&8));
+        assert!(mock.create_user.calls()[0].arg::<Transaction>(1).is_none());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "called outside of MockUserDal::run")]
+    async fn test_mock_outside_of_run() {
+        let _ = MockUserDal::delete_user(1).await;
+    }
+}
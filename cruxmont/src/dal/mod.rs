@@ -1 +1,6 @@
+pub mod cache;
 pub mod connections;
+pub mod mock;
+pub mod retry;
+pub mod transaction;
+pub mod unit_of_work;
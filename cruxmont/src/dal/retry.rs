@@ -0,0 +1,471 @@
+//! Defines the retries of transactions failing with a serialization failure or a deadlock.
+//!
+//! # Notes
+//! Under `SERIALIZABLE` or heavy contention PostgreSQL aborts transactions with SQLSTATE
+//! `40001` or `40P01`, they succeed when run again. `retry` on an atomic `#[db_transaction]`
+//! re-runs the whole transaction, waiting an exponential backoff with jitter between attempts:
+//!
+//! ```ignore
+//! #[db_transaction(
+//!     SqlxPostGresDescriptor,
+//!     IncreaseCount,
+//!     atomic,
+//!     isolation = "serializable",
+//!     retry(max_attempts = 5, base_delay_ms = 10, max_delay_ms = 200)
+//! )]
+//! async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
+//!     sqlx::query("UPDATE counts SET value = value + $1 WHERE id = 1")
+//!         .bind(number)
+//!         .execute(&mut **tx)
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The parameters are cloned for every attempt. Every retry is handed to the registered
+//! `RetryObserver`s, or logged as a `tracing` warning when there are none. When the attempts run
+//! out the error is marked with `RetryableError::retries_exhausted` so it maps to a
+//! `ServiceUnavailable` `CruxmontError`.
+use crate::errors::database::sqlstate;
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::borrow::Cow;
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+static RETRY_OBSERVERS: RwLock<Vec<Arc<dyn RetryObserver>>> = RwLock::new(Vec::new());
+
+/// When and how often a transaction is retried.
+///
+/// # Fields
+/// * `max_attempts` - The number of attempts including the first one.
+/// * `base_delay` - The delay before the first retry, doubled for every following retry.
+/// * `max_delay` - The longest delay between two attempts.
+/// * `sqlstates` - The SQLSTATE codes of the errors that are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub sqlstates: &'static [&'static str],
+}
+
+impl RetryPolicy {
+    /// Three attempts starting with a 10ms delay, retrying serialization failures and deadlocks.
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(1),
+        sqlstates: &[sqlstate::SERIALIZATION_FAILURE, sqlstate::DEADLOCK_DETECTED],
+    };
+
+    /// Checks if an error with a SQLSTATE is retried.
+    ///
+    /// # Arguments
+    /// * `code` - The SQLSTATE of the error.
+    ///
+    /// # Returns
+    /// * `bool` - True if the code is one of the retried codes.
+    pub fn retries(&self, code: &str) -> bool {
+        self.sqlstates.contains(&code)
+    }
+
+    /// Computes the delay before an attempt. The exponential backoff is capped at `max_delay`
+    /// and a random jitter of up to half of it is taken off so retries of concurrent
+    /// transactions are spread out.
+    ///
+    /// # Arguments
+    /// * `attempt` - The attempt about to be made, 2 for the first retry.
+    ///
+    /// # Returns
+    /// * `Duration` - The delay to wait.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(2).min(31);
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        let jitter = RandomState::new().hash_one(attempt) % 1024;
+        backoff.saturating_sub(backoff / 2 * jitter as u32 / 1024)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::DEFAULT
+    }
+}
+
+/// Defines the trait for the errors of retried transactions.
+pub trait RetryableError {
+    /// Gets the SQLSTATE of the error.
+    ///
+    /// # Returns
+    /// * `Option<Cow<'_, str>>` - The code, None if the error did not come from the database.
+    fn sqlstate(&self) -> Option<Cow<'_, str>>;
+
+    /// Marks the error returned when the attempts ran out, the error is kept as is by default.
+    ///
+    /// # Arguments
+    /// * `attempts` - The number of attempts made.
+    ///
+    /// # Returns
+    /// * `Self` - The marked error.
+    fn retries_exhausted(self, attempts: u32) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = attempts;
+        self
+    }
+}
+
+impl RetryableError for sqlx::Error {
+    fn sqlstate(&self) -> Option<Cow<'_, str>> {
+        self.as_database_error()?.code()
+    }
+
+    fn retries_exhausted(self, attempts: u32) -> Self {
+        match self {
+            sqlx::Error::Database(error) => {
+                sqlx::Error::Database(Box::new(RetriesExhausted { attempts, error }))
+            }
+            other => other,
+        }
+    }
+}
+
+/// A database error that was still returned after every attempt of a retried transaction.
+/// It keeps the SQLSTATE, constraint and table of the last error.
+#[derive(Debug)]
+pub struct RetriesExhausted {
+    attempts: u32,
+    error: Box<dyn DatabaseError>,
+}
+
+impl RetriesExhausted {
+    /// Gets the number of attempts made.
+    ///
+    /// # Returns
+    /// * `u32` - The number of attempts.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Gets the error of the last attempt.
+    ///
+    /// # Returns
+    /// * `&dyn DatabaseError` - The error.
+    pub fn last_error(&self) -> &dyn DatabaseError {
+        self.error.as_ref()
+    }
+}
+
+impl fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {} attempts", self.error, self.attempts)
+    }
+}
+
+impl StdError for RetriesExhausted {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.error.as_error())
+    }
+}
+
+impl DatabaseError for RetriesExhausted {
+    fn message(&self) -> &str {
+        self.error.message()
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        self.error.code()
+    }
+
+    fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+        self
+    }
+
+    fn constraint(&self) -> Option<&str> {
+        self.error.constraint()
+    }
+
+    fn table(&self) -> Option<&str> {
+        self.error.table()
+    }
+
+    fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+/// A retry of a transaction, or the last failure when the attempts ran out.
+///
+/// # Fields
+/// * `transaction` - The name of the transaction, such as `IncreaseCount::increase_count`, the same
+///   as the name of its span.
+/// * `attempt` - The attempt that failed.
+/// * `max_attempts` - The number of attempts allowed.
+/// * `sqlstate` - The SQLSTATE of the error.
+/// * `delay` - The delay before the next attempt, None when the attempts ran out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryEvent {
+    pub transaction: &'static str,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub sqlstate: String,
+    pub delay: Option<Duration>,
+}
+
+/// Defines the trait for observing retries, to log them or count them in metrics.
+pub trait RetryObserver: Send + Sync {
+    /// Receives a retry of a transaction.
+    ///
+    /// # Arguments
+    /// * `event` - The retry.
+    fn observe(&self, event: &RetryEvent);
+}
+
+/// Registers an observer that will receive every retry.
+///
+/// # Arguments
+/// * `observer` - The observer to register.
+pub fn register_retry_observer(observer: Arc<dyn RetryObserver>) {
+    RETRY_OBSERVERS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(observer);
+}
+
+/// Removes all registered observers.
+pub fn clear_retry_observers() {
+    RETRY_OBSERVERS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clear();
+}
+
+/// Hands a retry to the registered observers, logging it as a warning if there are none.
+fn observe_retry(event: &RetryEvent) {
+    let observers = RETRY_OBSERVERS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if observers.is_empty() {
+        match event.delay {
+            Some(delay) => tracing::warn!(
+                transaction = event.transaction,
+                attempt = event.attempt,
+                max_attempts = event.max_attempts,
+                sqlstate = event.sqlstate.as_str(),
+                delay_ms = delay.as_millis() as u64,
+                "transaction failed, retrying"
+            ),
+            None => tracing::warn!(
+                transaction = event.transaction,
+                attempt = event.attempt,
+                max_attempts = event.max_attempts,
+                sqlstate = event.sqlstate.as_str(),
+                "transaction failed, the attempts ran out"
+            ),
+        }
+    }
+    for observer in observers.iter() {
+        observer.observe(event);
+    }
+}
+
+/// Runs an attempt until it succeeds, fails with an error that is not retried or the attempts
+/// run out, used by the retried transactions.
+///
+/// # Arguments
+/// * `policy` - When and how often to retry.
+/// * `transaction` - The name of the transaction used in the retry events.
+/// * `attempt` - Builds the future of an attempt.
+///
+/// # Returns
+/// * `Result<T, E>` - The result of the last attempt.
+pub async fn run_with_retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    transaction: &'static str,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    E: RetryableError,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempts = 1;
+    loop {
+        let error = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        let Some(code) = error
+            .sqlstate()
+            .filter(|code| policy.retries(code))
+            .map(Cow::into_owned)
+        else {
+            return Err(error);
+        };
+        let mut event = RetryEvent {
+            transaction,
+            attempt: attempts,
+            max_attempts: policy.max_attempts,
+            sqlstate: code,
+            delay: None,
+        };
+        if attempts >= policy.max_attempts {
+            observe_retry(&event);
+            return Err(error.retries_exhausted(attempts));
+        }
+        attempts += 1;
+        let delay = policy.delay(attempts);
+        event.delay = Some(delay);
+        observe_retry(&event);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{CruxmontError, CurxmontErrorStatus};
+    use std::sync::Mutex;
+
+    #[derive(Debug)]
+    struct SerializationFailure;
+
+    impl fmt::Display for SerializationFailure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "could not serialize access")
+        }
+    }
+
+    impl StdError for SerializationFailure {}
+
+    impl DatabaseError for SerializationFailure {
+        fn message(&self) -> &str {
+            "could not serialize access"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(sqlstate::SERIALIZATION_FAILURE))
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    struct RecordingObserver(Mutex<Vec<RetryEvent>>);
+
+    impl RetryObserver for RecordingObserver {
+        fn observe(&self, event: &RetryEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    const FAST: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(2),
+        ..RetryPolicy::DEFAULT
+    };
+
+    #[tokio::test]
+    async fn test_retries_until_success_or_exhaustion() {
+        let _globals = crate::TEST_GLOBALS.lock().await;
+        let observer = Arc::new(RecordingObserver(Mutex::new(Vec::new())));
+        register_retry_observer(observer.clone());
+
+        let mut calls = 0;
+        let result = run_with_retry(&FAST, "Dal::succeeds", || {
+            calls += 1;
+            let failed = calls < 3;
+            async move {
+                if failed {
+                    return Err(sqlx::Error::Database(Box::new(SerializationFailure)));
+                }
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let result: Result<(), sqlx::Error> = run_with_retry(&FAST, "Dal::fails", || async {
+            Err(sqlx::Error::Database(Box::new(SerializationFailure)))
+        })
+        .await;
+        let error = CruxmontError::from(result.unwrap_err());
+        assert_eq!(error.status, CurxmontErrorStatus::ServiceUnavailable);
+        assert_eq!(
+            error.message,
+            "Transaction failed after 3 attempts because of concurrent updates, please retry"
+        );
+
+        let result: Result<(), sqlx::Error> = run_with_retry(&FAST, "Dal::not_found", || async {
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+
+        let events = observer.0.lock().unwrap().clone();
+        let events = events
+            .iter()
+            .filter(|event| event.transaction.starts_with("Dal::"))
+            .map(|event| (event.transaction, event.attempt, event.delay.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ("Dal::succeeds", 1, true),
+                ("Dal::succeeds", 2, true),
+                ("Dal::fails", 1, true),
+                ("Dal::fails", 2, true),
+                ("Dal::fails", 3, false),
+            ]
+        );
+        clear_retry_observers();
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            ..RetryPolicy::DEFAULT
+        };
+        for attempt in 2..10 {
+            let delay = policy.delay(attempt);
+            let backoff = Duration::from_millis(100 << (attempt - 2)).min(policy.max_delay);
+            assert!(
+                delay <= backoff && delay >= backoff / 2,
+                "attempt {}",
+                attempt
+            );
+        }
+    }
+}
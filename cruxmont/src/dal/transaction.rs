@@ -0,0 +1,159 @@
+//! Defines the SQL transactions begun by `#[db_transaction(..., atomic)]`.
+//!
+//! # Notes
+//! An atomic transaction begins a transaction on the pool parameter of the function and gives
+//! the body a `tx: &mut PgTransaction`. The transaction is committed when the body returns
+//! `Ok`, rolled back when it returns `Err` and rolled back by sqlx when it is dropped during a
+//! panic:
+//!
+//! ```ignore
+//! #[db_transaction(SqlxPostGresDescriptor, IncreaseCount, atomic, isolation = "serializable")]
+//! async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
+//!     sqlx::query("UPDATE counts SET value = value + $1 WHERE id = 1")
+//!         .bind(number)
+//!         .execute(&mut **tx)
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+use sqlx::{Pool, Postgres, Transaction};
+use std::fmt;
+use std::str::FromStr;
+
+/// The transaction given to the body of an atomic transaction.
+pub type PgTransaction = Transaction<'static, Postgres>;
+
+/// The isolation level of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Treated as `ReadCommitted` by PostgreSQL.
+    ReadUncommitted,
+    /// Every statement sees the data committed before it began, the PostgreSQL default.
+    ReadCommitted,
+    /// Every statement sees the data committed before the transaction began.
+    RepeatableRead,
+    /// The transaction behaves as if transactions ran one after the other, failing with a
+    /// serialization error when they cannot.
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// Gets the SQL of the isolation level.
+    ///
+    /// # Returns
+    /// * `&'static str` - The level as written after `ISOLATION LEVEL`.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+impl FromStr for IsolationLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value
+            .trim()
+            .to_lowercase()
+            .replace(['_', '-'], " ")
+            .as_str()
+        {
+            "read uncommitted" => Ok(IsolationLevel::ReadUncommitted),
+            "read committed" => Ok(IsolationLevel::ReadCommitted),
+            "repeatable read" => Ok(IsolationLevel::RepeatableRead),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            other => Err(format!("{} is not a known isolation level", other)),
+        }
+    }
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_sql())
+    }
+}
+
+/// The modes of a transaction, the database defaults are used for the modes left unset.
+///
+/// # Fields
+/// * `isolation` - The isolation level, None for the default of the database.
+/// * `read_only` - Whether the transaction rejects writes.
+/// * `deferrable` - Whether a serializable read only transaction waits for a snapshot that
+///   cannot fail with a serialization error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransactionOptions {
+    pub isolation: Option<IsolationLevel>,
+    pub read_only: bool,
+    pub deferrable: bool,
+}
+
+impl TransactionOptions {
+    /// Builds the statement beginning a transaction with the options.
+    ///
+    /// # Returns
+    /// * `String` - The statement, such as `BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY`.
+    pub fn begin_statement(&self) -> String {
+        let mut modes = Vec::new();
+        if let Some(isolation) = self.isolation {
+            modes.push(format!("ISOLATION LEVEL {}", isolation.as_sql()));
+        }
+        if self.read_only {
+            modes.push("READ ONLY".to_string());
+        }
+        if self.deferrable {
+            modes.push("DEFERRABLE".to_string());
+        }
+        if modes.is_empty() {
+            "BEGIN".to_string()
+        } else {
+            format!("BEGIN {}", modes.join(", "))
+        }
+    }
+}
+
+/// Begins a transaction, used by the atomic transactions.
+///
+/// # Arguments
+/// * `pool` - The pool the connection of the transaction is acquired from.
+/// * `options` - The modes of the transaction.
+///
+/// # Returns
+/// * `sqlx::Result<PgTransaction>` - The transaction, an error if it could not begin.
+pub async fn begin(
+    pool: &Pool<Postgres>,
+    options: &TransactionOptions,
+) -> sqlx::Result<PgTransaction> {
+    if *options == TransactionOptions::default() {
+        pool.begin().await
+    } else {
+        pool.begin_with(options.begin_statement()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_begin_statement() {
+        assert_eq!(TransactionOptions::default().begin_statement(), "BEGIN");
+        let options = TransactionOptions {
+            isolation: Some("serializable".parse().unwrap()),
+            read_only: true,
+            deferrable: true,
+        };
+        assert_eq!(
+            options.begin_statement(),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE"
+        );
+        assert_eq!(
+            "repeatable_read".parse::<IsolationLevel>(),
+            Ok(IsolationLevel::RepeatableRead)
+        );
+        assert!("snapshot".parse::<IsolationLevel>().is_err());
+    }
+}
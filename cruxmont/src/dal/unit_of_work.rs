@@ -0,0 +1,260 @@
+//! Defines the unit of work running several DAL calls in one transaction.
+//!
+//! # Notes
+//! DAL functions taking a generic `PgAcquire` executor accept a `&Pool<Postgres>`, a
+//! `&mut PgConnection`, a `&mut Transaction` or a `&mut UnitOfWork`, so the same function runs
+//! on its own or as part of a unit of work:
+//!
+//! ```ignore
+//! define_dal_transactions!(
+//!     IncreaseCount => increase_count<A: PgAcquire>(number: i32, executor: A) -> (),
+//!     GetCount => get_count<A: PgAcquire>(id: i32, executor: A) -> i32,
+//! );
+//!
+//! #[db_transaction(SqlxPostGresDescriptor, GetCount)]
+//! async fn get_count<A: PgAcquire>(id: i32, executor: A) -> i32 {
+//!     let mut connection = executor.acquire().await?;
+//!     let row: (i32,) = sqlx::query_as("SELECT value FROM counts WHERE id = $1")
+//!         .bind(id)
+//!         .fetch_one(&mut *connection)
+//!         .await?;
+//!     Ok(row.0)
+//! }
+//!
+//! async fn increase_and_get_count<X: IncreaseCount + GetCount, Y: YieldPostGresPool>(
+//! ) -> sqlx::Result<i32> {
+//!     let mut unit = UnitOfWork::begin::<Y>().await?;
+//!     X::increase_count(1, &mut unit).await?;
+//!     let count = X::get_count(1, &mut unit).await?;
+//!     unit.commit().await?;
+//!     Ok(count)
+//! }
+//! ```
+//!
+//! A unit of work dropped without being committed is rolled back. `savepoint` nests a unit of
+//! work that can be rolled back without rolling back its parent.
+use super::connections::sqlx_postgres::YieldPostGresPool;
+use super::transaction::{TransactionOptions, begin};
+use sqlx::pool::PoolConnection;
+use sqlx::{Acquire, PgConnection, Pool, Postgres, Transaction};
+use std::future::Future;
+use std::ops::DerefMut;
+use std::pin::Pin;
+
+/// An executor of PostgreSQL queries: a pool, a connection, a transaction or a unit of work.
+///
+/// # Notes
+/// `sqlx::Acquire` has a lifetime parameter that stops the futures of handlers generic over it
+/// from being proven `Send`, this trait has none so DAL functions can be used in axum handlers.
+pub trait PgAcquire: Send + Sized {
+    /// The connection queries are run on, returned to the pool when dropped if it was acquired.
+    type Connection: DerefMut<Target = PgConnection> + Send;
+
+    /// Acquires the connection of the executor.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<Self::Connection>` - The connection, an error if none could be acquired.
+    fn acquire(self) -> impl Future<Output = sqlx::Result<Self::Connection>> + Send;
+}
+
+impl PgAcquire for &Pool<Postgres> {
+    type Connection = PoolConnection<Postgres>;
+
+    async fn acquire(self) -> sqlx::Result<Self::Connection> {
+        Pool::acquire(self).await
+    }
+}
+
+impl<'t> PgAcquire for &'t mut PgConnection {
+    type Connection = &'t mut PgConnection;
+
+    async fn acquire(self) -> sqlx::Result<Self::Connection> {
+        Ok(self)
+    }
+}
+
+impl<'t> PgAcquire for &'t mut Transaction<'_, Postgres> {
+    type Connection = &'t mut PgConnection;
+
+    async fn acquire(self) -> sqlx::Result<Self::Connection> {
+        Ok(&mut **self)
+    }
+}
+
+impl<'t> PgAcquire for &'t mut UnitOfWork<'_> {
+    type Connection = &'t mut PgConnection;
+
+    async fn acquire(self) -> sqlx::Result<Self::Connection> {
+        Ok(self.connection())
+    }
+}
+
+/// A transaction shared by several DAL calls and committed once.
+pub struct UnitOfWork<'c> {
+    transaction: Transaction<'c, Postgres>,
+}
+
+impl UnitOfWork<'static> {
+    /// Begins a unit of work on the pool of a handle.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<UnitOfWork<'static>>` - The unit of work, an error if it could not begin.
+    pub async fn begin<P: YieldPostGresPool>() -> sqlx::Result<UnitOfWork<'static>> {
+        Self::begin_with::<P>(&TransactionOptions::default()).await
+    }
+
+    /// Begins a unit of work with an isolation level or access mode.
+    ///
+    /// # Arguments
+    /// * `options` - The modes of the transaction.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<UnitOfWork<'static>>` - The unit of work, an error if it could not begin.
+    pub async fn begin_with<P: YieldPostGresPool>(
+        options: &TransactionOptions,
+    ) -> sqlx::Result<UnitOfWork<'static>> {
+        Ok(UnitOfWork {
+            transaction: begin(P::yield_pool(), options).await?,
+        })
+    }
+}
+
+impl<'c> UnitOfWork<'c> {
+    /// Gets the transaction of the unit of work.
+    ///
+    /// # Returns
+    /// * `&mut Transaction<'c, Postgres>` - The transaction.
+    pub fn transaction(&mut self) -> &mut Transaction<'c, Postgres> {
+        &mut self.transaction
+    }
+
+    /// Gets the connection of the unit of work to run queries on directly.
+    ///
+    /// # Returns
+    /// * `&mut PgConnection` - The connection.
+    pub fn connection(&mut self) -> &mut PgConnection {
+        &mut self.transaction
+    }
+
+    /// Begins a nested unit of work with a savepoint. Rolling it back only undoes the work done
+    /// since the savepoint, committing it releases the savepoint into this unit of work.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<UnitOfWork<'_>>` - The nested unit of work.
+    pub async fn savepoint(&mut self) -> sqlx::Result<UnitOfWork<'_>> {
+        Ok(UnitOfWork {
+            transaction: Acquire::begin(&mut self.transaction).await?,
+        })
+    }
+
+    /// Commits the work, or releases the savepoint of a nested unit of work.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<()>` - An error if the commit failed.
+    pub async fn commit(self) -> sqlx::Result<()> {
+        self.transaction.commit().await
+    }
+
+    /// Rolls back the work, or the work since the savepoint of a nested unit of work.
+    ///
+    /// # Returns
+    /// * `sqlx::Result<()>` - An error if the rollback failed.
+    pub async fn rollback(self) -> sqlx::Result<()> {
+        self.transaction.rollback().await
+    }
+}
+
+type AcquireFuture<'t, T> = Pin<Box<dyn Future<Output = sqlx::Result<T>> + Send + 't>>;
+
+impl<'t> Acquire<'t> for &'t mut UnitOfWork<'_> {
+    type Database = Postgres;
+    type Connection = &'t mut PgConnection;
+
+    fn acquire(self) -> AcquireFuture<'t, Self::Connection> {
+        Acquire::acquire(&mut self.transaction)
+    }
+
+    fn begin(self) -> AcquireFuture<'t, Transaction<'t, Postgres>> {
+        Acquire::begin(&mut self.transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cruxmont_embedded_pg_test_macro::embedded_pg_test;
+    use cruxmont_test_utils::pools::unreachable_pool;
+    use sqlx::PgPool;
+    use std::sync::LazyLock;
+
+    struct UnreachablePool;
+
+    impl YieldPostGresPool for UnreachablePool {
+        fn yield_pool() -> &'static Pool<Postgres> {
+            static POOL: LazyLock<PgPool> = LazyLock::new(unreachable_pool);
+            &POOL
+        }
+    }
+
+    async fn count<A: PgAcquire>(executor: A) -> sqlx::Result<i64> {
+        let mut connection = executor.acquire().await?;
+        let row: (i64,) = sqlx::query_as("SELECT 1")
+            .fetch_one(&mut *connection)
+            .await?;
+        Ok(row.0)
+    }
+
+    async fn count_with_savepoint(unit: &mut UnitOfWork<'_>) -> sqlx::Result<i64> {
+        let first = count(&mut *unit).await?;
+        let mut savepoint = unit.savepoint().await?;
+        let second = count(&mut savepoint).await?;
+        savepoint.rollback().await?;
+        Ok(first + second)
+    }
+
+    #[tokio::test]
+    async fn test_unit_of_work_fails_to_begin() {
+        assert!(count(UnreachablePool::yield_pool()).await.is_err());
+        // spawned to check the unit of work can be held across awaits of a handler
+        let result = tokio::spawn(async {
+            let mut unit = UnitOfWork::begin::<UnreachablePool>().await?;
+            let total = count_with_savepoint(&mut unit).await?;
+            unit.commit().await?;
+            Ok::<_, sqlx::Error>(total)
+        })
+        .await
+        .unwrap();
+        assert!(result.is_err());
+    }
+
+    async fn insert_name<A: PgAcquire>(executor: A, name: &str) -> sqlx::Result<()> {
+        let mut connection = executor.acquire().await?;
+        sqlx::query("INSERT INTO names (name) VALUES ($1)")
+            .bind(name)
+            .execute(&mut *connection)
+            .await?;
+        Ok(())
+    }
+
+    #[embedded_pg_test]
+    async fn test_savepoint_rolls_back_without_its_parent() {
+        let pool = TestDbHandle::yield_pool();
+        sqlx::query("CREATE TABLE names (name TEXT NOT NULL)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let mut unit = UnitOfWork::begin::<TestDbHandle>().await.unwrap();
+        insert_name(&mut unit, "parent").await.unwrap();
+        let mut savepoint = unit.savepoint().await.unwrap();
+        insert_name(&mut savepoint, "savepoint").await.unwrap();
+        savepoint.rollback().await.unwrap();
+        unit.commit().await.unwrap();
+
+        let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM names")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        assert_eq!(names, vec![("parent".to_string(),)]);
+    }
+}
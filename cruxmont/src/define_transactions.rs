@@ -1,66 +1,413 @@
 //! Defines the macro around mapping functions to traits for transactions.
 
-#[macro_export]
-macro_rules! define_dal_transactions {
-    (
-        $( $trait:ident => $func_name:ident $(< $($generic:tt),* >)? ($($param:ident : $ptype:ty),*) -> $rtype:ty ),* $(,)?
-    ) => {
-        $(
-            pub trait $trait {
-                fn $func_name $(< $($generic),* >)? ($($param : $ptype),*) -> impl std::future::Future<Output = sqlx::Result<$rtype>> + Send;
+pub use cruxmont_db_tx::define_dal_transactions;
+
+#[cfg(test)]
+mod tests {
+    use cruxmont_db_tx::{db_transaction, db_transactions, define_dal_transactions};
+    use cruxmont_test_utils::pools::unreachable_pool;
+
+    pub struct NewUser {
+        pub name: String,
+    }
+
+    define_dal_transactions!(
+        mock MockUserDal;
+        CountUsers => count_users() -> i64,
+        UserRepo {
+            create(user: NewUser) -> i32,
+            rename(id: i32, name: &str) -> bool,
+        },
+    );
+
+    struct PostgresHandle;
+
+    #[db_transaction(PostgresHandle, CountUsers)]
+    async fn count_users() -> i64 {
+        Ok(2)
+    }
+
+    #[db_transactions(PostgresHandle, UserRepo)]
+    mod user_repo {
+        const FIRST_ID: i32 = 1;
+
+        async fn create(user: NewUser) -> i32 {
+            Ok(FIRST_ID + user.name.len() as i32)
+        }
+
+        async fn rename(id: i32, name: &str) -> bool {
+            Ok(id == FIRST_ID && !name.is_empty())
+        }
+    }
+
+    async fn onboard<X: UserRepo + CountUsers>() -> sqlx::Result<(i32, bool, i64)> {
+        let id = X::create(NewUser {
+            name: "ann".to_string(),
+        })
+        .await?;
+        let renamed = X::rename(1, "anne").await?;
+        Ok((id, renamed, X::count_users().await?))
+    }
+
+    #[tokio::test]
+    async fn test_grouped_traits() {
+        assert_eq!(onboard::<PostgresHandle>().await.unwrap(), (4, true, 2));
+
+        let mock = MockUserDal::new();
+        mock.create.returns(7);
+        mock.rename.returns(false);
+        mock.count_users.returns(0);
+        assert_eq!(
+            mock.run(onboard::<MockUserDal>()).await.unwrap(),
+            (7, false, 0)
+        );
+        assert_eq!(
+            mock.rename.calls()[0].arg::<String>(1).map(String::as_str),
+            Some("anne")
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ClaimError {
+        Taken(String),
+        Database(String),
+    }
+
+    impl From<sqlx::Error> for ClaimError {
+        fn from(error: sqlx::Error) -> Self {
+            ClaimError::Database(error.to_string())
+        }
+    }
+
+    impl crate::dal::retry::RetryableError for ClaimError {
+        fn sqlstate(&self) -> Option<std::borrow::Cow<'_, str>> {
+            None
+        }
+    }
+
+    define_dal_transactions!(
+        mock MockNameDal;
+        NameLookup {
+            /// Finds the first name starting with the prefix
+            first_name<'a>(names: &'a [String], prefix: &str) -> Option<String>,
+            claim<I>(names: I) -> Result<usize, ClaimError>
+                where I: IntoIterator<Item = String> + Send, I::IntoIter: Send,
+        },
+    );
+
+    define_dal_transactions!(
+        Longest => longest<'a>(names: &'a [String]) -> &'a str,
+    );
+
+    #[db_transactions(PostgresHandle, NameLookup)]
+    mod name_lookup {
+        async fn first_name<'a>(names: &'a [String], prefix: &str) -> Option<String> {
+            Ok(names.iter().find(|name| name.starts_with(prefix)).cloned())
+        }
+
+        async fn claim<I>(names: I) -> Result<usize, ClaimError>
+        where
+            I: IntoIterator<Item = String> + Send,
+            I::IntoIter: Send,
+        {
+            let mut claimed = 0;
+            for name in names {
+                if name == "root" {
+                    return Err(ClaimError::Taken(name));
+                }
+                claimed += 1;
             }
-        )*
-    };
-}
+            Ok(claimed)
+        }
+    }
+
+    #[db_transaction(PostgresHandle, Longest)]
+    async fn longest<'a>(names: &'a [String]) -> &'a str {
+        Ok(names
+            .iter()
+            .max_by_key(|name| name.len())
+            .map(String::as_str)
+            .unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_lifetimes_where_clauses_and_error_types() {
+        let names = vec!["ann".to_string(), "bernadette".to_string()];
+        assert_eq!(
+            PostgresHandle::first_name(&names, "be").await.unwrap(),
+            Some("bernadette".to_string())
+        );
+        assert_eq!(PostgresHandle::longest(&names).await.unwrap(), "bernadette");
+        assert_eq!(PostgresHandle::claim(names.clone()).await, Ok(2));
+        assert_eq!(
+            PostgresHandle::claim(vec!["root".to_string()]).await,
+            Err(ClaimError::Taken("root".to_string()))
+        );
+
+        let mock = MockNameDal::new();
+        mock.claim
+            .returns_once(Err(ClaimError::Database("closed".to_string())));
+        assert_eq!(
+            mock.run(MockNameDal::claim(names)).await,
+            Err(ClaimError::Database("closed".to_string()))
+        );
+    }
+
+    define_dal_transactions!(
+        Transfer => transfer(amount: i64, pool: &sqlx::PgPool) -> Result<i64, ClaimError>,
+        CountRows => count_rows(database: &sqlx::Pool<sqlx::Postgres>) -> i64,
+    );
+
+    #[db_transaction(
+        PostgresHandle,
+        Transfer,
+        atomic,
+        isolation = "serializable",
+        retry(max_attempts = 2, base_delay_ms = 1, sqlstates = ["40001"])
+    )]
+    async fn transfer(amount: i64, pool: &sqlx::PgPool) -> Result<i64, ClaimError> {
+        let row: (i64,) =
+            sqlx::query_as("UPDATE accounts SET balance = balance - $1 RETURNING balance")
+                .bind(amount)
+                .fetch_one(&mut **tx)
+                .await?;
+        if row.0 < 0 {
+            return Err(ClaimError::Taken("balance".to_string()));
+        }
+        Ok(row.0)
+    }
+
+    #[db_transaction(
+        PostgresHandle,
+        CountRows,
+        atomic,
+        isolation = "serializable",
+        read_only,
+        deferrable
+    )]
+    async fn count_rows(database: &sqlx::Pool<sqlx::Postgres>) -> i64 {
+        let row: (i64,) = sqlx::query_as("SELECT count(*) FROM accounts")
+            .fetch_one(&mut **tx)
+            .await?;
+        Ok(row.0)
+    }
+
+    #[tokio::test]
+    async fn test_atomic_transaction_fails_to_begin() {
+        let pool = unreachable_pool();
+        assert!(matches!(
+            PostgresHandle::transfer(10, &pool).await,
+            Err(ClaimError::Database(_))
+        ));
+        assert!(PostgresHandle::count_rows(&pool).await.is_err());
+    }
 
-// #[cfg(test)]
-// mod tests {
-
-//     use db_tx::db_transaction;
-//     use std::future::Future;
-
-//     struct TestStruct;
-
-//     trait TestTrait {
-//         fn test_fn() -> impl Future<Output = sqlx::Result<i32>> + Send;
-//     }
-
-//     #[db_transaction(TestStruct, TestTrait)]
-//     async fn test_fn() -> i32 {
-//         Ok(35)
-//     }
-
-//     #[tokio::test]
-//     async fn test_impl_transaction() {
-//         let outcome = TestStruct::test_fn().await;
-//         assert_eq!(outcome.unwrap(), 35);
-//     }
-
-//     #[tokio::test]
-//     async fn test_define_dal_transactions() {
-//         struct NewUser;
-
-//         define_dal_transactions!(
-//             CreateUser => create(user: NewUser) -> i32,
-//             DeleteUser => delete(id: i32) -> bool
-//         );
-
-//         struct PostgresHandle;
-
-//         #[db_transaction(PostgresHandle, DeleteUser)]
-//         async fn delete(_uid: i32) -> bool {
-//             Ok(true)
-//         }
-
-//         #[db_transaction(PostgresHandle, CreateUser)]
-//         async fn create(_user: NewUser) -> i32 {
-//             Ok(1)
-//         }
-//         let new_user = NewUser;
-//         let outcome = PostgresHandle::create(new_user).await.unwrap();
-//         assert_eq!(outcome, 1);
-
-//         let outcome = PostgresHandle::delete(1).await.unwrap();
-//         assert_eq!(outcome, true);
-//     }
-// }
+    #[derive(Clone, Copy)]
+    enum Ending {
+        Return,
+        Fail,
+        Panic,
+    }
+
+    define_dal_transactions!(
+        SetBalance => set_balance(balance: i64, ending: Ending, pool: &sqlx::PgPool) -> String,
+    );
+
+    #[db_transaction(PostgresHandle, SetBalance, atomic, isolation = "repeatable read")]
+    async fn set_balance(balance: i64, ending: Ending, pool: &sqlx::PgPool) -> String {
+        sqlx::query("UPDATE accounts SET balance = $1")
+            .bind(balance)
+            .execute(&mut **tx)
+            .await?;
+        let isolation: (String,) = sqlx::query_as("SHOW transaction_isolation")
+            .fetch_one(&mut **tx)
+            .await?;
+        match ending {
+            Ending::Return => Ok(isolation.0),
+            Ending::Fail => Err(sqlx::Error::RowNotFound),
+            Ending::Panic => panic!("the body panicked"),
+        }
+    }
+
+    async fn balance(pool: &sqlx::PgPool) -> i64 {
+        let row: (i64,) = sqlx::query_as("SELECT balance FROM accounts")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        row.0
+    }
+
+    #[cruxmont_embedded_pg_test_macro::embedded_pg_test]
+    async fn test_atomic_transaction_commits_only_on_ok() {
+        use crate::dal::connections::sqlx_postgres::YieldPostGresPool;
+        let pool = TestDbHandle::yield_pool();
+        sqlx::raw_sql(
+            "CREATE TABLE accounts (balance BIGINT NOT NULL); INSERT INTO accounts VALUES (100);",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let isolation = PostgresHandle::set_balance(50, Ending::Return, pool)
+            .await
+            .unwrap();
+        assert_eq!(isolation, "repeatable read");
+        assert_eq!(balance(pool).await, 50);
+
+        assert!(
+            PostgresHandle::set_balance(10, Ending::Fail, pool)
+                .await
+                .is_err()
+        );
+        assert_eq!(balance(pool).await, 50);
+
+        let panicked = tokio::spawn(PostgresHandle::set_balance(20, Ending::Panic, pool)).await;
+        assert!(panicked.unwrap_err().is_panic());
+        assert_eq!(balance(pool).await, 50);
+    }
+
+    static RENAME_ATTEMPTS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+    define_dal_transactions!(
+        RenameOwner => rename_owner(owner: String, pool: &sqlx::PgPool) -> String,
+    );
+
+    #[db_transaction(
+        PostgresHandle,
+        RenameOwner,
+        atomic,
+        retry(max_attempts = 3, base_delay_ms = 1)
+    )]
+    async fn rename_owner(owner: String, pool: &sqlx::PgPool) -> String {
+        sqlx::query("UPDATE owners SET name = $1")
+            .bind(&owner)
+            .execute(&mut **tx)
+            .await?;
+        let attempt = RENAME_ATTEMPTS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if attempt == 0 {
+            sqlx::query("DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$")
+                .execute(&mut **tx)
+                .await?;
+        }
+        // the parameter is moved out, every attempt runs on its own clone
+        Ok(owner)
+    }
+
+    #[cruxmont_embedded_pg_test_macro::embedded_pg_test]
+    async fn test_retried_transaction_runs_on_cloned_parameters() {
+        use crate::dal::connections::sqlx_postgres::YieldPostGresPool;
+        let pool = TestDbHandle::yield_pool();
+        sqlx::raw_sql(
+            "CREATE TABLE owners (name TEXT NOT NULL); INSERT INTO owners VALUES ('ann');",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let owner = PostgresHandle::rename_owner("bob".to_string(), pool)
+            .await
+            .unwrap();
+        assert_eq!(owner, "bob");
+        assert_eq!(
+            RENAME_ATTEMPTS.load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+        let row: (String,) = sqlx::query_as("SELECT name FROM owners")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(row.0, "bob");
+    }
+
+    define_dal_transactions!(
+        RoutedRead => routed_read(pool: &sqlx::PgPool) -> usize,
+    );
+
+    /// Reads on the pool it is routed to and returns the address of that pool.
+    #[db_transaction(PostgresHandle, RoutedRead, read_only)]
+    async fn routed_read(pool: &sqlx::PgPool) -> usize {
+        let _: i32 = sqlx::query_scalar("SELECT 1").fetch_one(pool).await?;
+        Ok(pool as *const sqlx::PgPool as usize)
+    }
+
+    #[cruxmont_embedded_pg_test_macro::embedded_pg_test]
+    async fn test_read_only_transaction_runs_on_a_replica_or_the_primary() {
+        use crate::dal::connections::replicas::{
+            ReadReplicas, ReplicaSelection, register_read_replicas,
+        };
+        use crate::dal::connections::sqlx_postgres::YieldPostGresPool;
+        let primary = TestDbHandle::yield_pool();
+        let address = primary as *const sqlx::PgPool as usize;
+        let replica = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect_lazy_with((*primary.connect_options()).clone());
+        register_read_replicas(
+            primary,
+            ReadReplicas::new(vec![replica], ReplicaSelection::RoundRobin),
+        );
+        assert_ne!(PostgresHandle::routed_read(primary).await.unwrap(), address);
+
+        // an unreachable replica is ejected and the read runs on the primary
+        let replicas = register_read_replicas(
+            primary,
+            ReadReplicas::new(vec![unreachable_pool()], ReplicaSelection::RoundRobin),
+        );
+        assert_eq!(PostgresHandle::routed_read(primary).await.unwrap(), address);
+        assert_eq!(replicas.available(), 0);
+    }
+
+    static SCORE_LOOKUPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    define_dal_transactions!(
+        CachedScore => cached_score(player: &str) -> i64,
+        CachedRank => cached_rank(player: &str) -> String,
+        ResetScore => reset_score(player: String) -> (),
+    );
+
+    #[db_transaction(
+        PostgresHandle,
+        CachedScore,
+        cache(ttl = "1m", key = format!("score:{}", player), tags = ["scores"])
+    )]
+    async fn cached_score(player: &str) -> i64 {
+        SCORE_LOOKUPS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(player.len() as i64)
+    }
+
+    // the same key as `cached_score`, the keys are namespaced so the values do not collide
+    #[db_transaction(
+        PostgresHandle,
+        CachedRank,
+        cache(ttl = "1m", key = format!("score:{}", player))
+    )]
+    async fn cached_rank(player: &str) -> String {
+        Ok(format!("rank of {}", player))
+    }
+
+    #[db_transaction(PostgresHandle, ResetScore, invalidates(keys = [CachedScore::cached_score => format!("score:{}", player)]))]
+    async fn reset_score(player: String) -> () {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_transaction_until_invalidated() {
+        use crate::dal::cache::CacheStats;
+        use std::sync::atomic::Ordering;
+
+        let hits = CacheStats::current().hits;
+        assert_eq!(PostgresHandle::cached_score("ann").await.unwrap(), 3);
+        assert_eq!(PostgresHandle::cached_score("ann").await.unwrap(), 3);
+        assert_eq!(SCORE_LOOKUPS.load(Ordering::Relaxed), 1);
+        assert!(CacheStats::current().hits > hits);
+        assert_eq!(
+            PostgresHandle::cached_rank("ann").await.unwrap(),
+            "rank of ann"
+        );
+
+        PostgresHandle::reset_score("ann".to_string())
+            .await
+            .unwrap();
+        assert_eq!(PostgresHandle::cached_score("ann").await.unwrap(), 3);
+        assert_eq!(SCORE_LOOKUPS.load(Ordering::Relaxed), 2);
+    }
+}
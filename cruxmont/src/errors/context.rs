@@ -0,0 +1,112 @@
+//! Defines the extension trait for adding context and a status to errors in a `Result`.
+//!
+//! # Notes
+//! The original error is kept as the source of the `CruxmontError` so the full chain is
+//! available to logging while only the message is sent in the HTTP response.
+use super::{CruxmontError, CurxmontErrorStatus};
+use std::error::Error as StdError;
+
+/// Converts the error of a `Result` into a `CruxmontError` with extra diagnostics.
+///
+/// # Example
+/// ```
+/// use cruxmont::errors::{CruxmontError, CurxmontErrorStatus, context::ResultExt};
+///
+/// fn parse_port(port: &str) -> Result<u16, CruxmontError> {
+///     port.parse::<u16>()
+///         .with_status(CurxmontErrorStatus::BadRequest)
+///         .context("parsing the port")
+/// }
+///
+/// assert_eq!(parse_port("abc").unwrap_err().status, CurxmontErrorStatus::BadRequest);
+/// ```
+pub trait ResultExt<T> {
+    /// Adds a line of context to the error.
+    ///
+    /// # Arguments
+    /// * `context` - What was happening when the error occurred.
+    ///
+    /// # Returns
+    /// * `Result<T, CruxmontError>` - The result with the context added to the error.
+    fn context(self, context: impl Into<String>) -> Result<T, CruxmontError>;
+
+    /// Adds a lazily built line of context to the error.
+    ///
+    /// # Arguments
+    /// * `context` - Builds what was happening when the error occurred.
+    ///
+    /// # Returns
+    /// * `Result<T, CruxmontError>` - The result with the context added to the error.
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T, CruxmontError>;
+
+    /// Sets the status of the error.
+    ///
+    /// # Arguments
+    /// * `status` - The status the error should be returned with.
+    ///
+    /// # Returns
+    /// * `Result<T, CruxmontError>` - The result with the status set on the error.
+    fn with_status(self, status: CurxmontErrorStatus) -> Result<T, CruxmontError>;
+}
+
+impl<T, E> ResultExt<T> for Result<T, E>
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn context(self, context: impl Into<String>) -> Result<T, CruxmontError> {
+        self.map_err(|error| CruxmontError::from_error(error).with_context(context))
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T, CruxmontError> {
+        self.map_err(|error| CruxmontError::from_error(error).with_context(context()))
+    }
+
+    fn with_status(self, status: CurxmontErrorStatus) -> Result<T, CruxmontError> {
+        self.map_err(|error| {
+            let mut error = CruxmontError::from_error(error);
+            error.status = status;
+            error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_keeps_source_chain() {
+        let outcome: Result<(), sqlx::Error> = Err(sqlx::Error::RowNotFound);
+        let error = outcome
+            .context("fetching the user")
+            .context("loading the profile")
+            .unwrap_err();
+
+        assert_eq!(error.status, CurxmontErrorStatus::NotFound);
+        assert_eq!(error.message, "Resource not found");
+        assert_eq!(
+            error.context(),
+            ["fetching the user", "loading the profile"]
+        );
+        assert!(StdError::source(&error).is_some());
+
+        let diagnostics = error.diagnostics().to_string();
+        assert!(diagnostics.starts_with("NOT_FOUND (404): Resource not found"));
+        assert!(diagnostics.contains("context: loading the profile"));
+        assert!(diagnostics.contains("caused by: no rows returned"));
+    }
+
+    #[test]
+    fn test_with_status_wraps_foreign_errors() {
+        let error = "abc"
+            .parse::<u16>()
+            .with_status(CurxmontErrorStatus::BadRequest)
+            .unwrap_err();
+        assert_eq!(error.status, CurxmontErrorStatus::BadRequest);
+        assert_eq!(error.message, "invalid digit found in string");
+
+        let serialized = serde_json::to_value(&error).unwrap();
+        assert!(serialized.get("source").is_none());
+        assert!(serialized.get("context").is_none());
+    }
+}
@@ -0,0 +1,288 @@
+//! Maps `sqlx` errors into `CruxmontError` using the Postgres SQLSTATE of the error.
+//!
+//! # Notes
+//! The violated constraint, table and column are carried in the `details` of the error so
+//! handlers can use `?` on DAL calls instead of hand writing `map_err` chains.
+use super::i18n::message_keys;
+use super::{CruxmontError, CurxmontErrorStatus};
+use crate::dal::retry::{RetriesExhausted, RetryableError};
+use serde::{Deserialize, Serialize};
+use sqlx::error::DatabaseError;
+use sqlx::postgres::PgDatabaseError;
+use std::borrow::Cow;
+
+/// The SQLSTATE codes that are mapped to a specific error status.
+pub mod sqlstate {
+    pub const NOT_NULL_VIOLATION: &str = "23502";
+    pub const FOREIGN_KEY_VIOLATION: &str = "23503";
+    pub const UNIQUE_VIOLATION: &str = "23505";
+    pub const CHECK_VIOLATION: &str = "23514";
+    pub const EXCLUSION_VIOLATION: &str = "23P01";
+    pub const SERIALIZATION_FAILURE: &str = "40001";
+    pub const DEADLOCK_DETECTED: &str = "40P01";
+    pub const QUERY_CANCELED: &str = "57014";
+}
+
+/// The structured details of an error returned by the database.
+///
+/// # Fields
+/// * `sqlstate` - The SQLSTATE code of the error.
+/// * `constraint` - The name of the violated constraint, if any.
+/// * `table` - The name of the affected table, if any.
+/// * `column` - The name of the affected column, if any.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DatabaseErrorDetails {
+    pub sqlstate: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+}
+
+impl DatabaseErrorDetails {
+    /// Extracts the details from a database error.
+    ///
+    /// # Arguments
+    /// * `error` - The error returned by the database.
+    ///
+    /// # Returns
+    /// * `DatabaseErrorDetails` - The extracted details.
+    pub fn from_database_error(error: &dyn DatabaseError) -> DatabaseErrorDetails {
+        if let Some(exhausted) = error.try_downcast_ref::<RetriesExhausted>() {
+            return DatabaseErrorDetails::from_database_error(exhausted.last_error());
+        }
+        let column = error
+            .try_downcast_ref::<PgDatabaseError>()
+            .and_then(|pg_error| pg_error.column())
+            .map(str::to_string);
+        DatabaseErrorDetails {
+            sqlstate: error
+                .code()
+                .map(|code| code.into_owned())
+                .unwrap_or_default(),
+            constraint: error.constraint().map(str::to_string),
+            table: error.table().map(str::to_string),
+            column,
+        }
+    }
+}
+
+/// Maps a SQLSTATE code to the message key and status of the error.
+///
+/// # Arguments
+/// * `code` - The SQLSTATE code of the error.
+///
+/// # Returns
+/// * `Option<(&'static str, CurxmontErrorStatus)>` - None if the code is not mapped.
+fn map_sqlstate(code: &str) -> Option<(&'static str, CurxmontErrorStatus)> {
+    let mapped = match code {
+        sqlstate::UNIQUE_VIOLATION => {
+            (message_keys::DUPLICATE_ENTRY, CurxmontErrorStatus::Conflict)
+        }
+        sqlstate::FOREIGN_KEY_VIOLATION => (
+            message_keys::FOREIGN_KEY_VIOLATION,
+            CurxmontErrorStatus::BadRequest,
+        ),
+        sqlstate::NOT_NULL_VIOLATION => (
+            message_keys::NOT_NULL_VIOLATION,
+            CurxmontErrorStatus::BadRequest,
+        ),
+        sqlstate::CHECK_VIOLATION => (
+            message_keys::CHECK_VIOLATION,
+            CurxmontErrorStatus::BadRequest,
+        ),
+        sqlstate::EXCLUSION_VIOLATION => (
+            message_keys::EXCLUSION_VIOLATION,
+            CurxmontErrorStatus::Conflict,
+        ),
+        sqlstate::SERIALIZATION_FAILURE => (
+            message_keys::SERIALIZATION_FAILURE,
+            CurxmontErrorStatus::Conflict,
+        ),
+        sqlstate::DEADLOCK_DETECTED => (
+            message_keys::DEADLOCK_DETECTED,
+            CurxmontErrorStatus::Conflict,
+        ),
+        sqlstate::QUERY_CANCELED => (
+            message_keys::QUERY_CANCELED,
+            CurxmontErrorStatus::ServiceUnavailable,
+        ),
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+impl From<sqlx::Error> for CruxmontError {
+    fn from(error: sqlx::Error) -> Self {
+        let mapped = match &error {
+            sqlx::Error::RowNotFound => {
+                CruxmontError::from_key(message_keys::NOT_FOUND, CurxmontErrorStatus::NotFound)
+            }
+            sqlx::Error::PoolTimedOut => CruxmontError::from_key(
+                message_keys::POOL_TIMED_OUT,
+                CurxmontErrorStatus::ServiceUnavailable,
+            ),
+            sqlx::Error::PoolClosed => CruxmontError::from_key(
+                message_keys::POOL_CLOSED,
+                CurxmontErrorStatus::ServiceUnavailable,
+            ),
+            sqlx::Error::Database(db_err) => {
+                let details = DatabaseErrorDetails::from_database_error(db_err.as_ref());
+                let exhausted = db_err.try_downcast_ref::<RetriesExhausted>();
+                let error = match (exhausted, map_sqlstate(&details.sqlstate)) {
+                    (Some(exhausted), _) => retries_exhausted_error(exhausted.attempts()),
+                    (None, Some((key, status))) => CruxmontError::from_key(key, status),
+                    (None, None) => CruxmontError::new(
+                        format!("Database error: {}", db_err),
+                        CurxmontErrorStatus::Unknown,
+                    ),
+                };
+                match serde_json::to_value(details) {
+                    Ok(details) => error.with_details(details),
+                    Err(_) => error,
+                }
+            }
+            _ => CruxmontError::new(
+                format!("Database error: {}", error),
+                CurxmontErrorStatus::Unknown,
+            ),
+        };
+        mapped.with_source(error)
+    }
+}
+
+/// Constructs the error of a transaction that failed on every attempt.
+fn retries_exhausted_error(attempts: u32) -> CruxmontError {
+    CruxmontError::from_key(
+        message_keys::RETRIES_EXHAUSTED,
+        CurxmontErrorStatus::ServiceUnavailable,
+    )
+    .with_message_arg("attempts", attempts)
+}
+
+impl RetryableError for CruxmontError {
+    fn sqlstate(&self) -> Option<Cow<'_, str>> {
+        let code = self.details.as_ref()?.get("sqlstate")?.as_str()?;
+        Some(Cow::Borrowed(code))
+    }
+
+    fn retries_exhausted(mut self, attempts: u32) -> Self {
+        let mut error = retries_exhausted_error(attempts);
+        error.details = self.details.take();
+        error.trace_id = self.trace_id.take();
+        error.internals.source = self.internals.source.take();
+        error.internals.context = std::mem::take(&mut self.internals.context);
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::error::ErrorKind;
+    use std::borrow::Cow;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct TestDatabaseError {
+        code: &'static str,
+        constraint: Option<&'static str>,
+        table: Option<&'static str>,
+    }
+
+    impl fmt::Display for TestDatabaseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "test database error {}", self.code)
+        }
+    }
+
+    impl std::error::Error for TestDatabaseError {}
+
+    impl DatabaseError for TestDatabaseError {
+        fn message(&self) -> &str {
+            "test database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.constraint
+        }
+
+        fn table(&self) -> Option<&str> {
+            self.table
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(TestDatabaseError {
+            code,
+            constraint: Some("users_username_key"),
+            table: Some("users"),
+        }))
+    }
+
+    #[test]
+    fn test_sqlstate_mapping() {
+        let cases = [
+            ("23505", CurxmontErrorStatus::Conflict),
+            ("23503", CurxmontErrorStatus::BadRequest),
+            ("23502", CurxmontErrorStatus::BadRequest),
+            ("23514", CurxmontErrorStatus::BadRequest),
+            ("23P01", CurxmontErrorStatus::Conflict),
+            ("40001", CurxmontErrorStatus::Conflict),
+            ("40P01", CurxmontErrorStatus::Conflict),
+            ("57014", CurxmontErrorStatus::ServiceUnavailable),
+            ("XX000", CurxmontErrorStatus::Unknown),
+        ];
+        for (code, status) in cases {
+            let error = CruxmontError::from(database_error(code));
+            assert_eq!(error.status, status, "sqlstate {}", code);
+        }
+    }
+
+    #[test]
+    fn test_constraint_details() {
+        let error = CruxmontError::from(database_error("23505"));
+        assert_eq!(error.message, "Duplicate entry");
+        assert_eq!(
+            error.details.as_deref(),
+            Some(&json!({
+                "sqlstate": "23505",
+                "constraint": "users_username_key",
+                "table": "users"
+            }))
+        );
+    }
+
+    #[test]
+    fn test_pool_errors() {
+        let error = CruxmontError::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.status, CurxmontErrorStatus::ServiceUnavailable);
+        let error = CruxmontError::from(sqlx::Error::PoolClosed);
+        assert_eq!(error.status, CurxmontErrorStatus::ServiceUnavailable);
+        let error = CruxmontError::from(sqlx::Error::RowNotFound);
+        assert_eq!(error.status, CurxmontErrorStatus::NotFound);
+    }
+}
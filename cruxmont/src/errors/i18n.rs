@@ -0,0 +1,533 @@
+//! Defines the localization of error messages.
+//!
+//! # Notes
+//! An error can carry a message key and interpolation args next to its English message. When a
+//! response is rendered inside the `localize_errors` middleware the key is looked up in the
+//! registered `MessageCatalog` for the locale negotiated from the `Accept-Language` header, then
+//! in the `DefaultCatalog`, and the English message is kept if neither has the key. Templates
+//! use `{name}` placeholders for the args.
+//!
+//! ```no_run
+//! use axum::{Router, middleware, routing::get};
+//! use cruxmont::errors::i18n::{JsonCatalog, localize_errors, set_message_catalog};
+//! use std::sync::Arc;
+//!
+//! // `locales/fr.json` holds `{"cruxmont.database.duplicate_entry": "Entrée en double"}`
+//! set_message_catalog(Arc::new(JsonCatalog::load("locales").unwrap()));
+//! let app: Router = Router::new()
+//!     .route("/", get(|| async { "Bonjour" }))
+//!     .layer(middleware::from_fn(localize_errors));
+//! ```
+use super::{CruxmontError, CurxmontErrorStatus};
+use axum::extract::Request;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::middleware::Next;
+use axum::response::Response as AxumResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// The locale of the messages in the `DefaultCatalog`, used when no requested locale matches.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The keys of the messages emitted by cruxmont itself.
+pub mod message_keys {
+    pub const NOT_FOUND: &str = "cruxmont.database.not_found";
+    pub const POOL_TIMED_OUT: &str = "cruxmont.database.pool_timed_out";
+    pub const POOL_CLOSED: &str = "cruxmont.database.pool_closed";
+    pub const DUPLICATE_ENTRY: &str = "cruxmont.database.duplicate_entry";
+    pub const FOREIGN_KEY_VIOLATION: &str = "cruxmont.database.foreign_key_violation";
+    pub const NOT_NULL_VIOLATION: &str = "cruxmont.database.not_null_violation";
+    pub const CHECK_VIOLATION: &str = "cruxmont.database.check_violation";
+    pub const EXCLUSION_VIOLATION: &str = "cruxmont.database.exclusion_violation";
+    pub const SERIALIZATION_FAILURE: &str = "cruxmont.database.serialization_failure";
+    pub const DEADLOCK_DETECTED: &str = "cruxmont.database.deadlock_detected";
+    pub const RETRIES_EXHAUSTED: &str = "cruxmont.database.retries_exhausted";
+    pub const QUERY_CANCELED: &str = "cruxmont.database.query_canceled";
+    pub const VALIDATION_FAILED: &str = "cruxmont.validation.failed";
+    pub const UPSTREAM_TIMED_OUT: &str = "cruxmont.upstream.timed_out";
+    pub const UPSTREAM_UNAVAILABLE: &str = "cruxmont.upstream.unavailable";
+    pub const UPSTREAM_INVALID_RESPONSE: &str = "cruxmont.upstream.invalid_response";
+    pub const REDACTED: &str = "cruxmont.error.redacted";
+    pub const REDACTED_INCIDENT: &str = "cruxmont.error.redacted_incident";
+    pub const CONFIG_NOT_FOUND: &str = "cruxmont.config.not_found";
+}
+
+/// The English messages of the `DefaultCatalog`.
+const DEFAULT_MESSAGES: &[(&str, &str)] = &[
+    (message_keys::NOT_FOUND, "Resource not found"),
+    (
+        message_keys::POOL_TIMED_OUT,
+        "Timed out waiting for a database connection",
+    ),
+    (
+        message_keys::POOL_CLOSED,
+        "Database connection pool is closed",
+    ),
+    (message_keys::DUPLICATE_ENTRY, "Duplicate entry"),
+    (
+        message_keys::FOREIGN_KEY_VIOLATION,
+        "Foreign key constraint violation",
+    ),
+    (
+        message_keys::NOT_NULL_VIOLATION,
+        "Not null constraint violation",
+    ),
+    (message_keys::CHECK_VIOLATION, "Check constraint violation"),
+    (
+        message_keys::EXCLUSION_VIOLATION,
+        "Exclusion constraint violation",
+    ),
+    (
+        message_keys::SERIALIZATION_FAILURE,
+        "Transaction could not be serialized, please retry",
+    ),
+    (
+        message_keys::DEADLOCK_DETECTED,
+        "Deadlock detected, please retry",
+    ),
+    (
+        message_keys::RETRIES_EXHAUSTED,
+        "Transaction failed after {attempts} attempts because of concurrent updates, please retry",
+    ),
+    (
+        message_keys::QUERY_CANCELED,
+        "Query was canceled or timed out",
+    ),
+    (message_keys::VALIDATION_FAILED, "Validation failed"),
+    (
+        message_keys::UPSTREAM_TIMED_OUT,
+        "Upstream service timed out",
+    ),
+    (
+        message_keys::UPSTREAM_UNAVAILABLE,
+        "Upstream service is unavailable",
+    ),
+    (
+        message_keys::UPSTREAM_INVALID_RESPONSE,
+        "Invalid response from upstream service",
+    ),
+    (message_keys::REDACTED, super::REDACTED_MESSAGE),
+    (
+        message_keys::REDACTED_INCIDENT,
+        "An internal error occurred (incident: {incident_id})",
+    ),
+    (
+        message_keys::CONFIG_NOT_FOUND,
+        "{variable} not found in {location}",
+    ),
+];
+
+/// The key of a message and the values of its placeholders.
+///
+/// # Fields
+/// * `key` - The key of the message in the catalogs.
+/// * `args` - The values of the `{name}` placeholders of the message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageKey {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub args: Map<String, Value>,
+}
+
+tokio::task_local! {
+    static REQUEST_LOCALE: String;
+}
+
+static MESSAGE_CATALOG: RwLock<Option<Arc<dyn MessageCatalog>>> = RwLock::new(None);
+
+/// Defines the trait for loading translated message templates. Implement it to back the
+/// translations with Fluent bundles, a database or any other source.
+pub trait MessageCatalog: Send + Sync {
+    /// Gets the template of a message.
+    ///
+    /// # Arguments
+    /// * `locale` - The locale of the message such as `fr` or `pt-BR`.
+    /// * `key` - The key of the message.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The template, None if the catalog does not have the message.
+    fn template(&self, locale: &str, key: &str) -> Option<String>;
+
+    /// Gets the locales the catalog has messages for.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The available locales.
+    fn locales(&self) -> Vec<String>;
+}
+
+/// The English messages emitted by cruxmont itself.
+pub struct DefaultCatalog;
+
+impl MessageCatalog for DefaultCatalog {
+    fn template(&self, locale: &str, key: &str) -> Option<String> {
+        if locale != DEFAULT_LOCALE {
+            return None;
+        }
+        DEFAULT_MESSAGES
+            .iter()
+            .find(|(message_key, _)| *message_key == key)
+            .map(|(_, template)| template.to_string())
+    }
+
+    fn locales(&self) -> Vec<String> {
+        vec![DEFAULT_LOCALE.to_string()]
+    }
+}
+
+/// A catalog of messages loaded from JSON files holding a flat object of keys to templates.
+#[derive(Debug, Clone, Default)]
+pub struct JsonCatalog {
+    messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl JsonCatalog {
+    /// Loads every `<locale>.json` file in a directory.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory holding the message files.
+    ///
+    /// # Returns
+    /// * `Result<JsonCatalog, CruxmontError>` - The catalog or the error if a file could not
+    ///   be read or parsed.
+    pub fn load(dir: impl AsRef<Path>) -> Result<JsonCatalog, CruxmontError> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir).map_err(|error| {
+            CruxmontError::unknown(format!(
+                "Failed to read message catalog {}: {}",
+                dir.display(),
+                error
+            ))
+        })?;
+        let mut catalog = JsonCatalog::default();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let messages = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|contents| {
+                    serde_json::from_str::<HashMap<String, String>>(&contents)
+                        .map_err(|error| error.to_string())
+                })
+                .map_err(|error| {
+                    CruxmontError::unknown(format!(
+                        "Failed to load messages from {}: {}",
+                        path.display(),
+                        error
+                    ))
+                })?;
+            catalog.insert(locale, messages);
+        }
+        Ok(catalog)
+    }
+
+    /// Adds the messages of a locale to the catalog.
+    ///
+    /// # Arguments
+    /// * `locale` - The locale of the messages.
+    /// * `messages` - The templates by message key.
+    pub fn insert(&mut self, locale: &str, messages: HashMap<String, String>) {
+        self.messages
+            .entry(locale.to_lowercase())
+            .or_default()
+            .extend(messages);
+    }
+}
+
+impl MessageCatalog for JsonCatalog {
+    fn template(&self, locale: &str, key: &str) -> Option<String> {
+        self.messages
+            .get(&locale.to_lowercase())
+            .and_then(|messages| messages.get(key))
+            .cloned()
+    }
+
+    fn locales(&self) -> Vec<String> {
+        self.messages.keys().cloned().collect()
+    }
+}
+
+/// Sets the catalog used to localize error messages.
+///
+/// # Arguments
+/// * `catalog` - The catalog to use.
+pub fn set_message_catalog(catalog: Arc<dyn MessageCatalog>) {
+    *MESSAGE_CATALOG
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(catalog);
+}
+
+/// Removes the catalog so only the `DefaultCatalog` is used.
+pub fn clear_message_catalog() {
+    *MESSAGE_CATALOG
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+fn registered_catalog() -> Option<Arc<dyn MessageCatalog>> {
+    MESSAGE_CATALOG
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Replaces the `{name}` placeholders of a template with the args.
+///
+/// # Arguments
+/// * `template` - The template of the message.
+/// * `args` - The values of the placeholders.
+///
+/// # Returns
+/// * `String` - The rendered message.
+pub fn interpolate(template: &str, args: &Map<String, Value>) -> String {
+    let mut message = template.to_string();
+    for (name, value) in args {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        message = message.replace(&format!("{{{}}}", name), &value);
+    }
+    message
+}
+
+/// Renders a message in a locale, falling back to the primary language of the locale and then
+/// to the default locale.
+///
+/// # Arguments
+/// * `locale` - The requested locale.
+/// * `key` - The key of the message.
+/// * `args` - The values of the placeholders.
+///
+/// # Returns
+/// * `Option<String>` - The rendered message, None if no catalog has the key.
+pub fn render_message(locale: &str, key: &str, args: &Map<String, Value>) -> Option<String> {
+    let mut locales = vec![locale.to_string()];
+    if let Some((primary, _)) = locale.split_once('-') {
+        locales.push(primary.to_string());
+    }
+    locales.push(DEFAULT_LOCALE.to_string());
+
+    let catalog = registered_catalog();
+    locales
+        .iter()
+        .find_map(|locale| {
+            catalog
+                .as_ref()
+                .and_then(|catalog| catalog.template(locale, key))
+                .or_else(|| DefaultCatalog.template(locale, key))
+        })
+        .map(|template| interpolate(&template, args))
+}
+
+/// Picks the locale for an `Accept-Language` header.
+///
+/// # Arguments
+/// * `accept_language` - The value of the header such as `fr-CA,fr;q=0.9,en;q=0.8`.
+/// * `available` - The locales there are messages for.
+///
+/// # Returns
+/// * `Option<String>` - The preferred available locale, None if none of them are accepted.
+pub fn negotiate_locale(accept_language: &str, available: &[String]) -> Option<String> {
+    let mut requested = accept_language
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.trim().split(';');
+            let tag = pieces.next()?.trim().to_lowercase();
+            let quality = pieces
+                .find_map(|piece| piece.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect::<Vec<(String, f32)>>();
+    requested.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let available = available
+        .iter()
+        .map(|locale| locale.to_lowercase())
+        .collect::<Vec<String>>();
+    requested.into_iter().find_map(|(tag, _)| {
+        if tag == "*" {
+            return available.first().cloned();
+        }
+        if available.contains(&tag) {
+            return Some(tag);
+        }
+        let primary = tag.split('-').next().unwrap_or(&tag);
+        available
+            .iter()
+            .find(|locale| locale.split('-').next() == Some(primary))
+            .cloned()
+    })
+}
+
+/// Gets the locale negotiated for the request being handled.
+///
+/// # Returns
+/// * `Option<String>` - The locale, None outside of the `localize_errors` middleware.
+pub fn current_locale() -> Option<String> {
+    REQUEST_LOCALE.try_with(|locale| locale.clone()).ok()
+}
+
+/// Runs a future with the locale used to render error messages.
+///
+/// # Arguments
+/// * `locale` - The locale to render messages in.
+/// * `future` - The future to run.
+///
+/// # Returns
+/// * `F::Output` - The output of the future.
+pub async fn with_locale<F: Future>(locale: impl Into<String>, future: F) -> F::Output {
+    REQUEST_LOCALE.scope(locale.into(), future).await
+}
+
+/// Middleware that renders the error responses of a request in the locale negotiated from its
+/// `Accept-Language` header. Add it with `axum::middleware::from_fn(localize_errors)`.
+pub async fn localize_errors(request: Request, next: Next) -> AxumResponse {
+    let mut available = registered_catalog()
+        .map(|catalog| catalog.locales())
+        .unwrap_or_default();
+    available.push(DEFAULT_LOCALE.to_string());
+    let locale = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| negotiate_locale(header, &available))
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+    with_locale(locale, next.run(request)).await
+}
+
+impl CruxmontError {
+    /// Constructs an error from a message key, the message is the default English message of
+    /// the key or the key itself if it is not in the `DefaultCatalog`.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the message.
+    /// * `status` - The status of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error.
+    pub fn from_key(key: &str, status: CurxmontErrorStatus) -> CruxmontError {
+        let message = DefaultCatalog
+            .template(DEFAULT_LOCALE, key)
+            .unwrap_or_else(|| key.to_string());
+        CruxmontError::new(message, status).with_message_key(key)
+    }
+
+    /// Attaches the key used to look up the translated message.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the message.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the message key attached.
+    pub fn with_message_key(mut self, key: impl Into<String>) -> CruxmontError {
+        let args = self
+            .message_key
+            .take()
+            .map(|message_key| message_key.args)
+            .unwrap_or_default();
+        self.message_key = Some(Box::new(MessageKey {
+            key: key.into(),
+            args,
+        }));
+        self
+    }
+
+    /// Attaches an arg used to fill a `{name}` placeholder of the message. The English message
+    /// is rendered with the arg straight away, the arg is kept for the translations if the error
+    /// has a message key.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the placeholder.
+    /// * `value` - The value of the placeholder.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the arg attached.
+    pub fn with_message_arg(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> CruxmontError {
+        let mut args = Map::new();
+        args.insert(name.into(), value.into());
+        self.message = interpolate(&self.message, &args);
+        if let Some(message_key) = self.message_key.as_mut() {
+            message_key.args.extend(args);
+        }
+        self
+    }
+
+    /// Renders the message in a locale. The message is unchanged if the error has no message
+    /// key or no catalog has the key.
+    ///
+    /// # Arguments
+    /// * `locale` - The locale to render the message in.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the localized message.
+    pub fn localized(mut self, locale: &str) -> CruxmontError {
+        if let Some(message_key) = &self.message_key
+            && let Some(message) = render_message(locale, &message_key.key, &message_key.args)
+        {
+            self.message = message;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_locale() {
+        let available = vec!["en".to_string(), "fr".to_string(), "pt-BR".to_string()];
+        assert_eq!(
+            negotiate_locale("fr-CA,fr;q=0.9,en;q=0.8", &available),
+            Some("fr".to_string())
+        );
+        assert_eq!(
+            negotiate_locale("de;q=0.9,pt-br", &available),
+            Some("pt-br".to_string())
+        );
+        assert_eq!(negotiate_locale("de", &available), None);
+    }
+
+    #[tokio::test]
+    async fn test_error_is_rendered_in_request_locale() {
+        let mut catalog = JsonCatalog::default();
+        catalog.insert(
+            "es",
+            HashMap::from([(
+                "test.user_missing".to_string(),
+                "No se encontró el usuario {id}".to_string(),
+            )]),
+        );
+        set_message_catalog(Arc::new(catalog));
+
+        let error = CruxmontError::not_found("User {id} not found")
+            .with_message_key("test.user_missing")
+            .with_message_arg("id", 7);
+        assert_eq!(error.message, "User 7 not found");
+        let message = with_locale("es-MX", async {
+            error.localized(&current_locale().unwrap()).message
+        })
+        .await;
+        assert_eq!(message, "No se encontró el usuario 7");
+
+        let error =
+            CruxmontError::from_key(message_keys::DUPLICATE_ENTRY, CurxmontErrorStatus::Conflict);
+        assert_eq!(error.localized("es").message, "Duplicate entry");
+        clear_message_catalog();
+    }
+}
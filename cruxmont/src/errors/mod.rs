@@ -0,0 +1,705 @@
+//! Defines a generic error.
+//!
+//! # Notes
+//! This is currently a placeholder but happy to talk about the error handling more
+pub mod context;
+pub mod database;
+pub mod i18n;
+pub mod problem;
+pub mod rejection;
+pub mod reporter;
+#[cfg(feature = "reqwest")]
+pub mod upstream;
+
+use axum::{
+    Json,
+    http::StatusCode as AxumStatusCode,
+    response::{IntoResponse, Response as AxumResponse},
+};
+use problem::{ErrorResponseFormat, ProblemDetails};
+use reporter::RedactionMode;
+use serde::{Deserialize, Serialize};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error as StdError;
+use std::fmt;
+use thiserror::Error;
+
+/// The response error status for usually a HTTP request.
+#[derive(Error, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum CurxmontErrorStatus {
+    #[error("Requested resource was not found")]
+    NotFound,
+    #[error("You are forbidden to access requested resource.")]
+    Forbidden,
+    #[error("Unknown Internal Error")]
+    Unknown,
+    #[error("Bad Request")]
+    BadRequest,
+    #[error("Conflict")]
+    Conflict,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Service Unavailable")]
+    ServiceUnavailable,
+    #[error("Method Not Allowed")]
+    MethodNotAllowed,
+    #[error("Not Acceptable")]
+    NotAcceptable,
+    #[error("Request Timeout")]
+    RequestTimeout,
+    #[error("Requested resource is no longer available")]
+    Gone,
+    #[error("Precondition Failed")]
+    PreconditionFailed,
+    #[error("Payload Too Large")]
+    PayloadTooLarge,
+    #[error("Unsupported Media Type")]
+    UnsupportedMediaType,
+    #[error("Unprocessable Entity")]
+    UnprocessableEntity,
+    #[error("Locked")]
+    Locked,
+    #[error("Too Many Requests")]
+    TooManyRequests,
+    #[error("Not Implemented")]
+    NotImplemented,
+    #[error("Bad Gateway")]
+    BadGateway,
+    #[error("Gateway Timeout")]
+    GatewayTimeout,
+    #[error("Error status {0}")]
+    Custom(u16),
+}
+
+impl CurxmontErrorStatus {
+    /// Constructs an error status from a numeric code. Error codes without a dedicated
+    /// variant are kept as `Custom` so the status is not lost.
+    ///
+    /// # Arguments
+    /// * `code` - The numeric code representing the error status.
+    ///
+    /// # Returns
+    /// * `CurxmontErrorStatus` - The corresponding error status.
+    pub fn from_code(code: u16) -> CurxmontErrorStatus {
+        match code {
+            404 => CurxmontErrorStatus::NotFound,
+            403 => CurxmontErrorStatus::Forbidden,
+            400 => CurxmontErrorStatus::BadRequest,
+            409 => CurxmontErrorStatus::Conflict,
+            401 => CurxmontErrorStatus::Unauthorized,
+            503 => CurxmontErrorStatus::ServiceUnavailable,
+            405 => CurxmontErrorStatus::MethodNotAllowed,
+            406 => CurxmontErrorStatus::NotAcceptable,
+            408 => CurxmontErrorStatus::RequestTimeout,
+            410 => CurxmontErrorStatus::Gone,
+            412 => CurxmontErrorStatus::PreconditionFailed,
+            413 => CurxmontErrorStatus::PayloadTooLarge,
+            415 => CurxmontErrorStatus::UnsupportedMediaType,
+            422 => CurxmontErrorStatus::UnprocessableEntity,
+            423 => CurxmontErrorStatus::Locked,
+            429 => CurxmontErrorStatus::TooManyRequests,
+            501 => CurxmontErrorStatus::NotImplemented,
+            502 => CurxmontErrorStatus::BadGateway,
+            504 => CurxmontErrorStatus::GatewayTimeout,
+            500 => CurxmontErrorStatus::Unknown,
+            code if (400..=599).contains(&code) => CurxmontErrorStatus::Custom(code),
+            _ => CurxmontErrorStatus::Unknown,
+        }
+    }
+
+    /// Gets the HTTP status code for the error status.
+    ///
+    /// # Returns
+    /// * `u16` - The numeric HTTP status code.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            CurxmontErrorStatus::NotFound => 404,
+            CurxmontErrorStatus::Forbidden => 403,
+            CurxmontErrorStatus::Unknown => 500,
+            CurxmontErrorStatus::BadRequest => 400,
+            CurxmontErrorStatus::Conflict => 409,
+            CurxmontErrorStatus::Unauthorized => 401,
+            CurxmontErrorStatus::ServiceUnavailable => 503,
+            CurxmontErrorStatus::MethodNotAllowed => 405,
+            CurxmontErrorStatus::NotAcceptable => 406,
+            CurxmontErrorStatus::RequestTimeout => 408,
+            CurxmontErrorStatus::Gone => 410,
+            CurxmontErrorStatus::PreconditionFailed => 412,
+            CurxmontErrorStatus::PayloadTooLarge => 413,
+            CurxmontErrorStatus::UnsupportedMediaType => 415,
+            CurxmontErrorStatus::UnprocessableEntity => 422,
+            CurxmontErrorStatus::Locked => 423,
+            CurxmontErrorStatus::TooManyRequests => 429,
+            CurxmontErrorStatus::NotImplemented => 501,
+            CurxmontErrorStatus::BadGateway => 502,
+            CurxmontErrorStatus::GatewayTimeout => 504,
+            CurxmontErrorStatus::Custom(code) => *code,
+        }
+    }
+
+    /// Gets the stable machine readable code for the error status. Clients should match on
+    /// this rather than on the human readable message.
+    ///
+    /// # Returns
+    /// * `&'static str` - The code such as `NOT_FOUND`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CurxmontErrorStatus::NotFound => "NOT_FOUND",
+            CurxmontErrorStatus::Forbidden => "FORBIDDEN",
+            CurxmontErrorStatus::Unknown => "UNKNOWN",
+            CurxmontErrorStatus::BadRequest => "BAD_REQUEST",
+            CurxmontErrorStatus::Conflict => "CONFLICT",
+            CurxmontErrorStatus::Unauthorized => "UNAUTHORIZED",
+            CurxmontErrorStatus::ServiceUnavailable => "SERVICE_UNAVAILABLE",
+            CurxmontErrorStatus::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            CurxmontErrorStatus::NotAcceptable => "NOT_ACCEPTABLE",
+            CurxmontErrorStatus::RequestTimeout => "REQUEST_TIMEOUT",
+            CurxmontErrorStatus::Gone => "GONE",
+            CurxmontErrorStatus::PreconditionFailed => "PRECONDITION_FAILED",
+            CurxmontErrorStatus::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            CurxmontErrorStatus::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            CurxmontErrorStatus::UnprocessableEntity => "UNPROCESSABLE_ENTITY",
+            CurxmontErrorStatus::Locked => "LOCKED",
+            CurxmontErrorStatus::TooManyRequests => "TOO_MANY_REQUESTS",
+            CurxmontErrorStatus::NotImplemented => "NOT_IMPLEMENTED",
+            CurxmontErrorStatus::BadGateway => "BAD_GATEWAY",
+            CurxmontErrorStatus::GatewayTimeout => "GATEWAY_TIMEOUT",
+            CurxmontErrorStatus::Custom(_) => "CUSTOM",
+        }
+    }
+}
+
+/// The message sent in place of the real message of a redacted error, the English message of
+/// `message_keys::REDACTED`.
+pub const REDACTED_MESSAGE: &str = "An internal error occurred";
+
+/// The custom error that Actix web automatically converts to a HTTP response.
+///
+/// # Fields
+/// * `message` - The message of the error.
+/// * `status` - The status of the error.
+/// * `details` - Optional structured details about the error, boxed to keep the error small.
+/// * `trace_id` - Optional ID of the request or trace the error happened in.
+/// * `incident_id` - Optional ID the error was reported under, set for server errors.
+/// * `message_key` - Optional key and args used to translate the message, boxed as well.
+///
+/// # Notes
+/// The source, context and backtrace of the error are internal diagnostics. They are never
+/// serialized or sent in a HTTP response but can be logged through `CruxmontError::diagnostics`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CruxmontError {
+    pub message: String,
+    pub status: CurxmontErrorStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Box<serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incident_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_key: Option<Box<i18n::MessageKey>>,
+    #[serde(skip)]
+    internals: Box<ErrorInternals>,
+}
+
+/// The internal diagnostics of a `CruxmontError`, boxed to keep the error small.
+#[derive(Debug, Default)]
+struct ErrorInternals {
+    source: Option<Box<dyn StdError + Send + Sync>>,
+    context: Vec<String>,
+    backtrace: Option<Backtrace>,
+}
+
+impl CruxmontError {
+    /// Constructs a new error.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    /// * `status` - The status of the error.
+    ///
+    /// # Returns
+    /// * `CustomError` - The new error.
+    pub fn new(message: impl Into<String>, status: CurxmontErrorStatus) -> CruxmontError {
+        CruxmontError {
+            message: message.into(),
+            status,
+            details: None,
+            trace_id: None,
+            incident_id: None,
+            message_key: None,
+            internals: Box::new(ErrorInternals {
+                source: None,
+                context: Vec::new(),
+                backtrace: capture_backtrace(),
+            }),
+        }
+    }
+
+    /// Constructs a new error with NotFound status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with NotFound status.
+    pub fn not_found(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::NotFound)
+    }
+
+    /// Constructs a new error with Forbidden status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Forbidden status.
+    pub fn forbidden(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Forbidden)
+    }
+
+    /// Constructs a new error with Unknown status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Unknown status.
+    pub fn unknown(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Unknown)
+    }
+
+    /// Constructs a new error with BadRequest status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with BadRequest status.
+    pub fn bad_request(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::BadRequest)
+    }
+
+    /// Constructs a new error with Conflict status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Conflict status.
+    pub fn conflict(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Conflict)
+    }
+
+    /// Constructs a new error with Unauthorized status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Unauthorized status.
+    pub fn unauthorized(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Unauthorized)
+    }
+
+    /// Constructs a new error with ServiceUnavailable status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with ServiceUnavailable status.
+    pub fn service_unavailable(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::ServiceUnavailable)
+    }
+
+    /// Constructs a new error with MethodNotAllowed status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with MethodNotAllowed status.
+    pub fn method_not_allowed(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::MethodNotAllowed)
+    }
+
+    /// Constructs a new error with NotAcceptable status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with NotAcceptable status.
+    pub fn not_acceptable(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::NotAcceptable)
+    }
+
+    /// Constructs a new error with RequestTimeout status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with RequestTimeout status.
+    pub fn request_timeout(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::RequestTimeout)
+    }
+
+    /// Constructs a new error with Gone status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Gone status.
+    pub fn gone(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Gone)
+    }
+
+    /// Constructs a new error with PreconditionFailed status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with PreconditionFailed status.
+    pub fn precondition_failed(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::PreconditionFailed)
+    }
+
+    /// Constructs a new error with PayloadTooLarge status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with PayloadTooLarge status.
+    pub fn payload_too_large(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::PayloadTooLarge)
+    }
+
+    /// Constructs a new error with UnsupportedMediaType status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with UnsupportedMediaType status.
+    pub fn unsupported_media_type(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::UnsupportedMediaType)
+    }
+
+    /// Constructs a new error with UnprocessableEntity status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with UnprocessableEntity status.
+    pub fn unprocessable_entity(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::UnprocessableEntity)
+    }
+
+    /// Constructs a new error with Locked status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with Locked status.
+    pub fn locked(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::Locked)
+    }
+
+    /// Constructs a new error with TooManyRequests status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with TooManyRequests status.
+    pub fn too_many_requests(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::TooManyRequests)
+    }
+
+    /// Constructs a new error with NotImplemented status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with NotImplemented status.
+    pub fn not_implemented(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::NotImplemented)
+    }
+
+    /// Constructs a new error with BadGateway status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with BadGateway status.
+    pub fn bad_gateway(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::BadGateway)
+    }
+
+    /// Constructs a new error with GatewayTimeout status.
+    ///
+    /// # Arguments
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with GatewayTimeout status.
+    pub fn gateway_timeout(message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::GatewayTimeout)
+    }
+
+    /// Constructs a new error with a custom status code for statuses without a dedicated
+    /// variant.
+    ///
+    /// # Arguments
+    /// * `code` - The HTTP status code of the error.
+    /// * `message` - The message of the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The new error with the corresponding status.
+    pub fn custom(code: u16, message: impl Into<String>) -> CruxmontError {
+        CruxmontError::new(message, CurxmontErrorStatus::from_code(code))
+    }
+
+    /// Attaches structured details to the error.
+    ///
+    /// # Arguments
+    /// * `details` - The details to be sent with the error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the details attached.
+    pub fn with_details(mut self, details: serde_json::Value) -> CruxmontError {
+        self.details = Some(Box::new(details));
+        self
+    }
+
+    /// Attaches the ID of the request or trace the error happened in.
+    ///
+    /// # Arguments
+    /// * `trace_id` - The ID of the request or trace.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the trace ID attached.
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> CruxmontError {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Attaches the error that caused this error. The source is kept for logging and is not
+    /// sent in the HTTP response.
+    ///
+    /// # Arguments
+    /// * `source` - The underlying error.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the source attached.
+    pub fn with_source(mut self, source: impl StdError + Send + Sync + 'static) -> CruxmontError {
+        self.internals.source = Some(Box::new(source));
+        self
+    }
+
+    /// Adds a line of context describing what was happening when the error occurred. The
+    /// context is kept for logging and is not sent in the HTTP response.
+    ///
+    /// # Arguments
+    /// * `context` - The context to add.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error with the context added.
+    pub fn with_context(mut self, context: impl Into<String>) -> CruxmontError {
+        self.internals.context.push(context.into());
+        self
+    }
+
+    /// Wraps any error into a `CruxmontError`. Errors that already are a `CruxmontError` are
+    /// returned unchanged, `sqlx` errors go through the SQLSTATE mapping and everything else
+    /// becomes an `Unknown` error with the original error kept as the source.
+    ///
+    /// # Arguments
+    /// * `error` - The error to wrap.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The wrapped error.
+    pub fn from_error(error: impl StdError + Send + Sync + 'static) -> CruxmontError {
+        let error: Box<dyn StdError + Send + Sync> = Box::new(error);
+        let error = match error.downcast::<CruxmontError>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        match error.downcast::<sqlx::Error>() {
+            Ok(error) => CruxmontError::from(*error),
+            Err(error) => {
+                let mut wrapped = CruxmontError::unknown(error.to_string());
+                wrapped.internals.source = Some(error);
+                wrapped
+            }
+        }
+    }
+
+    /// Gets the context that has been added to the error, oldest first.
+    ///
+    /// # Returns
+    /// * `&[String]` - The context of the error.
+    pub fn context(&self) -> &[String] {
+        &self.internals.context
+    }
+
+    /// Gets the backtrace captured when the error was created. Backtraces are only captured
+    /// when enabled with `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`.
+    ///
+    /// # Returns
+    /// * `Option<&Backtrace>` - The captured backtrace.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.internals.backtrace.as_ref()
+    }
+
+    /// Strips everything but the status, trace ID and incident ID from the error so it can be
+    /// sent to a client without leaking internal details.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The redacted error.
+    pub fn redacted(self) -> CruxmontError {
+        let mut redacted = match &self.incident_id {
+            Some(incident_id) => {
+                CruxmontError::from_key(i18n::message_keys::REDACTED_INCIDENT, self.status)
+                    .with_message_arg("incident_id", incident_id.clone())
+            }
+            None => CruxmontError::from_key(i18n::message_keys::REDACTED, self.status),
+        };
+        redacted.trace_id = self.trace_id;
+        redacted.incident_id = self.incident_id;
+        redacted
+    }
+
+    /// Renders the full internal diagnostics of the error for logging: the status, message,
+    /// context, source chain and backtrace.
+    ///
+    /// # Returns
+    /// * `ErrorDiagnostics` - A displayable report of the error.
+    pub fn diagnostics(&self) -> ErrorDiagnostics<'_> {
+        ErrorDiagnostics { error: self }
+    }
+}
+
+impl fmt::Display for CruxmontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl StdError for CruxmontError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.internals
+            .source
+            .as_deref()
+            .map(|source| source as &(dyn StdError + 'static))
+    }
+}
+
+/// A displayable report of the internal diagnostics of a `CruxmontError`.
+pub struct ErrorDiagnostics<'a> {
+    error: &'a CruxmontError,
+}
+
+impl fmt::Display for ErrorDiagnostics<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}",
+            self.error.status.code(),
+            self.error.status.status_code(),
+            self.error.message
+        )?;
+        for context in self.error.internals.context.iter().rev() {
+            write!(f, "\n  context: {}", context)?;
+        }
+        let mut source = StdError::source(self.error);
+        while let Some(error) = source {
+            write!(f, "\n  caused by: {}", error)?;
+            source = error.source();
+        }
+        if let Some(backtrace) = &self.error.internals.backtrace {
+            write!(f, "\n  backtrace:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Captures a backtrace for a new error when backtraces are enabled by `RUST_BACKTRACE` or
+/// `RUST_LIB_BACKTRACE`, capturing one for every error is too slow to do unconditionally.
+fn capture_backtrace() -> Option<Backtrace> {
+    let backtrace = Backtrace::capture();
+    match backtrace.status() {
+        BacktraceStatus::Captured => Some(backtrace),
+        _ => None,
+    }
+}
+
+impl IntoResponse for CruxmontError {
+    fn into_response(mut self) -> AxumResponse {
+        if self.status.status_code() >= 500 {
+            let incident_id = reporter::report_error(&self);
+            self.incident_id = Some(incident_id);
+            if RedactionMode::current() == RedactionMode::ServerErrors {
+                self = self.redacted();
+            }
+        }
+        if let Some(locale) = i18n::current_locale() {
+            self = self.localized(&locale);
+        }
+        match ErrorResponseFormat::current() {
+            ErrorResponseFormat::Message => {
+                let status_code = AxumStatusCode::from_u16(self.status.status_code())
+                    .unwrap_or(AxumStatusCode::INTERNAL_SERVER_ERROR);
+                (status_code, Json(self.message)).into_response()
+            }
+            ErrorResponseFormat::Problem => ProblemDetails::from(self).into_response(),
+        }
+    }
+}
+
+impl From<CruxmontError> for u32 {
+    fn from(value: CruxmontError) -> Self {
+        value.status.status_code() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code_round_trip() {
+        for code in [
+            400, 401, 403, 404, 405, 406, 408, 409, 410, 412, 413, 415, 418, 422, 423, 429, 500,
+            501, 502, 503, 504, 599,
+        ] {
+            let status = CurxmontErrorStatus::from_code(code);
+            assert_eq!(status.status_code(), code);
+            assert_eq!(u32::from(CruxmontError::new("error", status)), code as u32);
+        }
+        assert_eq!(
+            CurxmontErrorStatus::from_code(418),
+            CurxmontErrorStatus::Custom(418)
+        );
+        assert_eq!(
+            CurxmontErrorStatus::from_code(200),
+            CurxmontErrorStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_custom_status_response() {
+        let response = CruxmontError::custom(418, "I'm a teapot").into_response();
+        assert_eq!(response.status(), AxumStatusCode::IM_A_TEAPOT);
+        let response = CruxmontError::too_many_requests("slow down").into_response();
+        assert_eq!(response.status(), AxumStatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,189 @@
+//! Defines the structured `application/problem+json` error body (RFC 7807).
+//!
+//! # Notes
+//! By default `CruxmontError` is still sent as a bare JSON string so existing clients keep
+//! working. The structured body is opted into globally with `ErrorResponseFormat::set` or per
+//! response by returning a `ProblemDetails` directly.
+use super::i18n::MessageKey;
+use super::{CruxmontError, CurxmontErrorStatus};
+use crate::config::GetConfigVariable;
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode as AxumStatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response as AxumResponse},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The content type of a problem details response.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// The config variable used by `ErrorResponseFormat::from_config`.
+pub const ERROR_FORMAT_VARIABLE: &str = "CRUXMONT_ERROR_FORMAT";
+
+static ERROR_RESPONSE_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// The format of the body `CruxmontError` is converted into for a HTTP response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorResponseFormat {
+    /// The message of the error as a JSON string (the original format).
+    #[default]
+    Message,
+    /// A structured `application/problem+json` body.
+    Problem,
+}
+
+impl ErrorResponseFormat {
+    /// Gets the format currently used for error responses.
+    ///
+    /// # Returns
+    /// * `ErrorResponseFormat` - The format in use for the process.
+    pub fn current() -> ErrorResponseFormat {
+        match ERROR_RESPONSE_FORMAT.load(Ordering::Relaxed) {
+            1 => ErrorResponseFormat::Problem,
+            _ => ErrorResponseFormat::Message,
+        }
+    }
+
+    /// Sets the format used for all error responses in the process.
+    pub fn set(self) {
+        let value = match self {
+            ErrorResponseFormat::Message => 0,
+            ErrorResponseFormat::Problem => 1,
+        };
+        ERROR_RESPONSE_FORMAT.store(value, Ordering::Relaxed);
+    }
+
+    /// Reads the format from the `CRUXMONT_ERROR_FORMAT` config variable. `problem` or
+    /// `problem+json` selects the structured body, anything else (or a missing variable)
+    /// keeps the message format.
+    ///
+    /// # Returns
+    /// * `ErrorResponseFormat` - The format defined in the config.
+    pub fn from_config<T: GetConfigVariable>() -> ErrorResponseFormat {
+        match T::get_config_variable(ERROR_FORMAT_VARIABLE.to_string()) {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "problem" | "problem+json" => ErrorResponseFormat::Problem,
+                _ => ErrorResponseFormat::Message,
+            },
+            Err(_) => ErrorResponseFormat::Message,
+        }
+    }
+}
+
+/// The structured body of an error response.
+///
+/// # Fields
+/// * `problem_type` - URI reference identifying the problem type (`type` in JSON).
+/// * `title` - Short human readable summary of the problem type.
+/// * `status` - The HTTP status code.
+/// * `code` - Stable machine readable code for the error.
+/// * `error_status` - The `CurxmontErrorStatus` of the error.
+/// * `detail` - The human readable message of the error.
+/// * `details` - Optional structured details about the error.
+/// * `trace_id` - Optional ID of the request or trace the error happened in.
+/// * `incident_id` - Optional ID the error was reported under, set for server errors.
+/// * `message_key` - Optional key and args used to look up the translations of the message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProblemDetails {
+    #[serde(rename = "type", default = "default_problem_type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub code: String,
+    pub error_status: CurxmontErrorStatus,
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incident_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_key: Option<MessageKey>,
+}
+
+fn default_problem_type() -> String {
+    "about:blank".to_string()
+}
+
+impl From<CruxmontError> for ProblemDetails {
+    fn from(error: CruxmontError) -> Self {
+        ProblemDetails {
+            problem_type: default_problem_type(),
+            title: error.status.to_string(),
+            status: error.status.status_code(),
+            code: error.status.code().to_string(),
+            error_status: error.status,
+            detail: error.message,
+            details: error.details.map(|details| *details),
+            trace_id: error.trace_id,
+            incident_id: error.incident_id,
+            message_key: error.message_key.map(|message_key| *message_key),
+        }
+    }
+}
+
+impl From<ProblemDetails> for CruxmontError {
+    fn from(problem: ProblemDetails) -> Self {
+        let mut error = CruxmontError::new(problem.detail, problem.error_status);
+        error.details = problem.details.map(Box::new);
+        error.trace_id = problem.trace_id;
+        error.incident_id = problem.incident_id;
+        error.message_key = problem.message_key.map(Box::new);
+        error
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> AxumResponse {
+        let status_code =
+            AxumStatusCode::from_u16(self.status).unwrap_or(AxumStatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status_code, Json(self)).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_problem_details_round_trip() {
+        let error = CruxmontError::conflict("username already exists")
+            .with_details(json!({"field": "username"}))
+            .with_trace_id("abc-123");
+
+        let body = serde_json::to_value(ProblemDetails::from(error)).unwrap();
+        assert_eq!(body["type"], "about:blank");
+        assert_eq!(body["status"], 409);
+        assert_eq!(body["code"], "CONFLICT");
+        assert_eq!(body["error_status"], "Conflict");
+        assert_eq!(body["detail"], "username already exists");
+        assert_eq!(body["trace_id"], "abc-123");
+
+        let problem: ProblemDetails = serde_json::from_value(body).unwrap();
+        let decoded = CruxmontError::from(problem);
+        assert_eq!(decoded.status, CurxmontErrorStatus::Conflict);
+        assert_eq!(decoded.message, "username already exists");
+        assert_eq!(
+            decoded.details.as_deref(),
+            Some(&json!({"field": "username"}))
+        );
+        assert_eq!(decoded.trace_id.as_deref(), Some("abc-123"));
+    }
+
+    #[test]
+    fn test_problem_details_response_headers() {
+        let response = ProblemDetails::from(CruxmontError::not_found("no user")).into_response();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Maps the rejections of the axum `Json`, `Path` and `Query` extractors into `CruxmontError`.
+//!
+//! # Notes
+//! The status of the rejection is kept (for instance a body that does not match the target
+//! type is `UnprocessableEntity`) and the path of the offending field is carried in the
+//! `details` of the error so every error response goes through `CruxmontError::into_response`.
+use super::{CruxmontError, CurxmontErrorStatus};
+use axum::extract::path::ErrorKind as PathErrorKind;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use serde_json::{Map, Value, json};
+use std::error::Error as StdError;
+
+/// Walks the source chain of an error looking for an error of type `T`.
+///
+/// # Arguments
+/// * `error` - The error to start from.
+///
+/// # Returns
+/// * `Option<&T>` - The first error of type `T` in the chain.
+fn find_source<'a, T: StdError + 'static>(error: &'a (dyn StdError + 'static)) -> Option<&'a T> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(found) = error.downcast_ref::<T>() {
+            return Some(found);
+        }
+        current = error.source();
+    }
+    None
+}
+
+/// Converts a deserialization path into a field path, `.` being the root of the value.
+///
+/// # Arguments
+/// * `path` - The path reported by `serde_path_to_error`.
+///
+/// # Returns
+/// * `Option<String>` - The path of the field, None for the root.
+fn field_path(path: &serde_path_to_error::Path) -> Option<String> {
+    let path = path.to_string();
+    if path == "." { None } else { Some(path) }
+}
+
+/// Builds the error for a rejection with the given details.
+///
+/// # Arguments
+/// * `status_code` - The HTTP status code of the rejection.
+/// * `message` - The message of the rejection.
+/// * `details` - The details of the rejection.
+///
+/// # Returns
+/// * `CruxmontError` - The error for the rejection.
+fn rejection_error(
+    status_code: u16,
+    message: String,
+    details: Map<String, Value>,
+) -> CruxmontError {
+    let error = CruxmontError::new(message, CurxmontErrorStatus::from_code(status_code));
+    if details.is_empty() {
+        error
+    } else {
+        error.with_details(Value::Object(details))
+    }
+}
+
+impl From<JsonRejection> for CruxmontError {
+    fn from(rejection: JsonRejection) -> Self {
+        let mut details = Map::new();
+        if let Some(error) =
+            find_source::<serde_path_to_error::Error<serde_json::Error>>(&rejection)
+        {
+            if let Some(field) = field_path(error.path()) {
+                details.insert("field".to_string(), json!(field));
+            }
+            details.insert("line".to_string(), json!(error.inner().line()));
+            details.insert("column".to_string(), json!(error.inner().column()));
+        }
+        rejection_error(rejection.status().as_u16(), rejection.body_text(), details)
+            .with_source(rejection)
+    }
+}
+
+impl From<PathRejection> for CruxmontError {
+    fn from(rejection: PathRejection) -> Self {
+        let mut details = Map::new();
+        if let PathRejection::FailedToDeserializePathParams(error) = &rejection {
+            let field = match error.kind() {
+                PathErrorKind::ParseErrorAtKey { key, .. }
+                | PathErrorKind::InvalidUtf8InPathParam { key }
+                | PathErrorKind::DeserializeError { key, .. } => Some(key.clone()),
+                PathErrorKind::ParseErrorAtIndex { index, .. } => Some(index.to_string()),
+                _ => None,
+            };
+            if let Some(field) = field {
+                details.insert("field".to_string(), json!(field));
+            }
+        }
+        rejection_error(rejection.status().as_u16(), rejection.body_text(), details)
+            .with_source(rejection)
+    }
+}
+
+impl From<QueryRejection> for CruxmontError {
+    fn from(rejection: QueryRejection) -> Self {
+        let mut details = Map::new();
+        if let Some(field) =
+            find_source::<serde_path_to_error::Error<serde::de::value::Error>>(&rejection)
+                .and_then(|error| field_path(error.path()))
+        {
+            details.insert("field".to_string(), json!(field));
+        }
+        rejection_error(rejection.status().as_u16(), rejection.body_text(), details)
+            .with_source(rejection)
+    }
+}
@@ -0,0 +1,354 @@
+//! Defines the reporting of server errors and the redaction of their responses.
+//!
+//! # Notes
+//! Every error with a 5xx status that is turned into a response is given an incident ID and
+//! handed to the registered `ErrorReporter`s with its full diagnostics. When redaction is
+//! enabled the client only gets a generic message and the incident ID so the two can be matched
+//! up without leaking SQL or schema details.
+use super::{CruxmontError, CurxmontErrorStatus};
+use crate::config::GetConfigVariable;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use uuid::Uuid;
+
+/// The config variable used by `RedactionMode::from_config`.
+pub const ERROR_REDACTION_VARIABLE: &str = "CRUXMONT_ERROR_REDACTION";
+
+static REDACTION_MODE: AtomicU8 = AtomicU8::new(0);
+
+static ERROR_REPORTERS: RwLock<Vec<Arc<dyn ErrorReporter>>> = RwLock::new(Vec::new());
+
+/// Whether the responses of server errors are redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedactionMode {
+    /// Server errors are sent with their real message and details.
+    #[default]
+    Off,
+    /// Server errors are sent with a generic message and the incident ID.
+    ServerErrors,
+}
+
+impl RedactionMode {
+    /// Gets the redaction mode currently used for error responses.
+    ///
+    /// # Returns
+    /// * `RedactionMode` - The mode in use for the process.
+    pub fn current() -> RedactionMode {
+        match REDACTION_MODE.load(Ordering::Relaxed) {
+            1 => RedactionMode::ServerErrors,
+            _ => RedactionMode::Off,
+        }
+    }
+
+    /// Sets the redaction mode used for all error responses in the process.
+    pub fn set(self) {
+        let value = match self {
+            RedactionMode::Off => 0,
+            RedactionMode::ServerErrors => 1,
+        };
+        REDACTION_MODE.store(value, Ordering::Relaxed);
+    }
+
+    /// Reads the mode from the `CRUXMONT_ERROR_REDACTION` config variable. `on`, `true` or
+    /// `server` enables redaction, anything else (or a missing variable) disables it.
+    ///
+    /// # Returns
+    /// * `RedactionMode` - The mode defined in the config.
+    pub fn from_config<T: GetConfigVariable>() -> RedactionMode {
+        match T::get_config_variable(ERROR_REDACTION_VARIABLE.to_string()) {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "on" | "true" | "server" => RedactionMode::ServerErrors,
+                _ => RedactionMode::Off,
+            },
+            Err(_) => RedactionMode::Off,
+        }
+    }
+}
+
+/// The full record of a server error handed to the reporters.
+///
+/// # Fields
+/// * `incident_id` - The ID sent to the client to identify the error.
+/// * `status` - The status of the error.
+/// * `message` - The unredacted message of the error.
+/// * `details` - The unredacted details of the error.
+/// * `trace_id` - The ID of the request or trace the error happened in.
+/// * `diagnostics` - The context, source chain and backtrace of the error.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    pub incident_id: String,
+    pub status: CurxmontErrorStatus,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    pub diagnostics: String,
+}
+
+/// Defines the trait for receiving reports of server errors.
+pub trait ErrorReporter: Send + Sync {
+    /// Receives the report of a server error.
+    ///
+    /// # Arguments
+    /// * `report` - The report of the error.
+    fn report(&self, report: &ErrorReport);
+}
+
+/// Registers a reporter that will receive every server error.
+///
+/// # Arguments
+/// * `reporter` - The reporter to register.
+pub fn register_error_reporter(reporter: Arc<dyn ErrorReporter>) {
+    ERROR_REPORTERS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(reporter);
+}
+
+/// Removes all registered reporters.
+pub fn clear_error_reporters() {
+    ERROR_REPORTERS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clear();
+}
+
+/// Hands an error to all the registered reporters under a new incident ID.
+///
+/// # Arguments
+/// * `error` - The error to report.
+///
+/// # Returns
+/// * `String` - The incident ID the error was reported under.
+pub fn report_error(error: &CruxmontError) -> String {
+    let incident_id = Uuid::new_v4().to_string();
+    let reporters = ERROR_REPORTERS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if reporters.is_empty() {
+        return incident_id;
+    }
+    let report = ErrorReport {
+        incident_id: incident_id.clone(),
+        status: error.status,
+        message: error.message.clone(),
+        details: error.details.as_deref().cloned(),
+        trace_id: error.trace_id.clone(),
+        diagnostics: error.diagnostics().to_string(),
+    };
+    for reporter in reporters.iter() {
+        reporter.report(&report);
+    }
+    incident_id
+}
+
+/// Logs reports as `tracing` error events.
+pub struct TracingReporter;
+
+impl ErrorReporter for TracingReporter {
+    fn report(&self, report: &ErrorReport) {
+        tracing::error!(
+            incident_id = report.incident_id.as_str(),
+            status = report.status.status_code(),
+            trace_id = report.trace_id.as_deref(),
+            diagnostics = report.diagnostics.as_str(),
+            "server error"
+        );
+    }
+}
+
+/// Appends reports to a file as JSON lines.
+pub struct FileReporter {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileReporter {
+    /// Constructs a reporter for the given file. The file is created if it does not exist.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file to append to.
+    ///
+    /// # Returns
+    /// * `FileReporter` - The new reporter.
+    pub fn new(path: impl Into<PathBuf>) -> FileReporter {
+        FileReporter {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl ErrorReporter for FileReporter {
+    fn report(&self, report: &ErrorReport) {
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let line = match serde_json::to_string(report) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(error) = written {
+            tracing::error!(
+                incident_id = report.incident_id.as_str(),
+                path = %self.path.display(),
+                %error,
+                "failed to write the incident report"
+            );
+        }
+    }
+}
+
+/// Collects reports in memory, useful for asserting on reported errors in tests.
+#[derive(Default)]
+pub struct InMemoryReporter {
+    reports: Mutex<Vec<ErrorReport>>,
+}
+
+impl InMemoryReporter {
+    /// Constructs an empty reporter.
+    ///
+    /// # Returns
+    /// * `InMemoryReporter` - The new reporter.
+    pub fn new() -> InMemoryReporter {
+        InMemoryReporter::default()
+    }
+
+    /// Gets the reports collected so far.
+    ///
+    /// # Returns
+    /// * `Vec<ErrorReport>` - The collected reports.
+    pub fn reports(&self) -> Vec<ErrorReport> {
+        self.reports
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl ErrorReporter for InMemoryReporter {
+    fn report(&self, report: &ErrorReport) {
+        self.reports
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(report.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::REDACTED_MESSAGE;
+    use axum::response::IntoResponse;
+
+    /// Holds the lock of the global state and restores the reporters and the redaction mode
+    /// when dropped, even if the test panics.
+    struct GlobalsGuard {
+        reporters: Vec<Arc<dyn ErrorReporter>>,
+        redaction: RedactionMode,
+        _lock: tokio::sync::MutexGuard<'static, ()>,
+    }
+
+    impl GlobalsGuard {
+        fn new(lock: tokio::sync::MutexGuard<'static, ()>) -> GlobalsGuard {
+            GlobalsGuard {
+                reporters: ERROR_REPORTERS
+                    .read()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .clone(),
+                redaction: RedactionMode::current(),
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for GlobalsGuard {
+        fn drop(&mut self) {
+            *ERROR_REPORTERS
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                std::mem::take(&mut self.reporters);
+            self.redaction.set();
+        }
+    }
+
+    fn lock_globals() -> GlobalsGuard {
+        GlobalsGuard::new(crate::TEST_GLOBALS.blocking_lock())
+    }
+
+    #[test]
+    fn test_server_errors_are_reported_and_redacted() {
+        let _globals = lock_globals();
+        let reporter = Arc::new(InMemoryReporter::new());
+        register_error_reporter(reporter.clone());
+
+        let error = CruxmontError::unknown("Database error: relation \"users\" does not exist");
+        let incident_id = report_error(&error);
+        let report = reporter
+            .reports()
+            .into_iter()
+            .find(|report| report.incident_id == incident_id)
+            .expect("report the error");
+        assert_eq!(report.message, error.message);
+        assert!(report.diagnostics.starts_with("UNKNOWN (500)"));
+
+        let mut error = error;
+        error.incident_id = Some(incident_id.clone());
+        let redacted = error.redacted();
+        assert_eq!(
+            redacted.message,
+            format!("{} (incident: {})", REDACTED_MESSAGE, incident_id)
+        );
+        assert_eq!(redacted.incident_id, Some(incident_id));
+    }
+
+    #[tokio::test]
+    async fn test_responses_of_server_errors_are_reported_and_redacted() {
+        let _globals = GlobalsGuard::new(crate::TEST_GLOBALS.lock().await);
+        clear_error_reporters();
+        let reporter = Arc::new(InMemoryReporter::new());
+        register_error_reporter(reporter.clone());
+        RedactionMode::ServerErrors.set();
+        let response = CruxmontError::unknown("relation \"users\" does not exist").into_response();
+
+        let reports = reporter.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].message, "relation \"users\" does not exist");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let message: String = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            message,
+            format!(
+                "{} (incident: {})",
+                REDACTED_MESSAGE, reports[0].incident_id
+            )
+        );
+    }
+
+    #[test]
+    fn test_client_errors_are_not_reported() {
+        let _globals = lock_globals();
+        let reporter = Arc::new(InMemoryReporter::new());
+        register_error_reporter(reporter.clone());
+        let _ = CruxmontError::bad_request("missing a unique bad request message").into_response();
+        assert!(
+            reporter
+                .reports()
+                .iter()
+                .all(|report| report.message != "missing a unique bad request message")
+        );
+    }
+}
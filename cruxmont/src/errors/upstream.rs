@@ -0,0 +1,182 @@
+//! Decodes `CruxmontError` from the responses of upstream HTTP services.
+//!
+//! # Notes
+//! This is the inverse of `CruxmontError::into_response` so an error raised by one cruxmont
+//! service is passed on unchanged by the services calling it. Both body formats produced by a
+//! cruxmont server are understood (the JSON message string and `application/problem+json`),
+//! any other body is kept as the message and an empty body falls back to the status text.
+use super::i18n::message_keys;
+use super::problem::ProblemDetails;
+use super::{CruxmontError, CurxmontErrorStatus};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+
+/// The maximum number of characters of a non cruxmont body kept as the message.
+const MAX_UPSTREAM_MESSAGE_LENGTH: usize = 1024;
+
+/// Builds the error from the status and body of a response.
+///
+/// # Arguments
+/// * `status_code` - The HTTP status code of the response.
+/// * `body` - The body of the response.
+///
+/// # Returns
+/// * `CruxmontError` - The decoded error.
+fn decode_error(status_code: u16, body: &[u8]) -> CruxmontError {
+    let status = CurxmontErrorStatus::from_code(status_code);
+    if let Ok(problem) = serde_json::from_slice::<ProblemDetails>(body) {
+        return CruxmontError::from(problem);
+    }
+    if let Ok(message) = serde_json::from_slice::<String>(body) {
+        return CruxmontError::new(message, status);
+    }
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim();
+    if text.is_empty() {
+        return CruxmontError::new(status.to_string(), status);
+    }
+    CruxmontError::new(
+        text.chars()
+            .take(MAX_UPSTREAM_MESSAGE_LENGTH)
+            .collect::<String>(),
+        status,
+    )
+}
+
+impl CruxmontError {
+    /// Decodes the error returned by an upstream service.
+    ///
+    /// # Arguments
+    /// * `response` - The error response of the upstream service.
+    ///
+    /// # Returns
+    /// * `CruxmontError` - The error of the upstream service, or a `BadGateway` error if the
+    ///   body could not be read.
+    pub async fn from_response(response: reqwest::Response) -> CruxmontError {
+        let status_code = response.status().as_u16();
+        let url = response.url().to_string();
+        match response.bytes().await {
+            Ok(body) => decode_error(status_code, &body),
+            Err(error) => CruxmontError::from(error),
+        }
+        .with_context(format!("upstream {} responded with {}", url, status_code))
+    }
+}
+
+impl From<reqwest::Error> for CruxmontError {
+    fn from(error: reqwest::Error) -> Self {
+        let mapped = if error.is_timeout() {
+            CruxmontError::from_key(
+                message_keys::UPSTREAM_TIMED_OUT,
+                CurxmontErrorStatus::GatewayTimeout,
+            )
+        } else if error.is_connect() {
+            CruxmontError::from_key(
+                message_keys::UPSTREAM_UNAVAILABLE,
+                CurxmontErrorStatus::ServiceUnavailable,
+            )
+        } else if let Some(status) = error.status() {
+            let status = CurxmontErrorStatus::from_code(status.as_u16());
+            CruxmontError::new(status.to_string(), status)
+        } else if error.is_decode() || error.is_body() {
+            CruxmontError::from_key(
+                message_keys::UPSTREAM_INVALID_RESPONSE,
+                CurxmontErrorStatus::BadGateway,
+            )
+        } else {
+            CruxmontError::bad_gateway(format!("Upstream request failed: {}", error))
+        };
+        mapped.with_source(error)
+    }
+}
+
+/// Extends `reqwest::Response` with conversions into `CruxmontError`.
+pub trait ResponseExt: Sized {
+    /// Passes a successful response through and decodes an error response.
+    ///
+    /// # Returns
+    /// * `Result<reqwest::Response, CruxmontError>` - The response if the status is not an
+    ///   error, otherwise the error of the upstream service.
+    fn error_for_cruxmont_status(
+        self,
+    ) -> impl Future<Output = Result<reqwest::Response, CruxmontError>> + Send;
+
+    /// Deserializes the JSON body of a successful response and decodes an error response.
+    ///
+    /// # Returns
+    /// * `Result<T, CruxmontError>` - The body of the response or the error of the upstream
+    ///   service.
+    fn cruxmont_json<T: DeserializeOwned>(
+        self,
+    ) -> impl Future<Output = Result<T, CruxmontError>> + Send;
+}
+
+impl ResponseExt for reqwest::Response {
+    async fn error_for_cruxmont_status(self) -> Result<reqwest::Response, CruxmontError> {
+        let status = self.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(CruxmontError::from_response(self).await);
+        }
+        Ok(self)
+    }
+
+    async fn cruxmont_json<T: DeserializeOwned>(self) -> Result<T, CruxmontError> {
+        let response = self.error_for_cruxmont_status().await?;
+        Ok(response.json::<T>().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use serde_json::json;
+
+    async fn serve(response: axum::response::Response) -> reqwest::Response {
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let app =
+            axum::Router::new().route(
+                "/",
+                axum::routing::get(move || {
+                    let parts = parts.clone();
+                    let body = body.clone();
+                    async move {
+                        axum::response::Response::from_parts(parts, axum::body::Body::from(body))
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        reqwest::get(format!("http://{}/", address)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_errors_round_trip_through_responses() {
+        let error = CruxmontError::conflict("username already exists");
+        let response = serve(error.into_response()).await;
+        let decoded = response.error_for_cruxmont_status().await.unwrap_err();
+        assert_eq!(decoded.status, CurxmontErrorStatus::Conflict);
+        assert_eq!(decoded.message, "username already exists");
+
+        let problem = ProblemDetails::from(
+            CruxmontError::custom(418, "teapot").with_details(json!({"field": "brew"})),
+        );
+        let response = serve(problem.into_response()).await;
+        let decoded = CruxmontError::from_response(response).await;
+        assert_eq!(decoded.status, CurxmontErrorStatus::Custom(418));
+        assert_eq!(decoded.details.as_deref(), Some(&json!({"field": "brew"})));
+    }
+
+    #[test]
+    fn test_non_cruxmont_bodies() {
+        let error = decode_error(502, b"<html>bad gateway</html>");
+        assert_eq!(error.status, CurxmontErrorStatus::BadGateway);
+        assert_eq!(error.message, "<html>bad gateway</html>");
+
+        let error = decode_error(503, b"");
+        assert_eq!(error.status, CurxmontErrorStatus::ServiceUnavailable);
+        assert_eq!(error.message, "Service Unavailable");
+    }
+}
@@ -0,0 +1,275 @@
+//! Defines drop-in replacements for the axum `Json`, `Path` and `Query` extractors.
+//!
+//! # Notes
+//! The axum extractors reject requests with their own plain text responses. These wrappers
+//! convert the rejection into a `CruxmontError` so bad requests are returned in the same format
+//! as every other error of the API.
+//!
+//! ```
+//! use cruxmont::errors::CruxmontError;
+//! use cruxmont::extract::{Json, Path};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct NewUser {
+//!     username: String,
+//! }
+//!
+//! async fn rename_user(
+//!     Path(user_id): Path<i32>,
+//!     Json(user): Json<NewUser>,
+//! ) -> Result<Json<String>, CruxmontError> {
+//!     Ok(Json(format!("{}: {}", user_id, user.username)))
+//! }
+//! ```
+use crate::errors::CruxmontError;
+use crate::validation::Validate;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response as AxumResponse};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::ops::{Deref, DerefMut};
+
+/// Implements `Deref` and `DerefMut` to the inner value of an extractor wrapper.
+macro_rules! impl_deref {
+    ($wrapper:ident) => {
+        impl<T> Deref for $wrapper<T> {
+            type Target = T;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $wrapper<T> {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.0
+            }
+        }
+    };
+}
+
+/// JSON extractor and response that rejects with a `CruxmontError`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+/// Path parameter extractor that rejects with a `CruxmontError`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+/// Query string extractor that rejects with a `CruxmontError`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+/// Extractor that validates the value of the wrapped extractor, for example
+/// `Valid(Json(user)): Valid<Json<NewUser>>`. Rejects with an `UnprocessableEntity` error listing
+/// every field that failed validation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Valid<E>(pub E);
+
+/// Defines the trait for extractors whose extracted value can be validated by `Valid`.
+pub trait ValidatedExtractor {
+    /// The type of the extracted value.
+    type Value: Validate;
+
+    /// Gets the extracted value.
+    ///
+    /// # Returns
+    /// * `&Self::Value` - The value to validate.
+    fn value(&self) -> &Self::Value;
+}
+
+impl_deref!(Json);
+impl_deref!(Path);
+impl_deref!(Query);
+impl_deref!(Valid);
+
+/// Implements `ValidatedExtractor` for an extractor wrapper.
+macro_rules! impl_validated_extractor {
+    ($wrapper:ident) => {
+        impl<T: Validate> ValidatedExtractor for $wrapper<T> {
+            type Value = T;
+
+            fn value(&self) -> &Self::Value {
+                &self.0
+            }
+        }
+    };
+}
+
+impl_validated_extractor!(Json);
+impl_validated_extractor!(Path);
+impl_validated_extractor!(Query);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = CruxmontError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> AxumResponse {
+        axum::Json(self.0).into_response()
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = CruxmontError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = CruxmontError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
+impl<E, S> FromRequest<S> for Valid<E>
+where
+    E: FromRequest<S, Rejection = CruxmontError> + ValidatedExtractor,
+    S: Send + Sync,
+{
+    type Rejection = CruxmontError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let extracted = E::from_request(req, state).await?;
+        extracted.value().validate()?;
+        Ok(Valid(extracted))
+    }
+}
+
+impl<E, S> FromRequestParts<S> for Valid<E>
+where
+    E: FromRequestParts<S, Rejection = CruxmontError> + ValidatedExtractor,
+    S: Send + Sync,
+{
+    type Rejection = CruxmontError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extracted = E::from_request_parts(parts, state).await?;
+        extracted.value().validate()?;
+        Ok(Valid(extracted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::CurxmontErrorStatus;
+    use axum::body::Body;
+    use axum::http::header::CONTENT_TYPE;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize, Debug, Validate)]
+    #[allow(dead_code)]
+    struct NewUser {
+        #[validate(length(min = 3))]
+        username: String,
+        age: u8,
+    }
+
+    #[tokio::test]
+    async fn test_json_rejection_has_field_details() {
+        let req = Request::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"username": "maxwell", "age": "old"}"#))
+            .unwrap();
+        let error = Json::<NewUser>::from_request(req, &()).await.unwrap_err();
+        assert_eq!(error.status, CurxmontErrorStatus::UnprocessableEntity);
+        assert_eq!(error.details.unwrap()["field"], json!("age"));
+
+        let req = Request::builder()
+            .body(Body::from(r#"{"username": "maxwell", "age": 3}"#))
+            .unwrap();
+        let error = Json::<NewUser>::from_request(req, &()).await.unwrap_err();
+        assert_eq!(error.status, CurxmontErrorStatus::UnsupportedMediaType);
+    }
+
+    #[tokio::test]
+    async fn test_query_rejection_is_bad_request() {
+        let req = Request::builder()
+            .uri("/users?username=sam&age=300")
+            .body(Body::empty())
+            .unwrap();
+        let (mut parts, _) = req.into_parts();
+        let error = Query::<NewUser>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, CurxmontErrorStatus::BadRequest);
+        assert_eq!(error.details.unwrap()["field"], json!("age"));
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_rejects_invalid_fields() {
+        let req = Request::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"username": "mf", "age": 30}"#))
+            .unwrap();
+        let error = Valid::<Json<NewUser>>::from_request(req, &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, CurxmontErrorStatus::UnprocessableEntity);
+        assert_eq!(
+            error.details.unwrap()["errors"][0]["path"],
+            json!("username")
+        );
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct UserPath {
+        user_id: u32,
+    }
+
+    #[tokio::test]
+    async fn test_path_rejection_has_field_details() {
+        use tower::ServiceExt;
+
+        // path parameters are only set by the router, the handler sends the rejection back
+        let app = axum::Router::new().route(
+            "/users/{user_id}",
+            axum::routing::get(|path: Result<Path<UserPath>, CruxmontError>| async move {
+                axum::Json(path.err())
+            }),
+        );
+        let req = Request::builder()
+            .uri("/users/maxwell")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error = serde_json::from_slice::<Option<CruxmontError>>(&body)
+            .unwrap()
+            .expect("reject the path");
+        assert_eq!(error.status, CurxmontErrorStatus::BadRequest);
+        assert_eq!(error.details.unwrap()["field"], json!("user_id"));
+    }
+}
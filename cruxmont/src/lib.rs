@@ -1,12 +1,25 @@
+// lets the derive macros refer to `::cruxmont` from inside this crate
+extern crate self as cruxmont;
+
+pub mod app_env;
 pub mod dal;
 pub mod define_transactions;
 pub mod config;
 pub mod errors;
+pub mod extract;
+pub mod routes;
+pub mod telemetry;
+pub mod validation;
 
 
+// used by the routers generated by `routes!`
+pub use axum;
 pub use cruxmont_db_tx as db_tx;
+pub use define_transactions::define_dal_transactions;
 pub use cruxmont_http_tx as http_tx;
 pub use cruxmont_pg_pool_macro as pg_pool;
+// used by the spans of the generated transactions
+pub use tracing;
 
 #[cfg(feature = "embedded-pg")]
 pub use cruxmont_embedded_pg_test_macro as embedded_pg_test;
@@ -19,3 +32,8 @@ pub use cruxmont_test_utils as test_utils;
 
 #[cfg(feature = "test")]
 pub use cruxmont_pg_test_macro as pg_test;
+
+/// Serializes the tests changing the state global to the process, such as the error reporters,
+/// the redaction mode and the retry observers. Sync tests take it with `blocking_lock`.
+#[cfg(test)]
+pub(crate) static TEST_GLOBALS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
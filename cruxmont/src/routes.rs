@@ -0,0 +1,156 @@
+//! Defines the macro generating the router factory of generic handlers.
+//!
+//! # Notes
+//! Handlers generic over their descriptor and pool, or over an `AppEnv`, have to be
+//! instantiated with the same types for every route. `routes!` writes the route table once and
+//! generates a factory generic over those types, so the live router and the routers of the tests
+//! are built from the same table:
+//!
+//! ```ignore
+//! routes!(
+//!     pub fn counter_factory<E: AppEnv>;
+//!     GET "/increment" => increment::increase_and_get_count,
+//!     GET "/decrement" => decrement::decrease_and_get_count,
+//! );
+//!
+//! let app = counter_factory::<LiveEnv>(Router::new());
+//! let test_app = counter_factory::<EmbeddedEnv>(Router::new());
+//! ```
+
+/// Generates a function adding routes to a `Router`, with every handler instantiated with the
+/// generic parameters of the function. The method of a route is one of the constants of
+/// `axum::routing::MethodFilter` such as `GET` or `POST`, and the bounds of the parameters must
+/// be in scope. For example:
+///
+/// ```
+/// use cruxmont::routes;
+///
+/// pub trait Greeting {
+///     fn greeting() -> &'static str;
+/// }
+///
+/// pub struct English;
+///
+/// impl Greeting for English {
+///     fn greeting() -> &'static str {
+///         "hello"
+///     }
+/// }
+///
+/// async fn greet<G: Greeting>() -> &'static str {
+///     G::greeting()
+/// }
+///
+/// routes!(
+///     pub fn greeting_factory<G: Greeting>;
+///     GET "/greet" => greet,
+/// );
+///
+/// let app = greeting_factory::<English>(axum::Router::new());
+/// ```
+#[macro_export]
+macro_rules! routes {
+    (@handler $($handler:ident)::+; [$($param:ident),+]) => {
+        $($handler)::+::<$($param),+>
+    };
+    (
+        @factory [$(#[$meta:meta])*] [$vis:vis] $factory:ident [$($generics:tt)*] $params:tt;
+        $($method:ident $path:literal => $($handler:ident)::+),*
+    ) => {
+        $(#[$meta])*
+        $vis fn $factory<$($generics)*>(app: $crate::axum::Router) -> $crate::axum::Router {
+            app$(
+                .route(
+                    $path,
+                    $crate::axum::routing::on(
+                        $crate::axum::routing::MethodFilter::$method,
+                        $crate::routes!(@handler $($handler)::+; $params),
+                    ),
+                )
+            )*
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $factory:ident <$($param:ident: $bound:ident $(+ $more_bound:ident)*),+ $(,)?>;
+        $($method:ident $path:literal => $($handler:ident)::+),* $(,)?
+    ) => {
+        // the parameters are passed as one token tree to be repeated for every route
+        $crate::routes!(
+            @factory [$(#[$meta])*] [$vis] $factory
+            [$($param: $bound $(+ $more_bound)* + 'static),+] [$($param),+];
+            $($method $path => $($handler)::+),*
+        );
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    pub trait Count {
+        fn count() -> i32;
+    }
+
+    pub trait Label {
+        fn label() -> &'static str;
+    }
+
+    pub struct Live;
+
+    impl Count for Live {
+        fn count() -> i32 {
+            1
+        }
+    }
+
+    impl Label for Live {
+        fn label() -> &'static str {
+            "live"
+        }
+    }
+
+    async fn get_count<X: Count, Y: Label>() -> String {
+        format!("{} {}", Y::label(), X::count())
+    }
+
+    async fn reset_count<X: Count, Y: Label>() -> StatusCode {
+        StatusCode::NO_CONTENT
+    }
+
+    routes!(
+        fn counter_factory<X: Count, Y: Label>;
+        GET "/count" => get_count,
+        POST "/count" => reset_count,
+        DELETE "/count/reset" => self::reset_count,
+    );
+
+    async fn status(app: axum::Router, method: &str, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_routes_are_registered_with_the_parameters() {
+        let app = counter_factory::<Live, Live>(axum::Router::new());
+        assert_eq!(status(app.clone(), "GET", "/count").await, StatusCode::OK);
+        assert_eq!(
+            status(app.clone(), "POST", "/count").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            status(app.clone(), "DELETE", "/count/reset").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            status(app, "PUT", "/count").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+}
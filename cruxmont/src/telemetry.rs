@@ -0,0 +1,428 @@
+//! Defines the telemetry of the transactions generated by `#[db_transaction]` and
+//! `#[http_transaction]`.
+//!
+//! # Notes
+//! Every call of a generated transaction runs in a `tracing` span named after its trait and
+//! function, such as `GetCount::get_count`. The span records:
+//! * `args` - A summary of the arguments. Numbers, booleans and ids are written out, text is
+//!   replaced by its length and anything else by its type so secrets never reach the logs.
+//! * `duration_ms` - The time the call took.
+//! * `outcome` - `ok` or `error`.
+//! * `rows` - The rows affected by a `PgQueryResult` or returned in a `Vec`.
+//! * `cache` - `hit` or `miss` for the transactions declared with `cache(...)`.
+//!
+//! A call slower than the `SlowQueryThreshold` logs a warning with the SQL statements written
+//! in the body of the transaction.
+use crate::config::GetConfigVariable;
+use std::any::type_name;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::Span;
+
+/// The config variable used by `SlowQueryThreshold::from_config`.
+pub const SLOW_QUERY_VARIABLE: &str = "CRUXMONT_SLOW_QUERY_MS";
+
+/// The threshold used until one is set.
+pub const DEFAULT_SLOW_QUERY_THRESHOLD: Duration = Duration::from_millis(500);
+
+// in milliseconds, 0 disables the warnings
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(DEFAULT_SLOW_QUERY_THRESHOLD.as_millis() as u64);
+
+/// The duration above which a transaction is logged as slow, None if slow transactions are
+/// not logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowQueryThreshold(pub Option<Duration>);
+
+impl SlowQueryThreshold {
+    /// Gets the threshold currently used for the process.
+    ///
+    /// # Returns
+    /// * `SlowQueryThreshold` - The threshold in use.
+    pub fn current() -> SlowQueryThreshold {
+        match SLOW_QUERY_MS.load(Ordering::Relaxed) {
+            0 => SlowQueryThreshold(None),
+            millis => SlowQueryThreshold(Some(Duration::from_millis(millis))),
+        }
+    }
+
+    /// Sets the threshold used for all transactions in the process.
+    pub fn set(self) {
+        let millis = match self.0 {
+            Some(threshold) => (threshold.as_millis() as u64).max(1),
+            None => 0,
+        };
+        SLOW_QUERY_MS.store(millis, Ordering::Relaxed);
+    }
+
+    /// Reads the threshold in milliseconds from the `CRUXMONT_SLOW_QUERY_MS` config variable,
+    /// `0` or `off` disables the warnings. A missing or invalid value gives the default of 500ms.
+    ///
+    /// # Returns
+    /// * `SlowQueryThreshold` - The threshold defined in the config.
+    pub fn from_config<T: GetConfigVariable>() -> SlowQueryThreshold {
+        let default = SlowQueryThreshold(Some(DEFAULT_SLOW_QUERY_THRESHOLD));
+        let Ok(value) = T::get_config_variable(SLOW_QUERY_VARIABLE.to_string()) else {
+            return default;
+        };
+        match value.trim().to_lowercase().as_str() {
+            "0" | "off" | "false" => SlowQueryThreshold(None),
+            millis => match millis.parse::<u64>() {
+                Ok(millis) => SlowQueryThreshold(Some(Duration::from_millis(millis))),
+                Err(_) => {
+                    tracing::warn!(
+                        %value,
+                        variable = SLOW_QUERY_VARIABLE,
+                        "invalid slow query threshold, using 500ms"
+                    );
+                    default
+                }
+            },
+        }
+    }
+
+    /// Checks if a call is slow.
+    ///
+    /// # Arguments
+    /// * `elapsed` - The duration of the call.
+    ///
+    /// # Returns
+    /// * `bool` - True if the threshold is set and the call took at least as long.
+    pub fn is_slow(&self, elapsed: Duration) -> bool {
+        self.0.is_some_and(|threshold| elapsed >= threshold)
+    }
+}
+
+/// Measures a call of a transaction and records it on its span, used by the generated
+/// transactions.
+pub struct TransactionTelemetry {
+    name: &'static str,
+    statements: &'static [&'static str],
+    span: Span,
+    started: Instant,
+}
+
+impl TransactionTelemetry {
+    /// Starts measuring a call.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the transaction, such as `GetCount::get_count`.
+    /// * `span` - The span of the call.
+    /// * `args` - Builds the summary of the arguments, only called if the span is enabled.
+    /// * `statements` - The SQL statements written in the body of the transaction.
+    ///
+    /// # Returns
+    /// * `TransactionTelemetry` - The measurement of the call.
+    pub fn start(
+        name: &'static str,
+        span: Span,
+        args: impl FnOnce() -> Vec<(&'static str, String)>,
+        statements: &'static [&'static str],
+    ) -> TransactionTelemetry {
+        if !span.is_disabled() {
+            let summary = args()
+                .into_iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<String>>()
+                .join(", ");
+            span.record("args", summary.as_str());
+        }
+        TransactionTelemetry {
+            name,
+            statements,
+            span,
+            started: Instant::now(),
+        }
+    }
+
+    /// Gets the span of the call.
+    ///
+    /// # Returns
+    /// * `Span` - The span, to instrument the future of the call with.
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    /// Records the duration, outcome and rows of the call and warns if it was slow.
+    ///
+    /// # Arguments
+    /// * `is_ok` - Whether the call succeeded.
+    /// * `rows` - The rows affected or returned, None if unknown.
+    pub fn finish(self, is_ok: bool, rows: Option<u64>) {
+        let elapsed = self.started.elapsed();
+        let outcome = if is_ok { "ok" } else { "error" };
+        self.span.record("duration_ms", elapsed.as_millis() as u64);
+        self.span.record("outcome", outcome);
+        if let Some(rows) = rows {
+            self.span.record("rows", rows);
+        }
+        if SlowQueryThreshold::current().is_slow(elapsed) {
+            let _entered = self.span.enter();
+            tracing::warn!(
+                transaction = self.name,
+                duration_ms = elapsed.as_millis() as u64,
+                outcome,
+                statements = ?self.statements,
+                "slow transaction"
+            );
+        }
+    }
+}
+
+/// Starts the telemetry of a call, used by `#[db_transaction]` and `#[http_transaction]` so
+/// both open the same span with the same fields.
+///
+/// # Arguments
+/// * `name` - The name of the transaction, such as `"GetCount::get_count"`.
+/// * `args` - The arguments summarized in the span, such as `[id, name]`.
+/// * `statements` - The SQL statements written in the body of the transaction.
+///
+/// # Returns
+/// * `TransactionTelemetry` - The measurement of the call.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! transaction_telemetry {
+    ($name:literal, [$($arg:ident),* $(,)?], [$($statement:literal),* $(,)?]) => {
+        $crate::telemetry::TransactionTelemetry::start(
+            $name,
+            $crate::tracing::info_span!(
+                $name,
+                args = $crate::tracing::field::Empty,
+                duration_ms = $crate::tracing::field::Empty,
+                outcome = $crate::tracing::field::Empty,
+                rows = $crate::tracing::field::Empty,
+                cache = $crate::tracing::field::Empty,
+            ),
+            || {
+                #[allow(unused_imports)]
+                use $crate::telemetry::{
+                    SummarizeLoggable as _, SummarizeOpaque as _, SummarizeText as _,
+                };
+                ::std::vec![$(
+                    (
+                        ::core::stringify!($arg),
+                        (&&&$crate::telemetry::ArgSummary(&$arg)).summarize(),
+                    )
+                ),*]
+            },
+            &[$($statement),*],
+        )
+    };
+}
+
+/// Shortens a type name by removing its module paths, `alloc::string::String` becomes `String`.
+fn short_type_name<T: ?Sized>() -> String {
+    let name = type_name::<T>();
+    let mut short = String::with_capacity(name.len());
+    let mut segment = String::new();
+    for character in name.chars() {
+        if character.is_alphanumeric() || character == '_' || character == ':' {
+            segment.push(character);
+        } else {
+            short.push_str(segment.rsplit("::").next().unwrap_or_default());
+            segment.clear();
+            short.push(character);
+        }
+    }
+    short.push_str(segment.rsplit("::").next().unwrap_or_default());
+    short
+}
+
+/// Defines the trait for the arguments written out in the summary of a transaction. Implement
+/// it for identifiers and other values that are safe to log.
+pub trait LoggableArg {
+    /// Writes out the value for the logs.
+    ///
+    /// # Returns
+    /// * `String` - The value.
+    fn to_log(&self) -> String;
+}
+
+macro_rules! impl_loggable_arg {
+    ($($ty:ty),*) => {
+        $(
+            impl LoggableArg for $ty {
+                fn to_log(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_loggable_arg!(
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64,
+    bool,
+    char,
+    uuid::Uuid
+);
+
+impl<T: LoggableArg + ?Sized> LoggableArg for &T {
+    fn to_log(&self) -> String {
+        (**self).to_log()
+    }
+}
+
+impl<T: LoggableArg> LoggableArg for Option<T> {
+    fn to_log(&self) -> String {
+        match self {
+            Some(value) => value.to_log(),
+            None => "None".to_string(),
+        }
+    }
+}
+
+/// Wraps an argument so `summarize` picks the best summary for it. Loggable arguments are
+/// written out, text is replaced by its length and anything else by its type.
+#[doc(hidden)]
+pub struct ArgSummary<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait SummarizeLoggable {
+    fn summarize(&self) -> String;
+}
+
+impl<T: LoggableArg + ?Sized> SummarizeLoggable for &&ArgSummary<'_, T> {
+    fn summarize(&self) -> String {
+        self.0.to_log()
+    }
+}
+
+#[doc(hidden)]
+pub trait SummarizeText {
+    fn summarize(&self) -> String;
+}
+
+impl<T: AsRef<str> + ?Sized> SummarizeText for &ArgSummary<'_, T> {
+    fn summarize(&self) -> String {
+        format!("<{} chars>", self.0.as_ref().chars().count())
+    }
+}
+
+#[doc(hidden)]
+pub trait SummarizeOpaque {
+    fn summarize(&self) -> String;
+}
+
+impl<T: ?Sized> SummarizeOpaque for ArgSummary<'_, T> {
+    fn summarize(&self) -> String {
+        format!("<{}>", short_type_name::<T>())
+    }
+}
+
+/// Wraps the value of a transaction so `rows` finds the number of rows it affected or returned.
+#[doc(hidden)]
+pub struct RowsProbe<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait RowsAffected {
+    fn rows(&self) -> Option<u64>;
+}
+
+impl RowsAffected for &&RowsProbe<'_, sqlx::postgres::PgQueryResult> {
+    fn rows(&self) -> Option<u64> {
+        Some(self.0.rows_affected())
+    }
+}
+
+#[doc(hidden)]
+pub trait RowsReturned {
+    fn rows(&self) -> Option<u64>;
+}
+
+impl<T> RowsReturned for &RowsProbe<'_, Vec<T>> {
+    fn rows(&self) -> Option<u64> {
+        Some(self.0.len() as u64)
+    }
+}
+
+#[doc(hidden)]
+pub trait RowsUnknown {
+    fn rows(&self) -> Option<u64>;
+}
+
+impl<T: ?Sized> RowsUnknown for RowsProbe<'_, T> {
+    fn rows(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::secrets::Secret;
+    use crate::config::source::{MapConfig, ScopedConfig};
+    use std::sync::Arc;
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_argument_summaries_and_rows() {
+        let id = 7_i64;
+        let name = "ann";
+        let password = Secret::new("hunter2".to_string());
+        assert_eq!((&&&ArgSummary(&id)).summarize(), "7");
+        assert_eq!((&&&ArgSummary(&Some(&id))).summarize(), "7");
+        assert_eq!((&&&ArgSummary(&name)).summarize(), "<3 chars>");
+        assert_eq!((&&&ArgSummary(&password)).summarize(), "<Secret<String>>");
+
+        let rows = vec![1, 2, 3];
+        assert_eq!((&&&RowsProbe(&rows)).rows(), Some(3));
+        assert_eq!((&&&RowsProbe(&id)).rows(), None);
+    }
+
+    trait Echo {
+        fn echo(id: i64) -> impl std::future::Future<Output = Result<i64, reqwest::Error>> + Send;
+    }
+
+    struct EchoClient;
+
+    #[cruxmont_http_tx::http_transaction(EchoClient, Echo)]
+    async fn echo(id: i64) -> i64 {
+        Ok(id)
+    }
+
+    #[tokio::test]
+    async fn test_http_and_db_transactions_share_their_span_fields() {
+        assert_eq!(EchoClient::echo(7).await.unwrap(), 7);
+
+        let id = 7_i64;
+        let telemetry = crate::transaction_telemetry!("Echo::echo", [id], []);
+        let fields = telemetry.span().metadata().map(|metadata| {
+            metadata
+                .fields()
+                .iter()
+                .map(|field| field.name())
+                .collect::<Vec<&str>>()
+        });
+        assert_eq!(
+            fields,
+            Some(vec!["args", "duration_ms", "outcome", "rows", "cache"])
+        );
+    }
+
+    #[test]
+    fn test_slow_query_threshold_from_config() {
+        let config = MapConfig::new().with(SLOW_QUERY_VARIABLE, "250");
+        let threshold = ScopedConfig::sync_scope(Arc::new(config), || {
+            SlowQueryThreshold::from_config::<ScopedConfig>()
+        });
+        assert_eq!(
+            threshold,
+            SlowQueryThreshold(Some(Duration::from_millis(250)))
+        );
+        assert!(threshold.is_slow(Duration::from_millis(300)));
+        assert!(!SlowQueryThreshold(None).is_slow(Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,487 @@
+//! Defines field level validation of request bodies and parameters.
+//!
+//! # Notes
+//! Instead of hand rolling a `CruxmontError::bad_request` for every field a type derives
+//! `Validate` and all the problems are collected into `ValidationErrors`, which converts into a
+//! single `UnprocessableEntity` error listing every field that failed.
+//!
+//! ```
+//! use cruxmont::validation::Validate;
+//!
+//! #[derive(Validate)]
+//! struct NewUser {
+//!     #[validate(length(min = 3, max = 20), regex = "^[a-z0-9_]+$")]
+//!     username: String,
+//!     #[validate(email)]
+//!     email: String,
+//!     #[validate(range(min = 13))]
+//!     age: Option<u8>,
+//! }
+//!
+//! let user = NewUser {
+//!     username: "Me".to_string(),
+//!     email: "me.example.com".to_string(),
+//!     age: Some(12),
+//! };
+//! let errors = user.validate().unwrap_err();
+//! assert_eq!(errors.errors().len(), 4);
+//! ```
+use crate::errors::i18n::message_keys;
+use crate::errors::{CruxmontError, CurxmontErrorStatus};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::fmt;
+
+pub use cruxmont_validate_derive::Validate;
+#[cfg(feature = "regex")]
+pub use regex::Regex;
+
+/// A single problem with a field.
+///
+/// # Fields
+/// * `path` - The path of the field, nested fields are joined with `.` and indexes with `[i]`.
+/// * `code` - Stable machine readable code of the problem such as `length` or `email`.
+/// * `message` - The human readable description of the problem.
+/// * `params` - The parameters of the failed validation such as `min` and `max`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub path: String,
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+}
+
+impl FieldError {
+    /// Constructs a field error without a path, the path is set when it is added to
+    /// `ValidationErrors` with `push_at`.
+    ///
+    /// # Arguments
+    /// * `code` - The code of the problem.
+    /// * `message` - The description of the problem.
+    ///
+    /// # Returns
+    /// * `FieldError` - The new error.
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> FieldError {
+        FieldError {
+            path: String::new(),
+            code: code.into(),
+            message: message.into(),
+            params: Map::new(),
+        }
+    }
+
+    /// Adds a parameter to the error.
+    ///
+    /// # Arguments
+    /// * `key` - The name of the parameter.
+    /// * `value` - The value of the parameter.
+    ///
+    /// # Returns
+    /// * `FieldError` - The error with the parameter.
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Serialize) -> FieldError {
+        self.params.insert(
+            key.into(),
+            serde_json::to_value(value).unwrap_or(Value::Null),
+        );
+        self
+    }
+}
+
+/// Joins a field path onto a prefix.
+///
+/// # Arguments
+/// * `prefix` - The path of the parent field.
+/// * `path` - The path relative to the parent field.
+///
+/// # Returns
+/// * `String` - The full path of the field.
+fn join_path(prefix: &str, path: &str) -> String {
+    if prefix.is_empty() {
+        path.to_string()
+    } else if path.is_empty() {
+        prefix.to_string()
+    } else if path.starts_with('[') {
+        format!("{}{}", prefix, path)
+    } else {
+        format!("{}.{}", prefix, path)
+    }
+}
+
+/// All the problems found when validating a value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// Constructs an empty set of errors.
+    ///
+    /// # Returns
+    /// * `ValidationErrors` - The empty errors.
+    pub fn new() -> ValidationErrors {
+        ValidationErrors::default()
+    }
+
+    /// Adds an error.
+    ///
+    /// # Arguments
+    /// * `error` - The error to add.
+    pub fn push(&mut self, error: FieldError) {
+        self.errors.push(error);
+    }
+
+    /// Adds an error for a field, the path is joined onto the path the error already has.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the field.
+    /// * `error` - The error to add.
+    pub fn push_at(&mut self, path: &str, mut error: FieldError) {
+        error.path = join_path(path, &error.path);
+        self.errors.push(error);
+    }
+
+    /// Adds an error for a field from its code and message.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the field.
+    /// * `code` - The code of the problem.
+    /// * `message` - The description of the problem.
+    pub fn add(&mut self, path: &str, code: impl Into<String>, message: impl Into<String>) {
+        self.push_at(path, FieldError::new(code, message));
+    }
+
+    /// Adds the errors of a nested value under the path of the field holding it.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the field holding the nested value.
+    /// * `nested` - The errors of the nested value.
+    pub fn merge(&mut self, path: &str, nested: ValidationErrors) {
+        for error in nested.errors {
+            self.push_at(path, error);
+        }
+    }
+
+    /// Checks if there are no errors.
+    ///
+    /// # Returns
+    /// * `bool` - True if no errors have been added.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Gets the errors.
+    ///
+    /// # Returns
+    /// * `&[FieldError]` - The errors in the order they were added.
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// Converts the errors into the result of a validation.
+    ///
+    /// # Returns
+    /// * `Result<(), ValidationErrors>` - Ok if there are no errors.
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self
+            .errors
+            .iter()
+            .map(|error| format!("{}: {}", error.path, error.message))
+            .collect::<Vec<String>>();
+        write!(f, "Validation failed: {}", fields.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl From<ValidationErrors> for CruxmontError {
+    fn from(errors: ValidationErrors) -> Self {
+        CruxmontError::from_key(
+            message_keys::VALIDATION_FAILED,
+            CurxmontErrorStatus::UnprocessableEntity,
+        )
+        .with_details(json!({ "errors": errors.errors }))
+    }
+}
+
+/// Defines the trait for values that can be validated.
+pub trait Validate {
+    /// Validates the value.
+    ///
+    /// # Returns
+    /// * `Result<(), ValidationErrors>` - All the problems found with the value.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            Some(value) => value.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        for (index, value) in self.iter().enumerate() {
+            if let Err(nested) = value.validate() {
+                errors.merge(&format!("[{}]", index), nested);
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl<T: Validate + ?Sized> Validate for Box<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.as_ref().validate()
+    }
+}
+
+/// Defines the trait for values that have a length that can be validated.
+pub trait ValidateLength {
+    /// Gets the length used for validation, characters for strings.
+    ///
+    /// # Returns
+    /// * `usize` - The length of the value.
+    fn validation_length(&self) -> usize;
+}
+
+impl ValidateLength for str {
+    fn validation_length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl ValidateLength for String {
+    fn validation_length(&self) -> usize {
+        self.as_str().validation_length()
+    }
+}
+
+impl<T> ValidateLength for [T] {
+    fn validation_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> ValidateLength for Vec<T> {
+    fn validation_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: ValidateLength + ?Sized> ValidateLength for &T {
+    fn validation_length(&self) -> usize {
+        (**self).validation_length()
+    }
+}
+
+/// Checks the length of a value.
+///
+/// # Arguments
+/// * `value` - The value to check.
+/// * `min` - The minimum length, if any.
+/// * `max` - The maximum length, if any.
+///
+/// # Returns
+/// * `Option<FieldError>` - The error if the length is out of bounds.
+pub fn validate_length<T: ValidateLength + ?Sized>(
+    value: &T,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Option<FieldError> {
+    let length = value.validation_length();
+    let message = match (min, max) {
+        (Some(min), Some(max)) if length < min || length > max => {
+            format!("Length must be between {} and {}", min, max)
+        }
+        (Some(min), None) if length < min => format!("Length must be at least {}", min),
+        (None, Some(max)) if length > max => format!("Length must be at most {}", max),
+        _ => return None,
+    };
+    let mut error = FieldError::new("length", message).with_param("actual", length);
+    if let Some(min) = min {
+        error = error.with_param("min", min);
+    }
+    if let Some(max) = max {
+        error = error.with_param("max", max);
+    }
+    Some(error)
+}
+
+/// Checks that a value is within bounds.
+///
+/// # Arguments
+/// * `value` - The value to check.
+/// * `min` - The inclusive lower bound, if any.
+/// * `max` - The inclusive upper bound, if any.
+///
+/// # Returns
+/// * `Option<FieldError>` - The error if the value is out of bounds.
+pub fn validate_range<T: PartialOrd + fmt::Display + Serialize>(
+    value: &T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Option<FieldError> {
+    let message = match (&min, &max) {
+        (Some(min), Some(max)) if value < min || value > max => {
+            format!("Value must be between {} and {}", min, max)
+        }
+        (Some(min), None) if value < min => format!("Value must be at least {}", min),
+        (None, Some(max)) if value > max => format!("Value must be at most {}", max),
+        _ => return None,
+    };
+    let mut error = FieldError::new("range", message).with_param("actual", value);
+    if let Some(min) = min {
+        error = error.with_param("min", min);
+    }
+    if let Some(max) = max {
+        error = error.with_param("max", max);
+    }
+    Some(error)
+}
+
+/// Checks that a string matches a pattern.
+///
+/// # Arguments
+/// * `value` - The string to check.
+/// * `regex` - The pattern the string has to match.
+///
+/// # Returns
+/// * `Option<FieldError>` - The error if the string does not match.
+#[cfg(feature = "regex")]
+pub fn validate_regex(value: &str, regex: &Regex) -> Option<FieldError> {
+    if regex.is_match(value) {
+        return None;
+    }
+    Some(
+        FieldError::new("regex", "Value does not match the expected format")
+            .with_param("pattern", regex.as_str()),
+    )
+}
+
+/// Checks that a string is an email address. The check is deliberately loose (a local part, an
+/// `@` and a domain with a dot) as the only real check is sending an email.
+///
+/// # Arguments
+/// * `value` - The string to check.
+///
+/// # Returns
+/// * `Option<FieldError>` - The error if the string is not an email address.
+pub fn validate_email(value: &str) -> Option<FieldError> {
+    let valid = match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !value.chars().any(char::is_whitespace)
+                && !domain.contains('@')
+                && domain.contains('.')
+                && domain.split('.').all(|label| !label.is_empty())
+        }
+        None => false,
+    };
+    if valid {
+        None
+    } else {
+        Some(FieldError::new(
+            "email",
+            "Value must be a valid email address",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_admin(value: &str) -> Result<(), FieldError> {
+        if value == "admin" {
+            return Err(FieldError::new("reserved", "Username is reserved"));
+        }
+        Ok(())
+    }
+
+    #[derive(Validate)]
+    struct Address {
+        #[validate(length(min = 1))]
+        street: String,
+    }
+
+    #[derive(Validate)]
+    struct NewUser {
+        #[validate(length(min = 3, max = 20), custom = "not_admin")]
+        username: String,
+        #[validate(email)]
+        email: Option<String>,
+        #[validate(range(min = 13, max = 150))]
+        age: u8,
+        #[validate(nested)]
+        addresses: Vec<Address>,
+        #[validate(length(min = 1), nested)]
+        previous_addresses: Option<Vec<Address>>,
+    }
+
+    #[test]
+    fn test_derive_collects_all_field_errors() {
+        let user = NewUser {
+            username: "admin".to_string(),
+            email: Some("admin@localhost".to_string()),
+            age: 9,
+            addresses: vec![
+                Address {
+                    street: "1 Main Street".to_string(),
+                },
+                Address {
+                    street: String::new(),
+                },
+            ],
+            previous_addresses: Some(Vec::new()),
+        };
+        let errors = user.validate().unwrap_err();
+        let fields = errors
+            .errors()
+            .iter()
+            .map(|error| (error.path.as_str(), error.code.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("username", "reserved"),
+                ("email", "email"),
+                ("age", "range"),
+                ("addresses[1].street", "length"),
+                ("previous_addresses", "length"),
+            ]
+        );
+        assert_eq!(errors.errors()[2].params["min"], json!(13));
+
+        let error = CruxmontError::from(errors);
+        assert_eq!(error.status, CurxmontErrorStatus::UnprocessableEntity);
+        assert_eq!(
+            error.details.unwrap()["errors"][3]["path"],
+            json!("addresses[1].street")
+        );
+    }
+
+    #[test]
+    fn test_valid_value_passes() {
+        let user = NewUser {
+            username: "maxwell".to_string(),
+            email: None,
+            age: 30,
+            addresses: Vec::new(),
+            previous_addresses: None,
+        };
+        assert!(user.validate().is_ok());
+        assert!(validate_email("max@example.com").is_none());
+        assert!(validate_email("max@@example.com").is_some());
+    }
+}
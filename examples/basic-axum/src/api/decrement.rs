@@ -15,6 +15,7 @@
 //! - Errors are propagated as `CruxmontError` for consistency with the application's error handling.
 
 use crate::dal::tx_definitions::{DecreaseCount, GetCount};
+use crate::env::AppEnv;
 use cruxmont::dal::connections::sqlx_postgres::YieldPostGresPool;
 use cruxmont::errors::CruxmontError;
 use axum::{extract::Json, http::StatusCode, response::IntoResponse};
@@ -22,26 +23,18 @@ use axum::{extract::Json, http::StatusCode, response::IntoResponse};
 /// Decreases the count for id = 1 by 1 and returns the updated count.
 ///
 /// # Arguments
-/// - None: Uses the database pool provided by the `YieldPostGresPool` trait.
+/// - None: Uses the database pool of the environment.
 ///
 /// # Returns
 /// - `Ok(i32)`: The updated count value.
 /// - `Err(CruxmontError)`: If the operation fails (e.g., row not found or database error).
-pub async fn decrease_and_get_count<X, Y>() -> Result<impl IntoResponse, CruxmontError>
-where
-    X: DecreaseCount + GetCount,
-    Y: YieldPostGresPool,
-{
-    let pool = Y::yield_pool();
+pub async fn decrease_and_get_count<E: AppEnv>() -> Result<impl IntoResponse, CruxmontError> {
+    let pool = E::Pool::yield_pool();
     // Decrease the count by 1 for id = 1
-    X::decrease_count(1, pool).await.map_err(|e| {
-        CruxmontError::unknown(format!("Failed to decrease count: {}", e))
-    })?;
+    E::Dal::decrease_count(1, pool).await?;
 
     // Retrieve the updated count
-    let count = X::get_count(1, pool).await.map_err(|e| {
-        CruxmontError::unknown(format!("Failed to retrieve count: {}", e))
-    })?;
+    let count = E::Dal::get_count(1, pool).await?;
 
     Ok((StatusCode::OK, Json(count)))
 }
@@ -49,19 +42,60 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::env::TestEnv;
+    use cruxmont::config::EnvConfig;
     use cruxmont::dal::connections::sqlx_postgres::SqlxPostGresDescriptor;
+    use cruxmont::define_app_env;
     use sqlx::{Pool, Postgres};
     use cruxmont::embedded_pg_test::embedded_pg_test;
     use crate::dal::basic_migrations::run_migrations;
     use cruxmont::postgresql_embedded;
     use axum::http::StatusCode;
     use http_body_util::BodyExt;
+    use crate::dal::tx_definitions::MockCountDal;
+    use cruxmont::errors::CurxmontErrorStatus;
+
+    #[tokio::test]
+    async fn test_decrease_and_get_count_with_mock() {
+        let mock = MockCountDal::new();
+        mock.decrease_count.returns(());
+        mock.get_count.returns(-1);
+
+        let result = mock
+            .run(decrease_and_get_count::<TestEnv>())
+            .await
+            .expect("Failed to decrease and get count");
+        assert_eq!(result.into_response().status(), StatusCode::OK);
+        assert_eq!(mock.decrease_count.calls()[0].arg::<i32>(0), Some(&1));
+        mock.get_count.assert_called_times(1);
+    }
+
+    #[tokio::test]
+    async fn test_decrease_and_get_count_maps_missing_row() {
+        let mock = MockCountDal::new();
+        mock.decrease_count.returns_once(Err(sqlx::Error::RowNotFound));
+
+        let error = mock
+            .run(decrease_and_get_count::<TestEnv>())
+            .await
+            .err()
+            .expect("a missing row is an error");
+        assert_eq!(error.status, CurxmontErrorStatus::NotFound);
+        mock.get_count.assert_called_times(0);
+    }
 
     #[embedded_pg_test]
     async fn test_decrease_and_get_count() {
         // The SQLX_POSTGRES_POOL is provided by the test macro
-        let pool: &Pool<Postgres> = &*SQLX_POSTGRES_TEST_POOL;
+        let pool: &Pool<Postgres> = &SQLX_POSTGRES_TEST_POOL;
         run_migrations(pool).await.expect("run migrations");
+        define_app_env!(
+            impl AppEnv for EmbeddedEnv {
+                type Dal = SqlxPostGresDescriptor;
+                type Pool = TestDbHandle;
+                type Config = EnvConfig;
+            }
+        );
 
         // Initial count should be 0 (from migration)
         let initial_count = SqlxPostGresDescriptor::get_count(1, pool)
@@ -70,7 +104,7 @@ mod tests {
         assert_eq!(initial_count, 0);
 
         // Call the function to decrease and get count
-        let result = decrease_and_get_count::<SqlxPostGresDescriptor, TestDbHandle>()
+        let result = decrease_and_get_count::<EmbeddedEnv>()
             .await
             .expect("Failed to decrease and get count");
 
@@ -93,7 +127,7 @@ mod tests {
         assert_eq!(updated_count, -1);
 
         // Call again to ensure decrement works multiple times
-        let result = decrease_and_get_count::<SqlxPostGresDescriptor, TestDbHandle>()
+        let result = decrease_and_get_count::<EmbeddedEnv>()
             .await
             .expect("Failed to decrease and get count again");
 
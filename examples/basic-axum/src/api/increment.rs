@@ -7,40 +7,38 @@
 //!
 //! # Features
 //! - Increases the count for id = 1 by 1.
-//! - Retrieves the updated count value.
-//! - Uses generic traits for database operations to enable testing with mocks.
+//! - Retrieves the updated count value in the same unit of work, so concurrent requests never
+//!   see a count they did not write.
+//! - Uses generic traits for database operations so the transactions of any environment can be used.
 //!
 //! # Notes
 //! - The `increase_and_get_count` function is generic, allowing flexibility with different database implementations.
+//! - The unit of work is begun on the pool of the environment before the transactions run, so the
+//!   handler needs a database even with `TestEnv` and its mocked transactions. It is only tested
+//!   against the embedded database, `decrease_and_get_count` shows a handler tested with mocks.
 //! - Errors are propagated as `NanoServiceError` for consistency with the application's error handling.
 use crate::dal::tx_definitions::{IncreaseCount, GetCount};
+use crate::env::AppEnv;
 use cruxmont::errors::CruxmontError;
-use cruxmont::dal::connections::sqlx_postgres::YieldPostGresPool;
+use cruxmont::dal::unit_of_work::UnitOfWork;
 use axum::{extract::Json, http::StatusCode, response::IntoResponse};
 
 /// Increases the count for id = 1 by 1 and returns the updated count.
 ///
 /// # Arguments
-/// - `pool`: The PostgreSQL connection pool.
+/// - None: Begins a unit of work on the database pool of the environment.
 ///
 /// # Returns
 /// - `Ok(i32)`: The updated count value.
 /// - `Err(NanoServiceError)`: If the operation fails (e.g., row not found or database error).
-pub async fn increase_and_get_count<X, Y>() -> Result<impl IntoResponse, CruxmontError>
-where
-    X: IncreaseCount + GetCount,
-    Y: YieldPostGresPool
-{
-    let pool = Y::yield_pool();
+pub async fn increase_and_get_count<E: AppEnv>() -> Result<impl IntoResponse, CruxmontError> {
+    let mut unit = UnitOfWork::begin::<E::Pool>().await?;
     // Increase the count by 1 for id = 1
-    X::increase_count(1, pool).await.map_err(|e| {
-        CruxmontError::unknown(format!("Failed to increase count: {}", e))
-    })?;
+    E::Dal::increase_count(1, &mut unit).await?;
 
-    // Retrieve the updated count
-    let count = X::get_count(1, pool).await.map_err(|e| {
-        CruxmontError::unknown(format!("Failed to retrieve count: {}", e))
-    })?;
+    // Retrieve the updated count before committing both calls at once
+    let count = E::Dal::get_count(1, &mut unit).await?;
+    unit.commit().await?;
 
     Ok((StatusCode::OK, Json(count)))
 }
@@ -49,7 +47,9 @@ where
 mod tests {
     
     use super::*;
+    use cruxmont::config::EnvConfig;
     use cruxmont::dal::connections::sqlx_postgres::SqlxPostGresDescriptor;
+    use cruxmont::define_app_env;
     use sqlx::{Pool, Postgres};
     use cruxmont::embedded_pg_test::embedded_pg_test;
     use crate::dal::basic_migrations::run_migrations;
@@ -60,8 +60,15 @@ mod tests {
     #[embedded_pg_test]
     async fn test_increase_and_get_count() {
         // The SQLX_POSTGRES_POOL is provided by the test macro
-        let pool: &Pool<Postgres> = &*SQLX_POSTGRES_TEST_POOL;
+        let pool: &Pool<Postgres> = &SQLX_POSTGRES_TEST_POOL;
         run_migrations(pool).await.expect("run migrations");
+        define_app_env!(
+            impl AppEnv for EmbeddedEnv {
+                type Dal = SqlxPostGresDescriptor;
+                type Pool = TestDbHandle;
+                type Config = EnvConfig;
+            }
+        );
 
         // Initial count should be 0 (from migration)
         let initial_count = SqlxPostGresDescriptor::get_count(1, pool)
@@ -70,7 +77,7 @@ mod tests {
         assert_eq!(initial_count, 0);
 
         // Call the function to increase and get count
-        let result = increase_and_get_count::<SqlxPostGresDescriptor, TestDbHandle>()
+        let result = increase_and_get_count::<EmbeddedEnv>()
             .await
             .expect("Failed to increase and get count");
 
@@ -94,7 +101,7 @@ mod tests {
         assert_eq!(updated_count, 1);
 
         // Call again to ensure increment works multiple times
-        let result = increase_and_get_count::<SqlxPostGresDescriptor, TestDbHandle>()
+        let result = increase_and_get_count::<EmbeddedEnv>()
             .await
             .expect("Failed to increase and get count again");
         
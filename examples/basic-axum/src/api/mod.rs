@@ -2,24 +2,42 @@ pub mod decrement;
 pub mod increment;
 pub mod get;
 
-// The LivePostGresPool connects to the live DB
-use cruxmont::dal::connections::sqlx_postgres::{LivePostGresPool, SqlxPostGresDescriptor};
-use axum::{
-    Router,
-    routing::get,
-};
+use crate::env::AppEnv;
+use cruxmont::routes;
 
+routes!(
+    /// Registers the counter routes for an environment, such as `LiveEnv` for the live DB.
+    pub fn counter_factory<E: AppEnv>;
+    GET "/increment" => increment::increase_and_get_count,
+    GET "/decrement" => decrement::decrease_and_get_count,
+);
 
-pub fn counter_factory(app: Router) -> Router {
-    app.route(
-        "/increment",
-        get(increment::increase_and_get_count::<SqlxPostGresDescriptor, LivePostGresPool>),
-    )
-    .route(
-        "/decrement",
-        get(decrement::decrease_and_get_count::<
-            SqlxPostGresDescriptor,
-            LivePostGresPool,
-        >),
-    )
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dal::tx_definitions::MockCountDal;
+    use crate::env::TestEnv;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_decrement_route_runs_the_mocked_transactions() {
+        // the routes of the live router are registered with the mocked transactions
+        let app = counter_factory::<TestEnv>(axum::Router::new());
+        let mock = MockCountDal::new();
+        mock.decrease_count.returns(());
+        mock.get_count.returns(-1);
+
+        let request = Request::builder()
+            .uri("/decrement")
+            .body(Body::empty())
+            .unwrap();
+        let response = mock.run(app.oneshot(request)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<i32>(&body).unwrap(), -1);
+        mock.decrease_count.assert_called_times(1);
+    }
+}
@@ -3,6 +3,7 @@ use crate::dal::tx_definitions::{
     IncreaseCount, DecreaseCount, GetCount,
 };
 use cruxmont::db_tx::db_transaction;
+use cruxmont::dal::unit_of_work::PgAcquire;
 use sqlx::{Pool, Postgres};
 
 /// Implements the `IncreaseCount` trait for the `SqlxPostGresDescriptor`.
@@ -11,13 +12,13 @@ use sqlx::{Pool, Postgres};
 ///
 /// # Arguments
 /// - `number`: The amount to increase the count by.
-/// - `pool`: The PostgreSQL connection pool.
+/// - `executor`: The PostgreSQL pool, connection or unit of work.
 ///
 /// # Returns
 /// - `Ok(())`: If the update operation is successful.
 /// - `Err`: If the row with id = 1 is not found or the update fails.
 #[db_transaction(SqlxPostGresDescriptor, IncreaseCount)]
-async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
+async fn increase_count<A: PgAcquire>(number: i32, executor: A) -> () {
     let query = r#"
         UPDATE counts
         SET value = value + $1
@@ -25,9 +26,10 @@ async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
         RETURNING id
     "#;
 
+    let mut connection = executor.acquire().await?;
     let result = sqlx::query(query)
         .bind(number)
-        .fetch_optional(pool)
+        .fetch_optional(&mut *connection)
         .await?;
 
     if result.is_none() {
@@ -39,16 +41,17 @@ async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
 
 /// Implements the `DecreaseCount` trait for the `SqlxPostGresDescriptor`.
 ///
-/// Decreases the count value for the row with id = 1 by the specified number.
+/// Decreases the count value for the row with id = 1 by the specified number in a serializable
+/// transaction, rolled back if the row is not found and retried on serialization failures.
 ///
 /// # Arguments
 /// - `number`: The amount to decrease the count by.
-/// - `pool`: The PostgreSQL connection pool.
+/// - `pool`: The PostgreSQL connection pool the transaction `tx` is begun on.
 ///
 /// # Returns
 /// - `Ok(())`: If the update operation is successful.
 /// - `Err`: If the row with id = 1 is not found or the update fails.
-#[db_transaction(SqlxPostGresDescriptor, DecreaseCount)]
+#[db_transaction(SqlxPostGresDescriptor, DecreaseCount, atomic, isolation = "serializable", retry)]
 async fn decrease_count(number: i32, pool: &Pool<Postgres>) -> () {
     let query = r#"
         UPDATE counts
@@ -59,7 +62,7 @@ async fn decrease_count(number: i32, pool: &Pool<Postgres>) -> () {
 
     let result = sqlx::query(query)
         .bind(number)
-        .fetch_optional(pool)
+        .fetch_optional(&mut **tx)
         .await?;
 
     if result.is_none() {
@@ -75,22 +78,23 @@ async fn decrease_count(number: i32, pool: &Pool<Postgres>) -> () {
 ///
 /// # Arguments
 /// - `number`: The id of the count (fixed to 1 in this case).
-/// - `pool`: The PostgreSQL connection pool.
+/// - `executor`: The PostgreSQL pool, connection or unit of work.
 ///
 /// # Returns
 /// - `Ok(i32)`: The current count value.
 /// - `Err`: If the row with id = 1 is not found.
 #[db_transaction(SqlxPostGresDescriptor, GetCount)]
-async fn get_count(number: i32, pool: &Pool<Postgres>) -> i32 {
+async fn get_count<A: PgAcquire>(number: i32, executor: A) -> i32 {
     let query = r#"
         SELECT value
         FROM counts
         WHERE id = $1
     "#;
 
+    let mut connection = executor.acquire().await?;
     let row: (i32,) = sqlx::query_as(query)
         .bind(number)
-        .fetch_one(pool)
+        .fetch_one(&mut *connection)
         .await?;
 
     Ok(row.0)
@@ -1,9 +1,14 @@
 use cruxmont::define_dal_transactions;
+use cruxmont::dal::unit_of_work::PgAcquire;
 use sqlx::{Pool, Postgres};
 
 
 define_dal_transactions!(
-    IncreaseCount => increase_count(number: i32, pool: &Pool<Postgres>) -> (),
+    // lets the handlers be unit tested without a database
+    #[cfg(test)]
+    mock MockCountDal;
+    // generic over the executor so they can share a unit of work
+    IncreaseCount => increase_count<A: PgAcquire>(number: i32, executor: A) -> (),
     DecreaseCount => decrease_count(number: i32, pool: &Pool<Postgres>) -> (),
-    GetCount => get_count(number: i32, pool: &Pool<Postgres>) -> i32,
+    GetCount => get_count<A: PgAcquire>(number: i32, executor: A) -> i32,
 );
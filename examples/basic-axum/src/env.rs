@@ -0,0 +1,50 @@
+//! Defines the environments the counter handlers run in.
+//!
+//! # Notes
+//! - `LiveEnv` runs the transactions on the live DB and reads the config from the environment.
+//! - `TestEnv` runs the mocked transactions so handlers can be unit tested without a DB. Its pool
+//!   never connects, so handlers beginning a `UnitOfWork` on it fail and are tested on a DB.
+use crate::dal::tx_definitions::{DecreaseCount, GetCount, IncreaseCount};
+use cruxmont::config::EnvConfig;
+use cruxmont::dal::connections::sqlx_postgres::{LivePostGresPool, SqlxPostGresDescriptor};
+use cruxmont::define_app_env;
+
+define_app_env!(
+    /// The descriptor, pool and config used by the counter handlers.
+    pub trait AppEnv {
+        type Dal: IncreaseCount + DecreaseCount + GetCount;
+    }
+
+    LiveEnv {
+        type Dal = SqlxPostGresDescriptor;
+        type Pool = LivePostGresPool;
+        type Config = EnvConfig;
+    }
+
+    #[cfg(test)]
+    TestEnv {
+        type Dal = crate::dal::tx_definitions::MockCountDal;
+        type Pool = unused_pool::UnusedPool;
+        type Config = EnvConfig;
+    }
+);
+
+#[cfg(test)]
+mod unused_pool {
+    use cruxmont::dal::connections::sqlx_postgres::YieldPostGresPool;
+    use sqlx::{Pool, Postgres};
+
+    /// Yields a pool that is never connected as the mocked transactions do not use it.
+    pub struct UnusedPool;
+
+    impl YieldPostGresPool for UnusedPool {
+        fn yield_pool() -> &'static Pool<Postgres> {
+            static POOL: std::sync::LazyLock<Pool<Postgres>> = std::sync::LazyLock::new(|| {
+                sqlx::postgres::PgPoolOptions::new()
+                    .connect_lazy("postgres://localhost/unused")
+                    .expect("parse the URL")
+            });
+            &POOL
+        }
+    }
+}
@@ -0,0 +1,290 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Data, DeriveInput, Expr, ExprLit, Fields, GenericArgument, Lit, LitStr, Meta,
+    PathArguments, Result, Type, parse_macro_input,
+};
+
+/// The options of the struct declared with `#[config(...)]`.
+#[derive(Default)]
+struct StructOptions {
+    prefix: Option<LitStr>,
+}
+
+/// The options of a field declared with `#[config(...)]`.
+#[derive(Default)]
+struct FieldOptions {
+    env: Option<LitStr>,
+    default: Option<String>,
+    nested: bool,
+    prefix: Option<LitStr>,
+}
+
+fn parse_struct_options(attrs: &[Attribute]) -> Result<StructOptions> {
+    let mut options = StructOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("config")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                options.prefix = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported config option, expected `prefix`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn parse_field_options(attrs: &[Attribute]) -> Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    // the attributes setting `env` or `default` and `prefix`, the errors below point at them
+    let mut value_attr = None;
+    let mut prefix_attr = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("config")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("env") {
+                options.env = Some(meta.value()?.parse()?);
+                value_attr = Some(attr);
+            } else if meta.path.is_ident("default") {
+                // strings are used as is, other literals such as `default = 5` are stringified
+                let default = match meta.value()?.parse::<Lit>()? {
+                    Lit::Str(value) => value.value(),
+                    Lit::Int(value) => value.base10_digits().to_string(),
+                    Lit::Float(value) => value.base10_digits().to_string(),
+                    Lit::Bool(value) => value.value.to_string(),
+                    other => return Err(syn::Error::new(other.span(), "unsupported default")),
+                };
+                options.default = Some(default);
+                value_attr = Some(attr);
+            } else if meta.path.is_ident("nested") {
+                options.nested = true;
+            } else if meta.path.is_ident("prefix") {
+                options.prefix = Some(meta.value()?.parse()?);
+                prefix_attr = Some(attr);
+            } else {
+                return Err(meta.error(
+                    "unsupported config option, expected one of `env`, `default`, `nested` or `prefix`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+    if let (true, Some(attr)) = (options.nested, value_attr) {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "`nested` fields cannot have an `env` or `default`",
+        ));
+    }
+    if let (false, Some(attr)) = (options.nested, prefix_attr) {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "`prefix` is only supported on `nested` fields",
+        ));
+    }
+    Ok(options)
+}
+
+/// Joins the `///` doc comment of a field into the description of its variable.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) => match &name_value.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(doc.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join(" "))
+    }
+}
+
+/// Gets the type wrapped in an `Option`, None if the type is not an `Option`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first() {
+        Some(GenericArgument::Type(inner)) => Some(inner),
+        _ => None,
+    }
+}
+
+fn optional_string(value: &Option<String>) -> TokenStream2 {
+    match value {
+        Some(value) => quote! { ::core::option::Option::Some(#value) },
+        None => quote! { ::core::option::Option::<&'static str>::None },
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "#[derive(CruxmontConfig)] is only supported on structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[derive(CruxmontConfig)] is only supported on structs",
+            ));
+        }
+    };
+    let struct_options = parse_struct_options(&input.attrs)?;
+    let struct_prefix = struct_options
+        .prefix
+        .map(|prefix| prefix.value())
+        .unwrap_or_default();
+
+    let mut loads = Vec::new();
+    let mut variables = Vec::new();
+    let mut bindings = Vec::new();
+    let mut members = Vec::new();
+    for field in fields {
+        let options = parse_field_options(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named field");
+        let binding = format_ident!("__{}", ident);
+        let ty = &field.ty;
+        let upper_name = ident.to_string().trim_start_matches("r#").to_uppercase();
+
+        if options.nested {
+            let nested_prefix = options
+                .prefix
+                .map(|prefix| prefix.value())
+                .unwrap_or_else(|| format!("{}_", upper_name));
+            let nested_prefix = format!("{}{}", struct_prefix, nested_prefix);
+            loads.push(quote! {
+                let #binding = match <#ty as ::cruxmont::config::CruxmontConfig>::load_with_prefix::<__T>(
+                    &::std::format!("{}{}", prefix, #nested_prefix),
+                ) {
+                    ::core::result::Result::Ok(value) => ::core::option::Option::Some(value),
+                    ::core::result::Result::Err(error) => {
+                        errors.extend(error);
+                        ::core::option::Option::None
+                    }
+                };
+            });
+            variables.push(quote! {
+                variables.extend(<#ty as ::cruxmont::config::CruxmontConfig>::variables(
+                    &::std::format!("{}{}", prefix, #nested_prefix),
+                ));
+            });
+        } else {
+            let env = options
+                .env
+                .map(|env| env.value())
+                .unwrap_or_else(|| upper_name.clone());
+            let env = format!("{}{}", struct_prefix, env);
+            let default = optional_string(&options.default);
+            let description = optional_string(&doc_comment(&field.attrs));
+            let (parsed_ty, required) = match option_inner(ty) {
+                Some(inner) => (inner, false),
+                None => (ty, true),
+            };
+            let required_without_default = required && options.default.is_none();
+            let load = if required {
+                quote! {
+                    ::cruxmont::config::typed::load_required::<__T, #parsed_ty>(&name, #default, &mut errors)
+                }
+            } else {
+                quote! {
+                    ::cruxmont::config::typed::load_optional::<__T, #parsed_ty>(&name, #default, &mut errors)
+                }
+            };
+            loads.push(quote! {
+                let #binding = {
+                    let name = ::std::format!("{}{}", prefix, #env);
+                    #load
+                };
+            });
+            variables.push(quote! {
+                variables.push(::cruxmont::config::typed::ConfigVariable {
+                    name: ::std::format!("{}{}", prefix, #env),
+                    default: #default.map(::std::string::ToString::to_string),
+                    required: #required_without_default,
+                    description: #description.map(::std::string::ToString::to_string),
+                });
+            });
+        }
+        bindings.push(binding);
+        members.push(ident);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let construct = if bindings.is_empty() {
+        quote! { ::core::result::Result::Ok(Self {}) }
+    } else {
+        quote! {
+            match (#(#bindings,)*) {
+                (#(::core::option::Option::Some(#bindings),)*) if errors.is_empty() => {
+                    ::core::result::Result::Ok(Self { #(#members: #bindings,)* })
+                }
+                _ => ::core::result::Result::Err(errors),
+            }
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::cruxmont::config::CruxmontConfig for #name #ty_generics #where_clause {
+            fn load_with_prefix<__T: ::cruxmont::config::GetConfigVariable>(
+                prefix: &str,
+            ) -> ::core::result::Result<Self, ::cruxmont::config::typed::ConfigError> {
+                #[allow(unused_mut)]
+                let mut errors = ::cruxmont::config::typed::ConfigError::new();
+                #(#loads)*
+                #construct
+            }
+
+            fn variables(prefix: &str) -> ::std::vec::Vec<::cruxmont::config::typed::ConfigVariable> {
+                #[allow(unused_mut)]
+                let mut variables = ::std::vec::Vec::new();
+                #(#variables)*
+                variables
+            }
+        }
+    })
+}
+
+/// Derives `cruxmont::config::CruxmontConfig` so the struct is loaded from any
+/// `GetConfigVariable` implementation.
+///
+/// # Supported options
+/// * `#[config(prefix = "APP_")]` - On the struct, prefixes the variables of every field.
+/// * `#[config(env = "DATABASE_URL")]` - The name of the variable, defaults to the field name in
+///   upper case.
+/// * `#[config(default = "8080")]` - The value used when the variable is missing.
+/// * `#[config(nested)]` - The field is a struct deriving `CruxmontConfig`, its variables are
+///   prefixed with the field name in upper case and `_` unless `prefix = "..."` is given.
+///
+/// Values are parsed with `FromStr`, `Option` fields are `None` when the variable is missing
+/// and the `///` doc comment of a field is used as its description in `env_example`.
+#[proc_macro_derive(CruxmontConfig, attributes(config))]
+pub fn derive_cruxmont_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(error) => TokenStream::from(error.to_compile_error()),
+    }
+}
@@ -0,0 +1,193 @@
+//! Parses the `cache(...)` and `invalidates(...)` options and wraps the body of a transaction
+//! with them.
+use crate::kw;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Expr, LitStr, Path, Result, Token, parse::Parse, parse::ParseStream};
+
+/// Parses a list of tags, `["counts", "users"]`.
+fn parse_tags(input: ParseStream) -> Result<Vec<LitStr>> {
+    let content;
+    syn::bracketed!(content in input);
+    let tags = content.parse_terminated(|tag| tag.parse::<LitStr>(), Token![,])?;
+    Ok(tags.into_iter().collect())
+}
+
+/// Parses a duration such as `"500ms"`, `"30s"`, `"5m"` or `"1h"` into milliseconds.
+fn parse_ttl(ttl: &LitStr) -> Result<u64> {
+    let value = ttl.value();
+    let value = value.trim();
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let multiplier = match unit.trim() {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => 0,
+    };
+    let millis = amount
+        .parse::<u64>()
+        .ok()
+        .filter(|amount| *amount > 0 && multiplier > 0)
+        .and_then(|amount| amount.checked_mul(multiplier));
+    match millis {
+        Some(millis) => Ok(millis),
+        None => Err(syn::Error::new_spanned(
+            ttl,
+            "expected a duration such as \"500ms\", \"30s\", \"5m\" or \"1h\"",
+        )),
+    }
+}
+
+/// The options of `cache(ttl = "30s", key = format!("count:{}", id), tags = ["counts"])`.
+pub(crate) struct CacheOptions {
+    ttl_ms: u64,
+    key: Expr,
+    tags: Vec<LitStr>,
+}
+
+impl Parse for CacheOptions {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut ttl_ms = None;
+        let mut key = None;
+        let mut tags = Vec::new();
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::ttl) {
+                input.parse::<kw::ttl>()?;
+                input.parse::<Token![=]>()?;
+                ttl_ms = Some(parse_ttl(&input.parse()?)?);
+            } else if lookahead.peek(kw::key) {
+                input.parse::<kw::key>()?;
+                input.parse::<Token![=]>()?;
+                key = Some(input.parse()?);
+            } else if lookahead.peek(kw::tags) {
+                input.parse::<kw::tags>()?;
+                input.parse::<Token![=]>()?;
+                tags = parse_tags(input)?;
+            } else {
+                return Err(lookahead.error());
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        let (Some(ttl_ms), Some(key)) = (ttl_ms, key) else {
+            return Err(input.error(
+                "cache needs a ttl and a key, such as cache(ttl = \"30s\", key = format!(\"count:{}\", id))",
+            ));
+        };
+        Ok(CacheOptions { ttl_ms, key, tags })
+    }
+}
+
+/// A key removed by a write, `GetCount::get_count => format!("count:{}", id)`. The key is
+/// namespaced with the name of the cached transaction it was stored by.
+struct InvalidatedKey {
+    transaction: String,
+    key: Expr,
+}
+
+impl Parse for InvalidatedKey {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let path: Path = input.parse()?;
+        let segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<String>>();
+        // the name of a transaction is its trait and function, such as `GetCount::get_count`
+        let [.., trait_name, fn_name] = segments.as_slice() else {
+            return Err(syn::Error::new_spanned(
+                &path,
+                "expected the cached transaction of the key, such as `GetCount::get_count`",
+            ));
+        };
+        let transaction = format!("{}::{}", trait_name, fn_name);
+        input.parse::<Token![=>]>()?;
+        Ok(InvalidatedKey {
+            transaction,
+            key: input.parse()?,
+        })
+    }
+}
+
+/// The options of `invalidates(keys = [GetCount::get_count => format!("count:{}", id)], tags =
+/// ["counts"])`.
+pub(crate) struct InvalidateOptions {
+    keys: Vec<InvalidatedKey>,
+    tags: Vec<LitStr>,
+}
+
+impl Parse for InvalidateOptions {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut keys = Vec::new();
+        let mut tags = Vec::new();
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::keys) {
+                input.parse::<kw::keys>()?;
+                input.parse::<Token![=]>()?;
+                let content;
+                syn::bracketed!(content in input);
+                let parsed = content.parse_terminated(InvalidatedKey::parse, Token![,])?;
+                keys = parsed.into_iter().collect();
+            } else if lookahead.peek(kw::tags) {
+                input.parse::<kw::tags>()?;
+                input.parse::<Token![=]>()?;
+                tags = parse_tags(input)?;
+            } else {
+                return Err(lookahead.error());
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        if keys.is_empty() && tags.is_empty() {
+            return Err(input.error("invalidates needs keys or tags to invalidate"));
+        }
+        Ok(InvalidateOptions { keys, tags })
+    }
+}
+
+/// Wraps the future of a transaction so a write invalidates its keys and tags once it returns
+/// `Ok`. The keys are computed before the parameters move into the future.
+pub(crate) fn invalidate(options: &InvalidateOptions, body: TokenStream) -> TokenStream {
+    let transactions = options.keys.iter().map(|key| &key.transaction);
+    let keys = options.keys.iter().map(|key| &key.key);
+    let tags = &options.tags;
+    quote! {
+        {
+            let __cruxmont_invalidated_keys: ::std::vec::Vec<(&'static str, ::std::string::String)> =
+                ::std::vec![#((#transactions, ::std::string::ToString::to_string(&(#keys)))),*];
+            let __cruxmont_write = #body;
+            async move {
+                let __cruxmont_result = __cruxmont_write.await;
+                if __cruxmont_result.is_ok() {
+                    ::cruxmont::dal::cache::invalidate(&__cruxmont_invalidated_keys, &[#(#tags),*]);
+                }
+                __cruxmont_result
+            }
+        }
+    }
+}
+
+/// Wraps the future of a transaction so it is answered from the cache while its key is fresh.
+pub(crate) fn cache(options: &CacheOptions, name: &str, body: TokenStream) -> TokenStream {
+    let CacheOptions { ttl_ms, key, tags } = options;
+    quote! {
+        {
+            let __cruxmont_cache_key = ::std::string::ToString::to_string(&(#key));
+            ::cruxmont::dal::cache::cached(
+                #name,
+                __cruxmont_cache_key,
+                ::core::time::Duration::from_millis(#ttl_ms),
+                &[#(#tags),*],
+                #body,
+            )
+        }
+    }
+}
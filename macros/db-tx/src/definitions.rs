@@ -0,0 +1,326 @@
+//! Parses and expands `define_dal_transactions!`.
+use crate::{result_parts, transaction_output};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{ToTokens, quote};
+use std::collections::HashSet;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    Attribute, GenericParam, Generics, Ident, Result, Token, Type, WhereClause, WherePredicate,
+    braced, parenthesized,
+};
+
+syn::custom_keyword!(mock);
+
+/// The `mock MockName;` header asking for a mock of every trait.
+struct MockHeader {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+}
+
+/// A parameter of a transaction, `name: Type`.
+struct Param {
+    ident: Ident,
+    ty: Type,
+}
+
+impl Parse for Param {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        Ok(Param { ident, ty })
+    }
+}
+
+/// The signature of a transaction, `name<generics>(params) -> Output where ...`.
+struct Signature {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    generics: Generics,
+    params: Punctuated<Param, Token![,]>,
+    output: Type,
+}
+
+impl Parse for Signature {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let ident = input.parse()?;
+        let mut generics: Generics = input.parse()?;
+        let content;
+        parenthesized!(content in input);
+        let params = content.parse_terminated(Param::parse, Token![,])?;
+        let output = if input.peek(Token![->]) {
+            input.parse::<Token![->]>()?;
+            input.parse()?
+        } else {
+            syn::parse_quote! { () }
+        };
+        if input.peek(Token![where]) {
+            generics.where_clause = Some(parse_where_clause(input)?);
+        }
+        Ok(Signature {
+            attrs,
+            ident,
+            generics,
+            params,
+            output,
+        })
+    }
+}
+
+/// Parses a where clause. The comma separating two transactions would otherwise be read as
+/// the separator of two predicates, so a comma is only consumed when a predicate follows it.
+fn parse_where_clause(input: ParseStream) -> Result<WhereClause> {
+    let where_token = input.parse::<Token![where]>()?;
+    let mut predicates = Punctuated::<WherePredicate, Token![,]>::new();
+    loop {
+        predicates.push_value(input.parse()?);
+        let fork = input.fork();
+        if fork.parse::<Token![,]>().is_err() || fork.parse::<WherePredicate>().is_err() {
+            break;
+        }
+        predicates.push_punct(input.parse()?);
+    }
+    Ok(WhereClause {
+        where_token,
+        predicates,
+    })
+}
+
+/// A trait and its transactions.
+struct TraitDefinition {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    functions: Vec<Signature>,
+}
+
+/// The input of `define_dal_transactions!`.
+pub(crate) struct Definitions {
+    mock: Option<MockHeader>,
+    traits: Vec<TraitDefinition>,
+}
+
+impl Parse for Definitions {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut attrs = input.call(Attribute::parse_outer)?;
+        let mut mock_header = None;
+        if input.peek(mock) && input.peek2(Ident) {
+            input.parse::<mock>()?;
+            let ident = input.parse()?;
+            input.parse::<Token![;]>()?;
+            mock_header = Some(MockHeader {
+                attrs: std::mem::take(&mut attrs),
+                ident,
+            });
+        }
+        let mut traits = Vec::new();
+        while !input.is_empty() {
+            attrs.extend(input.call(Attribute::parse_outer)?);
+            let ident: Ident = input.parse()?;
+            let functions = if input.peek(Token![=>]) {
+                input.parse::<Token![=>]>()?;
+                vec![input.parse::<Signature>()?]
+            } else if input.peek(syn::token::Brace) {
+                let content;
+                braced!(content in input);
+                content
+                    .parse_terminated(Signature::parse, Token![,])?
+                    .into_iter()
+                    .collect()
+            } else {
+                return Err(input.error(
+                    "expected `=> function(...) -> T` or `{ function(...) -> T, ... }` after the trait name",
+                ));
+            };
+            traits.push(TraitDefinition {
+                attrs: std::mem::take(&mut attrs),
+                ident,
+                functions,
+            });
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        if let Some(attr) = attrs.first() {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "attributes must be followed by a trait",
+            ));
+        }
+        Ok(Definitions {
+            mock: mock_header,
+            traits,
+        })
+    }
+}
+
+/// Splits the output of a transaction into its value and error types.
+fn value_and_error(output: &Type) -> (TokenStream, TokenStream) {
+    match result_parts(output) {
+        Some((value, error)) => (value.to_token_stream(), error.to_token_stream()),
+        None => (output.to_token_stream(), quote! { sqlx::Error }),
+    }
+}
+
+/// Checks if a type borrows or uses one of the generics of its function.
+fn is_borrowed_or_generic(tokens: TokenStream, generics: &HashSet<String>) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Punct(punct) => punct.as_char() == '&' || punct.as_char() == '\'',
+        TokenTree::Ident(ident) => generics.contains(&ident.to_string()),
+        TokenTree::Group(group) => is_borrowed_or_generic(group.stream(), generics),
+        TokenTree::Literal(_) => false,
+    })
+}
+
+fn expand_trait(definition: &TraitDefinition) -> TokenStream {
+    let attrs = &definition.attrs;
+    let ident = &definition.ident;
+    let functions = definition.functions.iter().map(|function| {
+        let attrs = &function.attrs;
+        let name = &function.ident;
+        let generics = &function.generics;
+        let where_clause = &function.generics.where_clause;
+        let params = function.params.iter().map(|param| {
+            let (ident, ty) = (&param.ident, &param.ty);
+            quote! { #ident: #ty }
+        });
+        let output = transaction_output(&function.output);
+        quote! {
+            #(#attrs)*
+            fn #name #generics (#(#params),*) -> impl std::future::Future<Output = #output> + Send #where_clause;
+        }
+    });
+    quote! {
+        #(#attrs)*
+        pub trait #ident {
+            #(#functions)*
+        }
+    }
+}
+
+fn expand_mock(header: &MockHeader, traits: &[TraitDefinition]) -> Result<TokenStream> {
+    let attrs = &header.attrs;
+    let mock_ident = &header.ident;
+    let mut seen = HashSet::new();
+    let mut fields = Vec::new();
+    let mut constructors = Vec::new();
+    let mut impls = Vec::new();
+    for definition in traits {
+        let mut methods = Vec::new();
+        for function in &definition.functions {
+            let name = &function.ident;
+            if !seen.insert(name.to_string()) {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "function names must be unique across the traits of a mock",
+                ));
+            }
+            let generic_names = function
+                .generics
+                .params
+                .iter()
+                .filter_map(|param| match param {
+                    GenericParam::Type(ty) => Some(ty.ident.to_string()),
+                    GenericParam::Const(constant) => Some(constant.ident.to_string()),
+                    GenericParam::Lifetime(_) => None,
+                })
+                .collect::<HashSet<String>>();
+            if is_borrowed_or_generic(function.output.to_token_stream(), &generic_names) {
+                return Err(syn::Error::new_spanned(
+                    &function.output,
+                    "functions returning borrowed or generic types cannot be mocked",
+                ));
+            }
+            let (value, error) = value_and_error(&function.output);
+            let mock_name = format!("{}::{}", mock_ident, name);
+            fields.push(quote! {
+                pub #name: ::cruxmont::dal::mock::MockFunction<#value, #error>
+            });
+            constructors.push(quote! {
+                #name: ::cruxmont::dal::mock::MockFunction::new(#mock_name)
+            });
+
+            let generics = &function.generics;
+            let where_clause = &function.generics.where_clause;
+            let params = function.params.iter().map(|param| {
+                let (ident, ty) = (&param.ident, &param.ty);
+                quote! { #ident: #ty }
+            });
+            let args = function.params.iter().map(|param| &param.ident);
+            let output = transaction_output(&function.output);
+            let outside = format!("{} called outside of {}::run", mock_name, mock_ident);
+            methods.push(quote! {
+                #[allow(clippy::manual_async_fn, clippy::needless_borrow)]
+                fn #name #generics (#(#params),*) -> impl std::future::Future<Output = #output> + Send #where_clause {
+                    // arguments are copied now as they can borrow, the mock is looked up when polled
+                    let call = ::cruxmont::dal::mock::MockCall::new(::std::vec![
+                        #( (&&&::cruxmont::dal::mock::ArgRecorder(&#args)).record() ),*
+                    ]);
+                    async move {
+                        match ::cruxmont::dal::mock::active::<#mock_ident>() {
+                            ::core::option::Option::Some(mock) => mock.#name.call(call),
+                            ::core::option::Option::None => ::core::panic!(#outside),
+                        }
+                    }
+                }
+            });
+        }
+        let trait_ident = &definition.ident;
+        impls.push(quote! {
+            impl #trait_ident for #mock_ident {
+                #(#methods)*
+            }
+        });
+    }
+    Ok(quote! {
+        #(#attrs)*
+        #[derive(Clone)]
+        pub struct #mock_ident {
+            #(#fields,)*
+        }
+
+        #(#attrs)*
+        impl #mock_ident {
+            /// Constructs the mock without programmed results.
+            pub fn new() -> Self {
+                Self {
+                    #(#constructors,)*
+                }
+            }
+
+            /// Runs a future with the mock active, the mocked traits panic outside of it.
+            pub async fn run<F: std::future::Future>(&self, future: F) -> F::Output {
+                ::cruxmont::dal::mock::scope(self.clone(), future).await
+            }
+        }
+
+        #(#attrs)*
+        impl ::core::default::Default for #mock_ident {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        #(#attrs)*
+        const _: () = {
+            #[allow(unused_imports)]
+            use ::cruxmont::dal::mock::{RecordBorrowed as _, RecordOpaque as _, RecordOwned as _};
+
+            #(#impls)*
+        };
+    })
+}
+
+/// Expands the traits and the mock of the definitions.
+pub(crate) fn expand(definitions: Definitions) -> Result<TokenStream> {
+    let traits = definitions.traits.iter().map(expand_trait);
+    let mock = match &definitions.mock {
+        Some(header) => expand_mock(header, &definitions.traits)?,
+        None => TokenStream::new(),
+    };
+    Ok(quote! {
+        #(#traits)*
+        #mock
+    })
+}
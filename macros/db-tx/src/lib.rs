@@ -1,12 +1,230 @@
 extern crate proc_macro;
 
+mod cache;
+mod definitions;
+mod telemetry;
+
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{Ident, ItemFn, Result, Token, parse::Parse, parse::ParseStream, parse_macro_input};
+use syn::{
+    FnArg, GenericArgument, Ident, Item, ItemFn, ItemMod, LitInt, LitStr, Pat, PathArguments,
+    Result, ReturnType, Token, Type, parse::Parse, parse::ParseStream, parse_macro_input,
+};
+
+/// Gets the value and error types of a `Result<T, E>`, None for any other type.
+pub(crate) fn result_parts(ty: &Type) -> Option<(&Type, &Type)> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match (args.args.len(), args.args.first(), args.args.last()) {
+        (2, Some(GenericArgument::Type(value)), Some(GenericArgument::Type(error))) => {
+            Some((value, error))
+        }
+        _ => None,
+    }
+}
+
+/// Gets the output of the future of a transaction. `Result<T, E>` is kept so transactions can
+/// return a domain error, any other type `T` becomes `sqlx::Result<T>`.
+pub(crate) fn transaction_output(ty: &Type) -> proc_macro2::TokenStream {
+    match result_parts(ty) {
+        Some(_) => quote! { #ty },
+        None => quote! { sqlx::Result<#ty> },
+    }
+}
+
+/// Gets the declared return type of a function, `()` if it has none.
+fn declared_output(output: &ReturnType) -> Type {
+    match output {
+        ReturnType::Type(_, ty) => ty.as_ref().clone(),
+        ReturnType::Default => syn::parse_quote! { () },
+    }
+}
+
+/// The keywords of the options of `#[db_transaction]`.
+mod kw {
+    syn::custom_keyword!(atomic);
+    syn::custom_keyword!(isolation);
+    syn::custom_keyword!(read_only);
+    syn::custom_keyword!(deferrable);
+    syn::custom_keyword!(pool);
+    syn::custom_keyword!(retry);
+    syn::custom_keyword!(max_attempts);
+    syn::custom_keyword!(base_delay_ms);
+    syn::custom_keyword!(max_delay_ms);
+    syn::custom_keyword!(sqlstates);
+    syn::custom_keyword!(cache);
+    syn::custom_keyword!(ttl);
+    syn::custom_keyword!(key);
+    syn::custom_keyword!(tags);
+    syn::custom_keyword!(invalidates);
+    syn::custom_keyword!(keys);
+}
+
+/// The isolation levels accepted by `isolation = "..."` and their variants.
+const ISOLATION_LEVELS: [(&str, &str); 4] = [
+    ("read uncommitted", "ReadUncommitted"),
+    ("read committed", "ReadCommitted"),
+    ("repeatable read", "RepeatableRead"),
+    ("serializable", "Serializable"),
+];
+
+/// The options of `retry(max_attempts = 5, base_delay_ms = 10, max_delay_ms = 500, sqlstates = [...])`,
+/// the defaults of `RetryPolicy` are used for the options left out.
+#[derive(Default)]
+struct RetryOptions {
+    max_attempts: Option<LitInt>,
+    base_delay_ms: Option<LitInt>,
+    max_delay_ms: Option<LitInt>,
+    sqlstates: Option<Vec<LitStr>>,
+}
+
+impl Parse for RetryOptions {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut options = RetryOptions::default();
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::max_attempts) {
+                input.parse::<kw::max_attempts>()?;
+                input.parse::<Token![=]>()?;
+                let attempts: LitInt = input.parse()?;
+                if attempts.base10_parse::<u32>()? == 0 {
+                    return Err(syn::Error::new_spanned(
+                        attempts,
+                        "max_attempts must be at least 1",
+                    ));
+                }
+                options.max_attempts = Some(attempts);
+            } else if lookahead.peek(kw::base_delay_ms) {
+                input.parse::<kw::base_delay_ms>()?;
+                input.parse::<Token![=]>()?;
+                options.base_delay_ms = Some(input.parse()?);
+            } else if lookahead.peek(kw::max_delay_ms) {
+                input.parse::<kw::max_delay_ms>()?;
+                input.parse::<Token![=]>()?;
+                options.max_delay_ms = Some(input.parse()?);
+            } else if lookahead.peek(kw::sqlstates) {
+                input.parse::<kw::sqlstates>()?;
+                input.parse::<Token![=]>()?;
+                let content;
+                syn::bracketed!(content in input);
+                let codes = content.parse_terminated(|codes| codes.parse::<LitStr>(), Token![,])?;
+                options.sqlstates = Some(codes.into_iter().collect());
+            } else {
+                return Err(lookahead.error());
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl RetryOptions {
+    /// Builds the `RetryPolicy` of the options.
+    fn policy(&self) -> proc_macro2::TokenStream {
+        let mut fields = Vec::new();
+        if let Some(attempts) = &self.max_attempts {
+            fields.push(quote! { max_attempts: #attempts });
+        }
+        if let Some(delay) = &self.base_delay_ms {
+            fields.push(quote! { base_delay: ::core::time::Duration::from_millis(#delay) });
+        }
+        if let Some(delay) = &self.max_delay_ms {
+            fields.push(quote! { max_delay: ::core::time::Duration::from_millis(#delay) });
+        }
+        if let Some(codes) = &self.sqlstates {
+            fields.push(quote! { sqlstates: &[#(#codes),*] });
+        }
+        quote! {
+            ::cruxmont::dal::retry::RetryPolicy {
+                #(#fields,)*
+                ..::cruxmont::dal::retry::RetryPolicy::DEFAULT
+            }
+        }
+    }
+}
+
+/// The options of a transaction, `atomic, isolation = "...", read_only, deferrable`, `retry`,
+/// `cache` and `invalidates`.
+#[derive(Default)]
+struct AtomicOptions {
+    atomic: bool,
+    isolation: Option<Ident>,
+    read_only: bool,
+    deferrable: bool,
+    pool: Option<Ident>,
+    retry: Option<RetryOptions>,
+    cache: Option<cache::CacheOptions>,
+    invalidates: Option<cache::InvalidateOptions>,
+}
+
+impl AtomicOptions {
+    fn parse_option(&mut self, input: ParseStream) -> Result<()> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::atomic) {
+            input.parse::<kw::atomic>()?;
+            self.atomic = true;
+        } else if lookahead.peek(kw::read_only) {
+            input.parse::<kw::read_only>()?;
+            self.read_only = true;
+        } else if lookahead.peek(kw::deferrable) {
+            input.parse::<kw::deferrable>()?;
+            self.deferrable = true;
+        } else if lookahead.peek(kw::isolation) {
+            input.parse::<kw::isolation>()?;
+            input.parse::<Token![=]>()?;
+            let level: LitStr = input.parse()?;
+            let name = level.value().trim().to_lowercase().replace(['_', '-'], " ");
+            let Some((_, variant)) = ISOLATION_LEVELS.iter().find(|(sql, _)| *sql == name) else {
+                return Err(syn::Error::new_spanned(
+                    level,
+                    "expected one of \"read uncommitted\", \"read committed\", \"repeatable read\" or \"serializable\"",
+                ));
+            };
+            self.isolation = Some(Ident::new(variant, level.span()));
+        } else if lookahead.peek(kw::pool) {
+            input.parse::<kw::pool>()?;
+            input.parse::<Token![=]>()?;
+            self.pool = Some(input.parse()?);
+        } else if lookahead.peek(kw::retry) {
+            input.parse::<kw::retry>()?;
+            self.retry = Some(if input.peek(syn::token::Paren) {
+                let content;
+                syn::parenthesized!(content in input);
+                content.parse()?
+            } else {
+                RetryOptions::default()
+            });
+        } else if lookahead.peek(kw::cache) {
+            input.parse::<kw::cache>()?;
+            let content;
+            syn::parenthesized!(content in input);
+            self.cache = Some(content.parse()?);
+        } else if lookahead.peek(kw::invalidates) {
+            input.parse::<kw::invalidates>()?;
+            let content;
+            syn::parenthesized!(content in input);
+            self.invalidates = Some(content.parse()?);
+        } else {
+            return Err(lookahead.error());
+        }
+        Ok(())
+    }
+}
 
 struct ImplementTraitArgs {
     struct_name: Ident,
     trait_name: Ident,
+    options: AtomicOptions,
 }
 
 impl Parse for ImplementTraitArgs {
@@ -14,47 +232,406 @@ impl Parse for ImplementTraitArgs {
         let struct_name: Ident = input.parse()?;
         input.parse::<Token![,]>()?;
         let trait_name: Ident = input.parse()?;
+        let mut options = AtomicOptions::default();
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            options.parse_option(input)?;
+        }
+        if !input.is_empty() {
+            return Err(input.error("expected `,`"));
+        }
+        let has_modes =
+            options.isolation.is_some() || options.deferrable || options.retry.is_some();
+        if has_modes && !options.atomic {
+            return Err(input.error(
+                "isolation, deferrable and retry only apply to atomic transactions, add `atomic`",
+            ));
+        }
+        let serializable = options
+            .isolation
+            .as_ref()
+            .is_some_and(|level| level == "Serializable");
+        if options.deferrable && !(options.read_only && serializable) {
+            return Err(input.error(
+                "deferrable only applies to transactions with isolation = \"serializable\" and read_only",
+            ));
+        }
         Ok(Self {
             struct_name,
             trait_name,
+            options,
         })
     }
 }
 
-#[proc_macro_attribute]
-pub fn db_transaction(attr: TokenStream, item: TokenStream) -> TokenStream {
-    // Parse the attribute arguments
+/// Checks if a type is a reference to a sqlx pool, `&Pool<Postgres>` or `&PgPool`.
+fn is_pool_reference(ty: &Type) -> bool {
+    let Type::Reference(reference) = ty else {
+        return false;
+    };
+    let Type::Path(type_path) = reference.elem.as_ref() else {
+        return false;
+    };
+    type_path
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Pool" || segment.ident == "PgPool")
+}
+
+/// Finds the pool parameter an atomic transaction begins on, named with `pool = ident` or
+/// found by its type.
+fn pool_parameter(options: &AtomicOptions, function: &ItemFn) -> Result<Ident> {
+    if let Some(pool) = &options.pool {
+        return Ok(pool.clone());
+    }
+    let pools = function
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(typed) if is_pool_reference(&typed.ty) => match typed.pat.as_ref() {
+                Pat::Ident(pat) => Some(pat.ident.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<Ident>>();
+    match pools.as_slice() {
+        [pool] => Ok(pool.clone()),
+        [] => Err(syn::Error::new_spanned(
+            &function.sig,
+            "atomic and read only transactions need a `&Pool<Postgres>` parameter to run on",
+        )),
+        _ => Err(syn::Error::new_spanned(
+            &function.sig,
+            "several pool parameters, choose the one to begin the transaction on with `pool = name`",
+        )),
+    }
+}
+
+/// Builds the body of a transaction. An atomic transaction runs the body with `tx` borrowing
+/// the transaction, commits it on `Ok` and rolls it back on `Err`. A transaction dropped by a
+/// panic is rolled back by sqlx.
+fn transaction_body(
+    options: &AtomicOptions,
+    name: &str,
+    function: &ItemFn,
+    fn_output: &proc_macro2::TokenStream,
+) -> Result<proc_macro2::TokenStream> {
+    let fn_body = &function.block;
+    if !options.atomic {
+        if !options.read_only {
+            return Ok(quote! { async move #fn_body });
+        }
+        // a read only transaction runs its statements on a replica of its pool
+        let pool = pool_parameter(options, function)?;
+        return Ok(quote! {
+            async move {
+                let #pool = ::cruxmont::dal::connections::replicas::route_read_checked(#pool).await;
+                async move #fn_body.await
+            }
+        });
+    }
+    // `tx` is bound to the transaction so it cannot also be a parameter
+    for input in &function.sig.inputs {
+        if let FnArg::Typed(typed) = input
+            && let Pat::Ident(pat) = typed.pat.as_ref()
+            && pat.ident == "tx"
+        {
+            return Err(syn::Error::new_spanned(
+                &pat.ident,
+                "atomic transactions bind the transaction to `tx`, rename this parameter",
+            ));
+        }
+    }
+    let pool = pool_parameter(options, function)?;
+    let begin = if options.read_only {
+        quote! { ::cruxmont::dal::connections::replicas::begin_read }
+    } else {
+        quote! { ::cruxmont::dal::transaction::begin }
+    };
+    let isolation = match &options.isolation {
+        Some(level) => {
+            quote! { ::core::option::Option::Some(::cruxmont::dal::transaction::IsolationLevel::#level) }
+        }
+        None => quote! { ::core::option::Option::None },
+    };
+    let read_only = options.read_only;
+    let deferrable = options.deferrable;
+    let attempt = quote! {
+        async move {
+            let mut __cruxmont_transaction = #begin(
+                #pool,
+                &::cruxmont::dal::transaction::TransactionOptions {
+                    isolation: #isolation,
+                    read_only: #read_only,
+                    deferrable: #deferrable,
+                },
+            )
+            .await?;
+            let __cruxmont_result: #fn_output = {
+                #[allow(unused_variables)]
+                let tx = &mut __cruxmont_transaction;
+                async move #fn_body.await
+            };
+            match __cruxmont_result {
+                ::core::result::Result::Ok(value) => {
+                    __cruxmont_transaction.commit().await?;
+                    ::core::result::Result::Ok(value)
+                }
+                ::core::result::Result::Err(error) => {
+                    // the error of the body is more useful than a failed rollback
+                    let _ = __cruxmont_transaction.rollback().await;
+                    ::core::result::Result::Err(error)
+                }
+            }
+        }
+    };
+    let Some(retry) = &options.retry else {
+        return Ok(attempt);
+    };
+    // every attempt runs on its own clone of the parameters
+    let mut clones = Vec::new();
+    for input in &function.sig.inputs {
+        let FnArg::Typed(typed) = input else {
+            continue;
+        };
+        let Pat::Ident(pat) = typed.pat.as_ref() else {
+            return Err(syn::Error::new_spanned(
+                &typed.pat,
+                "retried transactions need named parameters",
+            ));
+        };
+        let ident = &pat.ident;
+        clones.push(quote! { let #pat = ::core::clone::Clone::clone(&#ident); });
+    }
+    let policy = retry.policy();
+    Ok(quote! {
+        async move {
+            ::cruxmont::dal::retry::run_with_retry(&#policy, #name, move || {
+                #(#clones)*
+                #attempt
+            })
+            .await
+        }
+    })
+}
+
+/// Turns a function into a method of the trait impl, run in a span named after the trait and
+/// function.
+fn transaction_method(
+    options: &AtomicOptions,
+    trait_name: &Ident,
+    function: &ItemFn,
+) -> Result<proc_macro2::TokenStream> {
+    let attrs = &function.attrs;
+    let fn_name = &function.sig.ident;
+
+    // Extract the function signature generics and where clause if there are any
+    let fn_generics = &function.sig.generics;
+    let where_clause = &function.sig.generics.where_clause;
+    let fn_inputs = &function.sig.inputs;
+
+    let fn_output = transaction_output(&declared_output(&function.sig.output));
+    // the retries are reported under the name of the span so both can be matched
+    let span_name = format!("{}::{}", trait_name, fn_name);
+    let mut body = transaction_body(options, &span_name, function, &fn_output)?;
+    if let Some(invalidates) = &options.invalidates {
+        body = cache::invalidate(invalidates, body);
+    }
+    if let Some(cached) = &options.cache {
+        body = cache::cache(cached, &span_name, body);
+    }
+    let body = telemetry::instrument(&span_name, function, &fn_output, body);
+    Ok(quote! {
+        #(#attrs)*
+        #[allow(clippy::manual_async_fn, clippy::needless_update, clippy::needless_borrow)]
+        fn #fn_name #fn_generics (#fn_inputs) -> impl std::future::Future<Output = #fn_output> + Send #where_clause {
+            #body
+        }
+    })
+}
+
+fn expand_db_transaction(
+    args: ImplementTraitArgs,
+    input_fn: ItemFn,
+) -> Result<proc_macro2::TokenStream> {
     let ImplementTraitArgs {
         struct_name,
         trait_name,
-    } = parse_macro_input!(attr as ImplementTraitArgs);
+        options,
+    } = args;
+    let method = transaction_method(&options, &trait_name, &input_fn)?;
+    Ok(quote! {
+        impl #trait_name for #struct_name {
+            #method
+        }
+    })
+}
+
+/// Implements a single function trait defined with `define_dal_transactions!` for a
+/// descriptor. The function declares the type of its value and returns a `sqlx::Result` of it,
+/// or declares `Result<T, E>` to return a domain error.
+///
+/// By default every statement of the body runs on its own. With `atomic` the body runs in a SQL
+/// transaction begun on the `&Pool<Postgres>` parameter, given to the body as
+/// `tx: &mut PgTransaction`, committed on `Ok` and rolled back on `Err` or panic:
+///
+/// ```ignore
+/// #[db_transaction(SqlxPostGresDescriptor, IncreaseCount, atomic, isolation = "serializable")]
+/// async fn increase_count(number: i32, pool: &Pool<Postgres>) -> () {
+///     sqlx::query("UPDATE counts SET value = value + $1 WHERE id = 1")
+///         .bind(number)
+///         .execute(&mut **tx)
+///         .await?;
+///     Ok(())
+/// }
+/// ```
+///
+/// `tx` is bound by the macro rather than declared, so an atomic transaction cannot have a
+/// parameter named `tx`.
+///
+/// Atomic transactions also accept `read_only`, `deferrable` for serializable read only
+/// transactions and `pool = name` when the function has several pools. A domain error `E` must
+/// implement `From<sqlx::Error>` for the errors of `BEGIN` and `COMMIT`.
+///
+/// `read_only` routes the transaction to a read replica of its pool parameter, atomic or not,
+/// falling back to the pool when it has no healthy replica. A replica that cannot be connected
+/// to is ejected and the transaction runs on the pool. See `cruxmont::dal::connections::replicas`.
+///
+/// `cache(ttl = "30s", key = format!("count:{}", id), tags = ["counts"])` answers the calls from
+/// the cache while the value of their key is fresh, and `invalidates(keys = [GetCount::get_count
+/// => format!("count:{}", id)], tags = [...])` removes keys and tagged values once a write
+/// returns `Ok`. The keys are expressions of the parameters namespaced with the name of the
+/// cached transaction, see `cruxmont::dal::cache`.
+///
+/// `retry` re-runs the whole transaction when it fails with a serialization failure or a
+/// deadlock, `retry(max_attempts = 5, base_delay_ms = 10, max_delay_ms = 500, sqlstates =
+/// ["40001", "40P01"])` tunes the `RetryPolicy`. The parameters must implement `Clone` and the
+/// error `RetryableError`, see `cruxmont::dal::retry`.
+#[proc_macro_attribute]
+pub fn db_transaction(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // Parse the attribute arguments
+    let args = parse_macro_input!(attr as ImplementTraitArgs);
 
     // Parse the input function
     let input_fn = parse_macro_input!(item as ItemFn);
 
-    // Extract function components
-    let fn_inputs = &input_fn.sig.inputs;
-    let fn_body = &input_fn.block;
-    let fn_name = &input_fn.sig.ident;
-
-    // Extract the function signature generics is there are any
-    let fn_generics = &input_fn.sig.generics;
+    match expand_db_transaction(args, input_fn) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(error) => TokenStream::from(error.to_compile_error()),
+    }
+}
 
-    let fn_output = match &input_fn.sig.output {
-        syn::ReturnType::Type(_, ty) => ty.as_ref(),
-        syn::ReturnType::Default => {
-            panic!("Function must have a return type.")
-        }
+fn expand_db_transactions(
+    args: ImplementTraitArgs,
+    module: ItemMod,
+) -> Result<proc_macro2::TokenStream> {
+    let ImplementTraitArgs {
+        struct_name,
+        trait_name,
+        options,
+    } = args;
+    let Some((_, items)) = module.content else {
+        return Err(syn::Error::new_spanned(
+            &module.ident,
+            "#[db_transactions] needs an inline module such as `mod user_repo { ... }`",
+        ));
     };
+    let mut methods = Vec::new();
+    let mut other_items = Vec::new();
+    for item in items {
+        match item {
+            Item::Fn(function) if function.sig.asyncness.is_none() => {
+                return Err(syn::Error::new_spanned(
+                    function.sig.fn_token,
+                    "functions of a #[db_transactions] module must be async",
+                ));
+            }
+            Item::Fn(function) => {
+                methods.push(transaction_method(&options, &trait_name, &function)?)
+            }
+            other => other_items.push(other),
+        }
+    }
+    let attrs = &module.attrs;
+    let vis = &module.vis;
+    let mod_name = &module.ident;
+    Ok(quote! {
+        #(#attrs)*
+        #vis mod #mod_name {
+            #[allow(unused_imports)]
+            use super::*;
 
-    // Generate the expanded code
-    let expanded = quote! {
-        impl #trait_name for #struct_name {
-            #[allow(clippy::manual_async_fn)]
-            fn #fn_name #fn_generics (#fn_inputs) -> impl std::future::Future<Output = sqlx::Result<#fn_output>> + Send {
-                async move #fn_body
+            #(#other_items)*
+
+            impl #trait_name for #struct_name {
+                #(#methods)*
             }
         }
-    };
-    TokenStream::from(expanded)
+    })
+}
+
+/// Implements a trait with several transactions, defined with the grouped syntax of
+/// `define_dal_transactions!`, from the async functions of a module:
+///
+/// ```ignore
+/// #[db_transactions(SqlxPostGresDescriptor, UserRepo)]
+/// mod user_repo {
+///     use sqlx::{Pool, Postgres};
+///
+///     async fn create_user(name: String, pool: &Pool<Postgres>) -> i32 {
+///         let row: (i32,) = sqlx::query_as("INSERT INTO users (name) VALUES ($1) RETURNING id")
+///             .bind(name)
+///             .fetch_one(pool)
+///             .await?;
+///         Ok(row.0)
+///     }
+/// }
+/// ```
+///
+/// As with `#[db_transaction]` the functions declare the type of their value and return a
+/// `sqlx::Result` of it. The options of `#[db_transaction]` such as `atomic` apply to every
+/// function. Other items of the module are kept and the items of the parent module are in scope.
+#[proc_macro_attribute]
+pub fn db_transactions(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as ImplementTraitArgs);
+    let module = parse_macro_input!(item as ItemMod);
+    match expand_db_transactions(args, module) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(error) => TokenStream::from(error.to_compile_error()),
+    }
+}
+
+/// Defines the traits of the database transactions, for example:
+///
+/// ```ignore
+/// define_dal_transactions!(
+///     mock MockUserDal;
+///     GetCount => get_count(id: i32) -> i32,
+///     UserRepo {
+///         /// Creates a user, failing with a domain error if the name is taken
+///         create_user(name: &str, pool: &Pool<Postgres>) -> Result<i32, UserError>,
+///         find_users<'a, I>(ids: I, pool: &'a Pool<Postgres>) -> Vec<User>
+///             where I: IntoIterator<Item = i32> + Send + 'a,
+///     },
+/// );
+/// ```
+///
+/// `Trait => function(...) -> T` defines a trait with a single function and
+/// `Trait { function(...) -> T, ... }` a trait with several functions, implemented in one go
+/// with `#[db_transactions]`. Functions accept generics, lifetimes and where clauses. A return
+/// type `T` gives a `sqlx::Result<T>` while `Result<T, E>` is kept as is.
+///
+/// Starting with `mock MockName;` also defines `MockName` implementing every trait, see
+/// `cruxmont::dal::mock`. Attributes before `mock` such as `#[cfg(test)]` are applied to the
+/// mock only. Each function of the mock is a public field of the same name used to program its
+/// results and read its calls. Functions returning borrowed or generic types cannot be mocked.
+#[proc_macro]
+pub fn define_dal_transactions(input: TokenStream) -> TokenStream {
+    let definitions = parse_macro_input!(input as definitions::Definitions);
+    match definitions::expand(definitions) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(error) => TokenStream::from(error.to_compile_error()),
+    }
 }
@@ -0,0 +1,145 @@
+//! Builds the span of a transaction and finds the SQL statements of its body.
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::HashMap;
+use syn::visit::{self, Visit};
+use syn::{Expr, ExprCall, ExprLit, ExprMacro, FnArg, ItemFn, Lit, Local, Pat};
+
+/// The sqlx functions and macros taking the SQL of a statement.
+const QUERY_FUNCTIONS: [&str; 8] = [
+    "query",
+    "query_as",
+    "query_scalar",
+    "query_with",
+    "query_as_with",
+    "query_scalar_with",
+    "raw_sql",
+    "query_file",
+];
+
+/// Collects the SQL passed to sqlx as a literal or as a variable bound to a literal.
+#[derive(Default)]
+struct StatementVisitor {
+    literals: HashMap<String, String>,
+    statements: Vec<String>,
+}
+
+impl StatementVisitor {
+    fn push(&mut self, sql: &str) {
+        let statement = sql.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !statement.is_empty() && !self.statements.contains(&statement) {
+            self.statements.push(statement);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for StatementVisitor {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let (Pat::Ident(pat), Some(init)) = (&local.pat, &local.init)
+            && let Expr::Lit(ExprLit {
+                lit: Lit::Str(sql), ..
+            }) = init.expr.as_ref()
+        {
+            self.literals.insert(pat.ident.to_string(), sql.value());
+        }
+        visit::visit_local(self, local);
+    }
+
+    fn visit_expr_call(&mut self, call: &'ast ExprCall) {
+        let is_query = match call.func.as_ref() {
+            Expr::Path(path) => path.path.segments.last().is_some_and(|segment| {
+                QUERY_FUNCTIONS.contains(&segment.ident.to_string().as_str())
+            }),
+            _ => false,
+        };
+        if is_query {
+            match call.args.first() {
+                Some(Expr::Lit(ExprLit {
+                    lit: Lit::Str(sql), ..
+                })) => self.push(&sql.value()),
+                Some(Expr::Path(path)) => {
+                    if let Some(sql) = path
+                        .path
+                        .get_ident()
+                        .and_then(|ident| self.literals.get(&ident.to_string()))
+                        .cloned()
+                    {
+                        self.push(&sql);
+                    }
+                }
+                _ => {}
+            }
+        }
+        visit::visit_expr_call(self, call);
+    }
+
+    fn visit_expr_macro(&mut self, expr: &'ast ExprMacro) {
+        let is_query =
+            expr.mac.path.segments.last().is_some_and(|segment| {
+                QUERY_FUNCTIONS.contains(&segment.ident.to_string().as_str())
+            });
+        if is_query {
+            // `query!("...")` and `query_as!(Type, "...")` take the SQL as their first literal
+            let sql = expr
+                .mac
+                .tokens
+                .clone()
+                .into_iter()
+                .find_map(|token| syn::parse2::<syn::LitStr>(token.into()).ok());
+            if let Some(sql) = sql {
+                self.push(&sql.value());
+            }
+        }
+        visit::visit_expr_macro(self, expr);
+    }
+}
+
+/// Finds the SQL statements written in the body of a transaction.
+pub(crate) fn statements(function: &ItemFn) -> Vec<String> {
+    let mut visitor = StatementVisitor::default();
+    visitor.visit_block(&function.block);
+    visitor.statements
+}
+
+/// Runs the body of a transaction in a span named after its trait and function, recording the
+/// summary of the arguments, the duration, the outcome and the rows of the call.
+pub(crate) fn instrument(
+    name: &str,
+    function: &ItemFn,
+    fn_output: &TokenStream,
+    body: TokenStream,
+) -> TokenStream {
+    let args = function.sig.inputs.iter().filter_map(|input| match input {
+        FnArg::Typed(typed) => match typed.pat.as_ref() {
+            Pat::Ident(pat) => Some(&pat.ident),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    });
+    let statements = statements(function);
+    quote! {
+        {
+            let __cruxmont_telemetry =
+                ::cruxmont::transaction_telemetry!(#name, [#(#args),*], [#(#statements),*]);
+            let __cruxmont_span = __cruxmont_telemetry.span();
+            ::cruxmont::tracing::Instrument::instrument(
+                async move {
+                    let __cruxmont_result: #fn_output = #body.await;
+                    #[allow(unused_imports)]
+                    use ::cruxmont::telemetry::{
+                        RowsAffected as _, RowsReturned as _, RowsUnknown as _,
+                    };
+                    let __cruxmont_rows = match &__cruxmont_result {
+                        ::core::result::Result::Ok(value) => {
+                            (&&&::cruxmont::telemetry::RowsProbe(value)).rows()
+                        }
+                        ::core::result::Result::Err(_) => ::core::option::Option::None,
+                    };
+                    __cruxmont_telemetry.finish(__cruxmont_result.is_ok(), __cruxmont_rows);
+                    __cruxmont_result
+                },
+                __cruxmont_span,
+            )
+        }
+    }
+}
@@ -30,8 +30,6 @@ pub fn embedded_pg_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 ..Default::default()
             };
             let mut db = postgresql_embedded::PostgreSQL::new(settings);
-            let db_handle = std::sync::Arc::new(std::sync::Mutex::new(db));
-            let db_handle_ref = db_handle.clone();
 
             let rt = Builder::new_current_thread()
                 .enable_all()
@@ -39,12 +37,11 @@ pub fn embedded_pg_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 .expect("create Tokio runtime");
 
             // create the DB in the core DB
-            rt.block_on(async move {
-                let mut db_ref = db_handle_ref.lock().expect("Lock the Mutex");
-                db_ref.setup().await.expect("setup DB");
-                db_ref.start().await.expect("start DB");
-                db_ref.create_database(#db_name).await.expect("start DB");
-                let url: String = db_ref.settings().url(#db_name);
+            rt.block_on(async {
+                db.setup().await.expect("setup DB");
+                db.start().await.expect("start DB");
+                db.create_database(#db_name).await.expect("start DB");
+                let url: String = db.settings().url(#db_name);
 
                 // set the environment variables for the DB
                 unsafe {
@@ -75,10 +72,9 @@ pub fn embedded_pg_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 result // Return the handle's result
             });
 
-            // rt.block_on(async move {
-            //     let mut db_ref = db_handle.lock().expect("Lock the Mutex");
-            //     db_ref.drop_database(#db_name).await.expect("drop DB");
-            //     db_ref.stop().await
+            // rt.block_on(async {
+            //     db.drop_database(#db_name).await.expect("drop DB");
+            //     db.stop().await
             // });
             test_result.unwrap();
         }
@@ -2,7 +2,9 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{Ident, ItemFn, Result, Token, parse::Parse, parse::ParseStream, parse_macro_input};
+use syn::{
+    FnArg, Ident, ItemFn, Pat, Result, Token, parse::Parse, parse::ParseStream, parse_macro_input,
+};
 
 struct ImplementTraitArgs {
     struct_name: Ident,
@@ -47,13 +49,37 @@ pub fn http_transaction(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    // Summarise the named arguments for the span of the call
+    let args = fn_inputs.iter().filter_map(|input| match input {
+        FnArg::Typed(typed) => match typed.pat.as_ref() {
+            Pat::Ident(pat) => Some(&pat.ident),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    });
+    let span_name = format!("{}::{}", trait_name, fn_name);
+
     // Generate the expanded code - returns a reqwest library error type as this macro wraps a function
     // which uses reqwest
     let expanded = quote! {
         impl #trait_name for #struct_name {
-            #[allow(clippy::manual_async_fn)]
+            #[allow(clippy::manual_async_fn, clippy::needless_borrow)]
             fn #fn_name #fn_generics (#fn_inputs) -> impl std::future::Future<Output = Result<#fn_output, reqwest::Error>> + Send {
-                async move #fn_body
+                let __cruxmont_telemetry =
+                    ::cruxmont::transaction_telemetry!(#span_name, [#(#args),*], []);
+                let __cruxmont_span = __cruxmont_telemetry.span();
+                ::cruxmont::tracing::Instrument::instrument(
+                    async move {
+                        let __cruxmont_result: Result<#fn_output, reqwest::Error> =
+                            async move #fn_body.await;
+                        __cruxmont_telemetry.finish(
+                            __cruxmont_result.is_ok(),
+                            ::core::option::Option::None,
+                        );
+                        __cruxmont_result
+                    },
+                    __cruxmont_span,
+                )
             }
         }
     };
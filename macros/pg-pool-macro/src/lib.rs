@@ -2,7 +2,7 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{Ident, LitStr, Token, parse::Parse, parse::ParseStream, parse_macro_input};
+use syn::{Ident, LitStr, Token, Type, parse::Parse, parse::ParseStream, parse_macro_input};
 
 /// The input args into the database.
 struct DbPoolArgs {
@@ -12,6 +12,10 @@ struct DbPoolArgs {
     url_env: LitStr,
     /// The string env variable for the maximum number of connections
     max_conn_env: LitStr,
+    /// The variable holding the password, overriding the password of the URL when set
+    password_env: Option<LitStr>,
+    /// The `GetConfigVariable` type the variables are read from
+    config: Option<Type>,
 }
 
 impl Parse for DbPoolArgs {
@@ -21,38 +25,98 @@ impl Parse for DbPoolArgs {
         let url_env: LitStr = input.parse()?;
         input.parse::<Token![,]>()?;
         let max_conn_env: LitStr = input.parse()?;
+        let mut password_env = None;
+        let mut config = None;
+        while input.parse::<Token![,]>().is_ok() && !input.is_empty() {
+            let option: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            if option == "password" {
+                password_env = Some(input.parse()?);
+            } else if option == "config" {
+                config = Some(input.parse()?);
+            } else {
+                return Err(syn::Error::new(
+                    option.span(),
+                    "unsupported option, expected `password` or `config`",
+                ));
+            }
+        }
         Ok(DbPoolArgs {
             pool_ident,
             url_env,
             max_conn_env,
+            password_env,
+            config,
         })
     }
 }
 
+/// Defines a lazily connected postgres pool.
+///
+/// # Usage
+/// `define_pg_pool!(POOL, "DATABASE_URL", "DB_MAX_CONNECTIONS")` reads both variables from the
+/// environment through `cruxmont::config::EnvConfig`, so `DATABASE_URL_FILE` works as well. A
+/// variable that is set but cannot be read panics with its name on first use of the pool rather
+/// than falling back to a default. Two optional arguments can follow:
+/// * `password = "DB_PASSWORD"` - The variable holding the password, so `DATABASE_URL` does not
+///   have to contain credentials. The password of the URL is used when the variable is not set.
+/// * `config = AppConfig` - The `cruxmont::config::GetConfigVariable` type every variable is read
+///   from, defaults to `cruxmont::config::EnvConfig` which supports the `VAR_FILE` convention.
 #[proc_macro]
 pub fn define_pg_pool(input: TokenStream) -> TokenStream {
     let DbPoolArgs {
         pool_ident,
         url_env,
         max_conn_env,
+        password_env,
+        config,
     } = parse_macro_input!(input as DbPoolArgs);
 
+    let config = match config {
+        Some(config) => quote! { #config },
+        None => quote! { ::cruxmont::config::EnvConfig },
+    };
+    let password = match password_env {
+        Some(password_env) => quote! {
+            if let Some(password) = get(#password_env) {
+                options = options.password(&password);
+            }
+        },
+        None => quote! {},
+    };
     quote! {
         pub static #pool_ident: std::sync::LazyLock<sqlx::postgres::PgPool> = std::sync::LazyLock::new(|| {
-            let connection_string = std::env::var(#url_env).unwrap();
+            // a missing variable falls back to its default, any other failure is a broken setup
+            let get = |variable: &str| {
+                match <#config as ::cruxmont::config::GetConfigVariable>::get_config_variable(
+                    variable.to_string(),
+                ) {
+                    Ok(value) => Some(value),
+                    Err(error) if ::cruxmont::config::is_not_found(&error) => None,
+                    Err(error) => panic!("{} could not be read: {}", variable, error.message),
+                }
+            };
+            let connection_string = get(#url_env)
+                .unwrap_or_else(|| panic!("{} is not set", #url_env));
 
-            let max_connections = match std::env::var(#max_conn_env) {
-                Ok(val) => val,
-                Err(_) => "5".to_string()
-            }.trim().parse::<u32>().map_err(|_e| {
-                format!("Could not parse {} as max connections", #max_conn_env)
-            }).unwrap();
+            // the URL is left out of the message as it can hold credentials
+            #[allow(unused_mut)]
+            let mut options = <sqlx::postgres::PgConnectOptions as ::std::str::FromStr>::from_str(
+                &connection_string,
+            )
+            .unwrap_or_else(|_e| panic!("Could not parse {} as a postgres URL", #url_env));
+            #password
 
-            let pool = sqlx::postgres::PgPoolOptions::new()
-                .max_connections(max_connections);
+            let max_connections = get(#max_conn_env)
+                .unwrap_or_else(|| "5".to_string())
+                .trim()
+                .parse::<u32>()
+                .map_err(|_e| format!("Could not parse {} as max connections", #max_conn_env))
+                .unwrap();
 
-            pool.connect_lazy(&connection_string)
-                .expect("Failed to create pool")
+            sqlx::postgres::PgPoolOptions::new()
+                .max_connections(max_connections)
+                .connect_lazy_with(options)
         });
     }
     .into()
@@ -1,5 +1,5 @@
 //! We can use the macro with the following:
-//! ```
+//! ```ignore
 //! #[db_test]
 //! async fn test_create_username_conflict() {
 //!     // SQLX_POSTGRES_POOL is the test DB pool provided by the macro
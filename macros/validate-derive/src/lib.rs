@@ -0,0 +1,250 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, quote};
+use syn::{
+    Data, DeriveInput, Expr, Fields, LitStr, Member, Path, Result, Type, meta::ParseNestedMeta,
+    parse_macro_input,
+};
+
+/// The validations declared on a single field with `#[validate(...)]`.
+#[derive(Default)]
+struct FieldValidations {
+    length: Option<(Option<Expr>, Option<Expr>)>,
+    range: Option<(Option<Expr>, Option<Expr>)>,
+    regex: Option<LitStr>,
+    email: bool,
+    custom: Vec<Path>,
+    nested: bool,
+}
+
+impl FieldValidations {
+    fn is_empty(&self) -> bool {
+        self.length.is_none()
+            && self.range.is_none()
+            && self.regex.is_none()
+            && !self.email
+            && self.custom.is_empty()
+            && !self.nested
+    }
+}
+
+/// Parses the `min = ..., max = ...` arguments of `length(...)` and `range(...)`.
+fn parse_bounds(meta: &ParseNestedMeta) -> Result<(Option<Expr>, Option<Expr>)> {
+    let mut min = None;
+    let mut max = None;
+    meta.parse_nested_meta(|bound| {
+        if bound.path.is_ident("min") {
+            min = Some(bound.value()?.parse::<Expr>()?);
+        } else if bound.path.is_ident("max") {
+            max = Some(bound.value()?.parse::<Expr>()?);
+        } else {
+            return Err(bound.error("expected `min` or `max`"));
+        }
+        Ok(())
+    })?;
+    if min.is_none() && max.is_none() {
+        return Err(meta.error("expected at least one of `min` or `max`"));
+    }
+    Ok((min, max))
+}
+
+/// Parses all the `#[validate(...)]` attributes of a field.
+fn parse_field_validations(attrs: &[syn::Attribute]) -> Result<FieldValidations> {
+    let mut validations = FieldValidations::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("length") {
+                validations.length = Some(parse_bounds(&meta)?);
+            } else if meta.path.is_ident("range") {
+                validations.range = Some(parse_bounds(&meta)?);
+            } else if meta.path.is_ident("regex") {
+                let pattern: LitStr = meta.value()?.parse()?;
+                // an invalid pattern fails the build rather than the first validation
+                if let Err(error) = regex_syntax::parse(&pattern.value()) {
+                    return Err(syn::Error::new_spanned(
+                        &pattern,
+                        format!("invalid pattern in #[validate(regex)]: {}", error),
+                    ));
+                }
+                validations.regex = Some(pattern);
+            } else if meta.path.is_ident("email") {
+                validations.email = true;
+            } else if meta.path.is_ident("custom") {
+                let function: LitStr = meta.value()?.parse()?;
+                validations.custom.push(function.parse()?);
+            } else if meta.path.is_ident("nested") {
+                validations.nested = true;
+            } else {
+                return Err(meta.error(
+                    "unsupported validation, expected one of `length`, `range`, `regex`, `email`, `custom` or `nested`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(validations)
+}
+
+/// Checks if the type of a field is an `Option`, in which case the validations only apply to
+/// the value when it is present.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+fn optional_tokens(value: &Option<Expr>) -> TokenStream2 {
+    match value {
+        Some(value) => quote! { ::core::option::Option::Some(#value) },
+        None => quote! { ::core::option::Option::None },
+    }
+}
+
+/// Generates the checks of a single field against its `value` binding.
+fn field_checks(field_path: &str, validations: &FieldValidations) -> TokenStream2 {
+    let mut checks = Vec::new();
+    if let Some((min, max)) = &validations.length {
+        let (min, max) = (optional_tokens(min), optional_tokens(max));
+        checks.push(quote! {
+            if let ::core::option::Option::Some(error) = ::cruxmont::validation::validate_length(value, #min, #max) {
+                errors.push_at(#field_path, error);
+            }
+        });
+    }
+    if let Some((min, max)) = &validations.range {
+        let (min, max) = (optional_tokens(min), optional_tokens(max));
+        checks.push(quote! {
+            if let ::core::option::Option::Some(error) = ::cruxmont::validation::validate_range(value, #min, #max) {
+                errors.push_at(#field_path, error);
+            }
+        });
+    }
+    if let Some(pattern) = &validations.regex {
+        checks.push(quote! {
+            {
+                static REGEX: ::std::sync::LazyLock<::cruxmont::validation::Regex> =
+                    ::std::sync::LazyLock::new(|| {
+                        ::cruxmont::validation::Regex::new(#pattern)
+                            .expect("invalid pattern in #[validate(regex)]")
+                    });
+                if let ::core::option::Option::Some(error) = ::cruxmont::validation::validate_regex(
+                    ::core::convert::AsRef::<str>::as_ref(value),
+                    &REGEX,
+                ) {
+                    errors.push_at(#field_path, error);
+                }
+            }
+        });
+    }
+    if validations.email {
+        checks.push(quote! {
+            if let ::core::option::Option::Some(error) = ::cruxmont::validation::validate_email(
+                ::core::convert::AsRef::<str>::as_ref(value),
+            ) {
+                errors.push_at(#field_path, error);
+            }
+        });
+    }
+    for function in &validations.custom {
+        checks.push(quote! {
+            if let ::core::result::Result::Err(error) = #function(value) {
+                errors.push_at(#field_path, error);
+            }
+        });
+    }
+    if validations.nested {
+        checks.push(quote! {
+            if let ::core::result::Result::Err(nested) = ::cruxmont::validation::Validate::validate(value) {
+                errors.merge(#field_path, nested);
+            }
+        });
+    }
+    quote! { #(#checks)* }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[derive(Validate)] is only supported on structs",
+            ));
+        }
+    };
+    let named = matches!(fields, Fields::Named(_));
+
+    let mut field_blocks = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let validations = parse_field_validations(&field.attrs)?;
+        if validations.is_empty() {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(index.into()),
+        };
+        let field_path = if named {
+            member.to_token_stream().to_string()
+        } else {
+            index.to_string()
+        };
+        let checks = field_checks(&field_path, &validations);
+
+        // the checks of an `Option` field, nested validation included, only run on a present value
+        if is_option(&field.ty) {
+            field_blocks.push(quote! {
+                if let ::core::option::Option::Some(value) = &self.#member {
+                    #checks
+                }
+            });
+        } else {
+            field_blocks.push(quote! {
+                {
+                    let value = &self.#member;
+                    #checks
+                }
+            });
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::cruxmont::validation::Validate for #name #ty_generics #where_clause {
+            fn validate(&self) -> ::core::result::Result<(), ::cruxmont::validation::ValidationErrors> {
+                #[allow(unused_mut)]
+                let mut errors = ::cruxmont::validation::ValidationErrors::new();
+                #(#field_blocks)*
+                errors.into_result()
+            }
+        }
+    })
+}
+
+/// Derives `cruxmont::validation::Validate` from `#[validate(...)]` attributes on the fields.
+///
+/// # Supported validations
+/// * `length(min = 1, max = 20)` - The length of a string (in characters) or collection.
+/// * `range(min = 0, max = 150)` - The value is within the bounds.
+/// * `regex = "^[a-z]+$"` - The string matches the pattern, needs the `regex` feature of cruxmont.
+/// * `email` - The string is an email address.
+/// * `custom = "path::to::function"` - A `fn(&T) -> Result<(), FieldError>` for the field.
+/// * `nested` - The field implements `Validate`, its errors are prefixed with the field name.
+///
+/// Validations on an `Option` field only apply when the value is present.
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(error) => TokenStream::from(error.to_compile_error()),
+    }
+}